@@ -1,7 +1,7 @@
 use clap::{Parser, Subcommand};
 
 use anyhow::{bail, Result};
-use inquire::{Select, Text};
+use inquire::{Confirm, Select, Text};
 use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
 use regex::Regex;
@@ -19,8 +19,13 @@ use maxima::{
 
 use maxima::{
     content::{
-        downloader::ZipDownloader,
-        manager::{QueuedGame, QueuedGameBuilder},
+        downloader::{DownloadConcurrency, ZipDownloader},
+        eadesktop::{EaInstallState, InstallRegistry},
+        manager::{DownloadQueue, InstallFailure, InstallSize, QueuedGame, QueuedGameBuilder},
+        peer::{PeerServer, DEFAULT_PEER_PORT},
+        progress::format_bytes,
+        uninstall::UninstallOptions,
+        verify::FileVerifyStatus,
         ContentService,
     },
     core::{
@@ -39,6 +44,7 @@ use maxima::{
         },
         LockedMaxima, Maxima, MaximaEvent, MaximaOptionsBuilder,
     },
+    gameinfo::load_game_info_from_json,
     ooa,
     rtm::client::BasicPresence,
     util::{
@@ -114,6 +120,108 @@ enum Mode {
         #[arg(long)]
         file: String,
     },
+    VerifyGame {
+        slug: String,
+
+        /// Download missing or corrupt files again
+        #[arg(long)]
+        repair: bool,
+    },
+    CheckUpdates,
+    Uninstall {
+        slug: String,
+
+        /// Also delete the game's wine prefix
+        #[arg(long)]
+        remove_prefix: bool,
+
+        /// Also delete the game's cached licenses
+        #[arg(long)]
+        remove_licenses: bool,
+    },
+    /// Install a game from a local archive, `file://` URL or mirror instead of the CDN
+    InstallArchive {
+        slug: String,
+
+        /// Absolute path the game will be installed to
+        path: String,
+
+        /// Archive to install from
+        #[arg(long)]
+        source: String,
+
+        /// Build contained in the archive, defaults to the live build
+        #[arg(long)]
+        build_id: Option<String>,
+
+        /// Where to store the Wine prefix, required on Linux
+        #[arg(long)]
+        wine_prefix: Option<String>,
+
+        /// Language to install, like `de_DE`. Can be given more than once, the first one is
+        /// what the game runs in. Every language is installed when not given.
+        #[arg(long = "language")]
+        languages: Vec<String>,
+    },
+    /// Install other languages of a game, and remove the ones not listed
+    ChangeLanguage {
+        slug: String,
+
+        /// Languages to keep installed, the first one is what the game runs in
+        #[arg(required = true)]
+        languages: Vec<String>,
+    },
+    /// List the add-ons of an installed game, or install one into its directory
+    Dlc {
+        slug: String,
+
+        /// Offer ID of the add-on to install, the add-ons are listed when not given
+        offer_id: Option<String>,
+    },
+    /// Take over an install of EA Desktop, finishing its download if it didn't finish
+    AdoptEaInstall {
+        slug: String,
+
+        /// Where to store the Wine prefix of the game, required on Linux
+        #[arg(long)]
+        wine_prefix: Option<String>,
+    },
+    /// Move an installed game to another directory
+    MoveGame {
+        slug: String,
+
+        /// Absolute path the game will be moved to
+        destination: String,
+    },
+    /// Show or change the download queue
+    Queue {
+        #[command(subcommand)]
+        action: Option<QueueAction>,
+    },
+    /// Serve installed builds to other machines using Maxima with `--peer`
+    Serve {
+        /// Address to listen on, defaults to all interfaces on port 31040
+        #[arg(long)]
+        address: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum QueueAction {
+    /// Show the current download and everything after it
+    List,
+    /// Stop downloading until the queue is resumed
+    Pause,
+    /// Continue downloading and wait for the queue to finish
+    Resume,
+    /// Take a game out of the queue
+    Remove { game: String },
+    /// Move a game to another position, 0 is the next one to be installed
+    Move { game: String, index: usize },
+    /// Queue a game whose install failed again, and wait for the queue to finish
+    Retry { game: String },
+    /// Forget about finished installs
+    ClearCompleted,
 }
 
 #[derive(Parser, Debug)]
@@ -125,6 +233,33 @@ struct Args {
     #[arg(long)]
     #[clap(global = true)]
     login: Option<String>,
+
+    /// Amount of files downloaded at the same time
+    #[arg(long)]
+    #[clap(global = true)]
+    download_concurrency: Option<usize>,
+
+    /// Base URL of a server with the same builds as the CDN, like a LAN cache. Can be given
+    /// more than once, the CDN is used when none of them work.
+    #[arg(long = "mirror")]
+    #[clap(global = true)]
+    mirrors: Vec<String>,
+
+    /// Base URL of another machine running `serve`, like `http://192.168.1.20:31040`. Files
+    /// are downloaded from peers first. Can be given more than once.
+    #[arg(long = "peer")]
+    #[clap(global = true)]
+    peers: Vec<String>,
+
+    /// Register finished installs with EA Desktop, so it finds them too
+    #[arg(long)]
+    #[clap(global = true)]
+    share_with_ea_desktop: bool,
+
+    /// Wine prefix EA Desktop runs in, required on Linux to share installs with it
+    #[arg(long)]
+    #[clap(global = true)]
+    ea_prefix: Option<String>,
 }
 
 #[tokio::main]
@@ -266,6 +401,44 @@ async fn startup() -> Result<()> {
         );
     }
 
+    if let Some(files) = args.download_concurrency {
+        let defaults = DownloadConcurrency::default();
+        maxima_arc
+            .lock()
+            .await
+            .content_manager()
+            .set_concurrency(DownloadConcurrency::new(
+                files,
+                *defaults.ranges(),
+                *defaults.range_size(),
+            ));
+    }
+
+    if !args.mirrors.is_empty() {
+        maxima_arc
+            .lock()
+            .await
+            .content_manager()
+            .set_mirrors(args.mirrors.clone());
+    }
+
+    if !args.peers.is_empty() {
+        maxima_arc
+            .lock()
+            .await
+            .content_manager()
+            .set_peers(args.peers.clone());
+    }
+
+    if args.share_with_ea_desktop {
+        let registry = ea_desktop_registry(args.ea_prefix.as_deref())?;
+        maxima_arc
+            .lock()
+            .await
+            .content_manager()
+            .set_ea_desktop_registry(Some(registry));
+    }
+
     // Take back the focus since the browser and bootstrap will take it
     take_foreground_focus()?;
 
@@ -334,6 +507,46 @@ async fn startup() -> Result<()> {
             build_id,
             file,
         } => download_specific_file(maxima_arc.clone(), &offer_id, &build_id, &file).await,
+        Mode::VerifyGame { slug, repair } => verify_game(maxima_arc.clone(), &slug, repair).await,
+        Mode::CheckUpdates => check_updates(maxima_arc.clone()).await,
+        Mode::Uninstall {
+            slug,
+            remove_prefix,
+            remove_licenses,
+        } => uninstall(maxima_arc.clone(), &slug, remove_prefix, remove_licenses).await,
+        Mode::InstallArchive {
+            slug,
+            path,
+            source,
+            build_id,
+            wine_prefix,
+            languages,
+        } => {
+            install_archive(
+                maxima_arc.clone(),
+                &slug,
+                &path,
+                &source,
+                build_id,
+                wine_prefix,
+                languages,
+            )
+            .await
+        }
+        Mode::ChangeLanguage { slug, languages } => {
+            change_language(maxima_arc.clone(), &slug, languages).await
+        }
+        Mode::Dlc { slug, offer_id } => dlc(maxima_arc.clone(), &slug, offer_id).await,
+        Mode::AdoptEaInstall { slug, wine_prefix } => {
+            adopt_ea_install(maxima_arc.clone(), &slug, args.ea_prefix, wine_prefix).await
+        }
+        Mode::MoveGame { slug, destination } => {
+            move_game(maxima_arc.clone(), &slug, &destination).await
+        }
+        Mode::Queue { action } => {
+            manage_queue(maxima_arc.clone(), action.unwrap_or(QueueAction::List)).await
+        }
+        Mode::Serve { address } => serve_builds(address).await,
     }?;
 
     Ok(())
@@ -458,34 +671,144 @@ async fn interactive_install_game(maxima_arc: LockedMaxima) -> Result<()> {
         return Ok(());
     }
 
+    let languages = Text::new("Which languages should be installed? (like en_US,de_DE)")
+        .with_help_message("The first one is what the game runs in, leave empty for all")
+        .prompt()?
+        .split(',')
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<String>>();
+
     let game = QueuedGameBuilder::default()
         .offer_id(offer_id)
         .build_id(build.build_id().to_owned())
         .path(path.clone())
         .slug(slug) // Needs the slug here for the manifest touchup after installation, which needs to know the wine prefix path
         .wine_prefix(Some(wine_prefix))
+        .languages(languages)
         .build()?;
 
-    let start_time = Instant::now();
+    let size = maxima.content_manager().install_size(&game).await?;
+    log_install_size(&size);
+    size.check()?;
+
+    if !Confirm::new("Start the download?")
+        .with_default(true)
+        .prompt()?
+    {
+        return Ok(());
+    }
+
     maxima.content_manager().install_now(game).await?;
+    drop(maxima);
+
+    wait_for_downloads(maxima_arc).await;
+    Ok(())
+}
+
+async fn install_archive(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    path: &str,
+    source: &str,
+    build_id: Option<String>,
+    wine_prefix: Option<String>,
+    languages: Vec<String>,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = match maxima.mut_library().game_by_base_slug(slug).await? {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned offer found for '{}'", slug),
+    };
+
+    let build_id = match build_id {
+        Some(build_id) => build_id,
+        None => {
+            let builds = maxima
+                .content_manager()
+                .service()
+                .available_builds(offer.offer_id())
+                .await?;
+            match builds.live_build() {
+                Some(build) => build.build_id().to_owned(),
+                None => bail!("Couldn't find a suitable game build"),
+            }
+        }
+    };
+
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        bail!("Path {:?} is not absolute", path);
+    }
+
+    let wine_prefix = wine_prefix.map(PathBuf::from);
+    if cfg!(unix) && wine_prefix.is_none() {
+        bail!("A Wine prefix is required, use --wine-prefix");
+    }
+
+    info!("Installing build {} of {} from {}", build_id, slug, source);
+
+    let game = QueuedGameBuilder::default()
+        .offer_id(offer.offer_id().to_owned())
+        .build_id(build_id)
+        .path(path)
+        .slug(slug.to_owned())
+        .wine_prefix(wine_prefix)
+        .source(Some(source.to_owned()))
+        .languages(languages)
+        .build()?;
+
+    let size = maxima.content_manager().install_size(&game).await?;
+    log_install_size(&size);
+    size.check()?;
 
+    maxima.content_manager().install_now(game).await?;
     drop(maxima);
 
+    wait_for_downloads(maxima_arc).await;
+    Ok(())
+}
+
+fn log_install_size(size: &InstallSize) {
+    info!(
+        "Download size: {}, install size: {} ({} needed, {} free)",
+        format_bytes(*size.download_size()),
+        format_bytes(*size.install_size()),
+        format_bytes(*size.required_space()),
+        size.available_space()
+            .map_or_else(|| "unknown".to_owned(), format_bytes),
+    );
+}
+
+async fn wait_for_downloads(maxima_arc: LockedMaxima) {
+    let start_time = Instant::now();
+
     loop {
         let mut maxima = maxima_arc.lock().await;
 
+        maxima.update().await;
+
         for event in maxima.consume_pending_events() {
             match event {
                 MaximaEvent::ReceivedLSXRequest(_pid, _request) => (),
+                MaximaEvent::DownloadProgress(progress) => info!("{}", progress),
+                MaximaEvent::InstallPlayable(offer_id) => {
+                    info!("{} can be played while the download finishes", offer_id)
+                }
+                MaximaEvent::InstallFailed { offer_id, reason } => {
+                    error!("Failed to install {}, {}", offer_id, reason);
+                    if let InstallFailure::Files(failed_files) = reason {
+                        for file in failed_files {
+                            error!("  {}", file);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
 
-        maxima.update().await;
-
-        if let Some(downloader) = maxima.content_manager().current() {
-            info!("Downloading: {}%/100%", downloader.percentage_done());
-        } else {
+        if maxima.content_manager().current().is_none() {
             break;
         }
 
@@ -501,8 +824,6 @@ async fn interactive_install_game(maxima_arc: LockedMaxima) -> Result<()> {
         elapsed_time.as_secs(),
         elapsed_time.subsec_millis()
     );
-
-    Ok(())
 }
 
 async fn download_specific_file(
@@ -553,6 +874,333 @@ async fn download_specific_file(
     Ok(())
 }
 
+async fn verify_game(maxima_arc: LockedMaxima, slug: &str, repair: bool) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = match maxima.mut_library().game_by_base_slug(slug).await? {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned offer found for '{}'", slug),
+    };
+
+    let install_info = load_game_info_from_json(slug)?;
+
+    let builds = maxima
+        .content_manager()
+        .service()
+        .available_builds(offer.offer_id())
+        .await?;
+    let build = match builds.live_build() {
+        Some(build) => build,
+        None => bail!("Couldn't find a suitable game build"),
+    };
+
+    let game = QueuedGameBuilder::default()
+        .offer_id(offer.offer_id().to_owned())
+        .build_id(build.build_id().to_owned())
+        .path(install_info.path())
+        .slug(slug.to_owned())
+        .wine_prefix(install_info.wine_prefix())
+        .languages(install_info.languages.clone())
+        .build()?;
+
+    let results = maxima.content_manager().verify(&game, repair).await?;
+
+    let mut bad = 0;
+    for result in &results {
+        if result.status.is_ok() && result.status != FileVerifyStatus::Repaired {
+            continue;
+        }
+
+        if !result.status.is_ok() {
+            bad += 1;
+        }
+
+        info!("{}: {:?}", result.name, result.status);
+    }
+
+    info!(
+        "Verified {} files, {} are missing, corrupt or unreadable",
+        results.len(),
+        bad
+    );
+    Ok(())
+}
+
+async fn uninstall(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    remove_prefix: bool,
+    remove_licenses: bool,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = match maxima.mut_library().game_by_base_slug(slug).await? {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned offer found for '{}'", slug),
+    };
+
+    let options = UninstallOptions {
+        remove_wine_prefix: remove_prefix,
+        remove_licenses,
+    };
+
+    maxima.content_manager().uninstall(&offer, options).await?;
+    info!("Uninstalled {}", offer.offer().display_name());
+    Ok(())
+}
+
+/// Finds the offer ID of a queued game by its slug or offer ID
+fn queued_offer_id(queue: &DownloadQueue, game: &str) -> Result<String> {
+    let found = queue
+        .current()
+        .iter()
+        .chain(queue.queued().iter())
+        .find(|x| x.slug() == game || x.offer_id() == game);
+
+    match found {
+        Some(queued) => Ok(queued.offer_id().to_owned()),
+        None => bail!("'{}' is not in the download queue", game),
+    }
+}
+
+async fn serve_builds(address: Option<String>) -> Result<()> {
+    let address = address.unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PEER_PORT));
+    let server = PeerServer::bind(&address).await?;
+    server.run().await?;
+    Ok(())
+}
+
+async fn manage_queue(maxima_arc: LockedMaxima, action: QueueAction) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+    let content_manager = maxima.content_manager();
+
+    match action {
+        QueueAction::List => {
+            let queue = content_manager.queue();
+            match queue.current() {
+                Some(current) if *queue.paused() => info!("Paused: {}", current.slug()),
+                Some(current) => info!("Downloading: {}", current.slug()),
+                None if *queue.paused() => info!("Queue is paused"),
+                None => info!("Nothing is downloading"),
+            }
+
+            for (i, game) in queue.queued().iter().enumerate() {
+                match game.failure() {
+                    Some(failure) => info!(
+                        "{}: {} ({}), failed: {}",
+                        i,
+                        game.slug(),
+                        game.offer_id(),
+                        failure
+                    ),
+                    None => info!("{}: {} ({})", i, game.slug(), game.offer_id()),
+                }
+            }
+
+            if !queue.completed().is_empty() {
+                info!("{} installs completed", queue.completed().len());
+            }
+        }
+        QueueAction::Pause => {
+            content_manager.pause().await?;
+            info!("Paused the download queue");
+        }
+        QueueAction::Resume => {
+            content_manager.resume().await?;
+            drop(maxima);
+            wait_for_downloads(maxima_arc).await;
+        }
+        QueueAction::Remove { game } => {
+            let offer_id = queued_offer_id(content_manager.queue(), &game)?;
+            content_manager.remove(&offer_id).await?;
+            info!("Removed {} from the download queue", game);
+
+            // Removing the current download starts the next one
+            if content_manager.current().is_some() {
+                drop(maxima);
+                wait_for_downloads(maxima_arc).await;
+            }
+        }
+        QueueAction::Move { game, index } => {
+            let offer_id = queued_offer_id(content_manager.queue(), &game)?;
+            content_manager.move_to(&offer_id, index).await?;
+            info!("Moved {} to position {}", game, index);
+        }
+        QueueAction::Retry { game } => {
+            let offer_id = queued_offer_id(content_manager.queue(), &game)?;
+            content_manager.retry(&offer_id).await?;
+            info!("Retrying the install of {}", game);
+            drop(maxima);
+            wait_for_downloads(maxima_arc).await;
+        }
+        QueueAction::ClearCompleted => {
+            content_manager.clear_completed().await?;
+            info!("Cleared completed installs");
+        }
+    }
+
+    Ok(())
+}
+
+async fn change_language(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    languages: Vec<String>,
+) -> Result<()> {
+    maxima_arc
+        .lock()
+        .await
+        .content_manager()
+        .change_language(slug, languages)
+        .await?;
+
+    wait_for_downloads(maxima_arc).await;
+    Ok(())
+}
+
+async fn dlc(maxima_arc: LockedMaxima, slug: &str, offer_id: Option<String>) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = match maxima.mut_library().game_by_base_slug(slug).await? {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned offer found for '{}'", slug),
+    };
+
+    let Some(offer_id) = offer_id else {
+        let installed = load_game_info_from_json(slug)
+            .map(|x| x.dlc)
+            .unwrap_or_default();
+
+        info!("Add-ons of {}:", offer.offer().display_name());
+        for addon in maxima.content_manager().available_dlc(&offer).await? {
+            info!(
+                "{:<width$} - {:<width2$} - Owned: {} - Installed: {}",
+                addon.display_name(),
+                addon.offer_id(),
+                addon.is_owned(),
+                installed.iter().any(|x| &x.offer_id == addon.offer_id()),
+                width = 55,
+                width2 = 25
+            );
+        }
+
+        return Ok(());
+    };
+
+    maxima
+        .content_manager()
+        .install_dlc(&offer, &offer_id)
+        .await?;
+    drop(maxima);
+
+    wait_for_downloads(maxima_arc).await;
+    Ok(())
+}
+
+async fn adopt_ea_install(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    ea_prefix: Option<String>,
+    wine_prefix: Option<String>,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = match maxima.mut_library().game_by_base_slug(slug).await? {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned offer found for '{}'", slug),
+    };
+
+    let registry = ea_desktop_registry(ea_prefix.as_deref())?;
+    maxima
+        .content_manager()
+        .set_ea_desktop_registry(Some(registry));
+
+    let wine_prefix = wine_prefix.map(PathBuf::from);
+    if cfg!(unix) && wine_prefix.is_none() {
+        bail!("A Wine prefix is required, use --wine-prefix");
+    }
+
+    let state = maxima
+        .content_manager()
+        .adopt_ea_desktop_install(&offer, wine_prefix)
+        .await?;
+
+    match state {
+        EaInstallState::NotInstalled => bail!("EA Desktop doesn't have {} installed", slug),
+        EaInstallState::Installed(path) => {
+            info!("Adopted {} installed in {}", slug, path.display());
+            Ok(())
+        }
+        EaInstallState::Partial(path) => {
+            info!("Finished the download of {} in {}", slug, path.display());
+            Ok(())
+        }
+    }
+}
+
+/// The registry EA Desktop keeps its installs in, the one of its Wine prefix when it runs under Wine
+fn ea_desktop_registry(ea_prefix: Option<&str>) -> Result<InstallRegistry> {
+    match ea_prefix {
+        Some(prefix) => Ok(InstallRegistry::wine_prefix(&PathBuf::from(prefix))),
+        #[cfg(windows)]
+        None => Ok(InstallRegistry::Native),
+        #[cfg(not(windows))]
+        None => bail!("The Wine prefix of EA Desktop is required, use --ea-prefix"),
+    }
+}
+
+async fn move_game(maxima_arc: LockedMaxima, slug: &str, destination: &str) -> Result<()> {
+    let mover = {
+        let mut maxima = maxima_arc.lock().await;
+        let mover = maxima
+            .content_manager()
+            .install_mover(slug, PathBuf::from(destination))
+            .await?;
+        Arc::new(mover)
+    };
+
+    info!(
+        "Moving {} ({} bytes) to {}",
+        slug,
+        mover.total_bytes(),
+        destination
+    );
+
+    let task_mover = mover.clone();
+    let mut task = tokio::spawn(async move { task_mover.run().await });
+
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+    let result = loop {
+        tokio::select! {
+            result = &mut task => break result?,
+            _ = tokio::signal::ctrl_c() => mover.cancel(),
+            _ = interval.tick() => info!("Moving: {:.1}%/100%", mover.percentage_done()),
+        }
+    };
+
+    let install_info = result?;
+    info!("Moved {} to {}", slug, install_info.path().display());
+    Ok(())
+}
+
+async fn check_updates(maxima_arc: LockedMaxima) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let updates = maxima.check_for_updates().await?;
+    for update in &updates {
+        info!(
+            "{}: {} -> {}{}",
+            update.slug,
+            update.installed_version,
+            update.available_version,
+            if update.mandatory { " (mandatory)" } else { "" }
+        );
+    }
+
+    info!("{} updates available", updates.len());
+    Ok(())
+}
+
 async fn generate_download_links(maxima_arc: LockedMaxima) -> Result<()> {
     let mut maxima = maxima_arc.lock().await;
 
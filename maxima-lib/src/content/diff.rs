@@ -0,0 +1,58 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::content::zip::{ZipFile, ZipFileEntry};
+
+/// Difference between the central directories of two builds of the same game
+#[derive(Default, Debug)]
+pub struct BuildDiff {
+    /// Entries that only exist in the new build
+    pub added: Vec<ZipFileEntry>,
+    /// Entries that exist in both builds, but with a different size or CRC32
+    pub changed: Vec<ZipFileEntry>,
+    /// Names of entries that only exist in the installed build
+    pub removed: Vec<String>,
+    /// Entries that are identical in both builds
+    pub unchanged: Vec<ZipFileEntry>,
+}
+
+impl BuildDiff {
+    /// Entries that need to be downloaded to go from the installed build to the new one
+    pub fn entries_to_download(&self) -> impl Iterator<Item = &ZipFileEntry> {
+        self.added.iter().chain(self.changed.iter())
+    }
+}
+
+fn is_same(a: &ZipFileEntry, b: &ZipFileEntry) -> bool {
+    a.uncompressed_size() == b.uncompressed_size() && a.crc32() == b.crc32()
+}
+
+pub fn diff_builds(installed: &ZipFile, target: &ZipFile) -> BuildDiff {
+    let installed_entries: HashMap<&str, &ZipFileEntry> = installed
+        .entries()
+        .iter()
+        .map(|x| (x.name().as_str(), x))
+        .collect();
+
+    let mut diff = BuildDiff::default();
+    let mut seen = HashSet::new();
+
+    for entry in target.entries() {
+        seen.insert(entry.name().as_str());
+
+        match installed_entries.get(entry.name().as_str()) {
+            None => diff.added.push(entry.clone()),
+            Some(old) if !is_same(old, entry) => diff.changed.push(entry.clone()),
+            Some(_) => diff.unchanged.push(entry.clone()),
+        }
+    }
+
+    diff.removed = installed
+        .entries()
+        .iter()
+        .map(|x| x.name())
+        .filter(|x| !seen.contains(x.as_str()))
+        .cloned()
+        .collect();
+
+    diff
+}
@@ -1,16 +1,24 @@
 use std::{
     cmp,
+    collections::HashSet,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     pin::Pin,
     prelude,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     task,
+    time::Duration,
 };
 
 use crate::{
     content::{
+        limiter::RateLimiter,
         manager::DownloaderError,
+        peer::PeerClient,
+        source::{read_range_parallel, source_for, ByteSource, SourceError},
         zip::{CompressionType, ZipFile, ZipFileEntry},
         zlib::{restore_zlib_state, write_zlib_state},
     },
@@ -19,25 +27,56 @@ use crate::{
         native::{maxima_dir, NativeError, SafeParent, SafeStr},
     },
 };
-use async_compression::tokio::write::DeflateDecoder;
 use async_trait::async_trait;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bzip2::write::BzDecoder;
+use deflate64::InflaterManaged;
 use derive_getters::Getters;
-use flate2::bufread::DeflateDecoder as BufreadDeflateDecoder;
+use flate2::{bufread::DeflateDecoder as BufreadDeflateDecoder, Decompress, FlushDecompress};
 use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, error, warn};
-use reqwest::Client;
 use strum_macros::Display;
 use thiserror::Error;
 use tokio::{
     fs::{create_dir, create_dir_all, File, OpenOptions},
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, BufReader, BufWriter},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
     runtime::Handle,
 };
 use tokio_util::compat::FuturesAsyncReadCompatExt;
+use xz2::{stream::Stream as XzStream, write::XzDecoder};
+use zstd::stream::write::Decoder as ZstdDecoder;
+
+/// Amount of compressed bytes written between two decoder state checkpoints
+const CHECKPOINT_INTERVAL: usize = 16 * 1024 * 1024;
+
+/// Files downloaded at the same time by default
+const DEFAULT_FILE_CONCURRENCY: usize = 16;
+/// Ranges of a single large file downloaded at the same time by default
+const DEFAULT_RANGE_CONCURRENCY: usize = 4;
+/// Files bigger than this are split into ranges of this size by default
+const DEFAULT_RANGE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Attempts made to download an entry before giving up on it
+const ENTRY_TRIES: u32 = 5;
+/// Wait before the first retry of an entry, doubled after every attempt
+const ENTRY_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Version (2 bytes), size of the properties (2 bytes) and the properties (5 bytes)
+const ZIP_LZMA_HEADER_SIZE: usize = 9;
+const LZMA_PROPERTIES_SIZE: u16 = 5;
+
+/// Most output produced by the Deflate and Deflate64 decoders for a single write
+const DECODER_OUTPUT_SIZE: usize = 64 * 1024;
+
+/// Where an entry is written to until it's complete and verified
+fn part_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
 
-fn zstate_path(id: &str, path: &str) -> Result<PathBuf, DownloaderError> {
-    let mut path = maxima_dir()?.join("temp/downloader").join(id).join(path);
+fn zstate_path(state_dir: &Path, path: &str) -> Result<PathBuf, DownloaderError> {
+    let mut path = state_dir.join(path);
     path.set_extension("eazstate");
     std::fs::create_dir_all(path.safe_parent()?)?;
     Ok(path)
@@ -53,17 +92,22 @@ trait DownloadDecoder: Send {
     fn write_in_pos(&self) -> u64;
     fn write_out_pos(&self) -> u64;
 
+    /// Called after `written` input bytes were accepted by the writer
+    fn advance(&mut self, _written: usize) {}
+
     fn get_mut<'b>(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>>;
 }
 
+/// Deflate decoder whose zlib state is saved at checkpoints, so interrupted files resume
 struct ZLibDeflateDecoder {
-    decoder: Arc<Mutex<DeflateDecoder<BufWriter<File>>>>,
+    decoder: Arc<Mutex<BufferDecoderWriter<InflateBufferDecoder, BufWriter<File>>>>,
 }
 
 impl ZLibDeflateDecoder {
     fn new(writer: BufWriter<File>) -> Self {
+        let decoder = InflateBufferDecoder::new();
         Self {
-            decoder: Arc::new(Mutex::new(DeflateDecoder::new(writer))),
+            decoder: Arc::new(Mutex::new(BufferDecoderWriter::new(decoder, writer))),
         }
     }
 }
@@ -72,13 +116,13 @@ impl ZLibDeflateDecoder {
 impl DownloadDecoder for ZLibDeflateDecoder {
     fn save_state(&mut self, buf: &mut BytesMut) {
         let mut decoder = self.decoder.lock().unwrap();
-        let zstream = decoder.inner_mut().decoder_mut().inner.decompress.get_raw();
+        let zstream = decoder.decoder.decompress.get_raw();
         write_zlib_state(buf, zstream);
     }
 
     fn restore_state(&mut self, buf: &mut Bytes) {
         let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
+        let decompress = &mut decoder.decoder.decompress;
         decompress.reset(false);
         let zstream = decompress.get_raw();
         restore_zlib_state(buf, zstream);
@@ -86,7 +130,7 @@ impl DownloadDecoder for ZLibDeflateDecoder {
 
     fn seek(&mut self, pos: SeekFrom) -> Result<(), DownloaderError> {
         let mut decoder = self.decoder.lock().unwrap();
-        let file = decoder.get_mut();
+        let file = &mut decoder.inner;
 
         let handle = Handle::current();
         let _ = handle.enter();
@@ -97,15 +141,13 @@ impl DownloadDecoder for ZLibDeflateDecoder {
 
     fn write_in_pos(&self) -> u64 {
         let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
-        let zstream = decompress.get_raw();
+        let zstream = decoder.decoder.decompress.get_raw();
         zstream.total_in as u64
     }
 
     fn write_out_pos(&self) -> u64 {
         let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
-        let zstream = decompress.get_raw();
+        let zstream = decoder.decoder.decompress.get_raw();
         zstream.total_out as u64
     }
 
@@ -131,7 +173,6 @@ impl NoopDecoder {
 #[async_trait]
 impl DownloadDecoder for NoopDecoder {
     fn save_state(&mut self, buf: &mut BytesMut) {
-        self.pos = self.writer.lock().unwrap().buffer().len() as u64;
         buf.put_u64(self.pos);
     }
 
@@ -157,16 +198,345 @@ impl DownloadDecoder for NoopDecoder {
         self.pos
     }
 
+    fn advance(&mut self, written: usize) {
+        self.pos += written as u64;
+    }
+
     fn get_mut<'b>(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>> {
         self.writer.clone()
     }
 }
 
+/// Decoder for compression methods whose state can't be saved. Failed attempts are retried
+/// from where they stopped, but after a restart the entry is downloaded from the start.
+struct StreamDecoder {
+    writer: Arc<Mutex<dyn AsyncWriteWrapper>>,
+    pos: u64,
+}
+
+impl StreamDecoder {
+    fn new<W: AsyncWriteWrapper + 'static>(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            pos: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl DownloadDecoder for StreamDecoder {
+    /// Left empty, so an interrupted download never looks resumable
+    fn save_state(&mut self, _buf: &mut BytesMut) {}
+
+    fn restore_state(&mut self, _buf: &mut Bytes) {}
+
+    fn seek(&mut self, _pos: SeekFrom) -> Result<(), DownloaderError> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "decoder state can't be restored",
+        )
+        .into())
+    }
+
+    fn write_in_pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Not tracked, nothing is resumed from it
+    fn write_out_pos(&self) -> u64 {
+        0
+    }
+
+    fn advance(&mut self, written: usize) {
+        self.pos += written as u64;
+    }
+
+    fn get_mut<'b>(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>> {
+        self.writer.clone()
+    }
+}
+
+/// Decoder that writes its output into a buffer, which `BufferDecoderWriter` writes out
+trait BufferDecoder: Send + Unpin {
+    /// Decompresses some of `input` into the buffer, returning how much of it was used
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize>;
+
+    fn output(&mut self) -> &mut Vec<u8>;
+}
+
+impl BufferDecoder for BzDecoder<Vec<u8>> {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        let read = self.write(input)?;
+        self.flush()?;
+        Ok(read)
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+}
+
+impl BufferDecoder for XzDecoder<Vec<u8>> {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        let read = self.write(input)?;
+        self.flush()?;
+        Ok(read)
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+}
+
+impl BufferDecoder for ZstdDecoder<'static, Vec<u8>> {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        let read = self.write(input)?;
+        self.flush()?;
+        Ok(read)
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+}
+
+/// Raw deflate, as ZIP archives store it, without a zlib header
+struct InflateBufferDecoder {
+    decompress: Decompress,
+    output: Vec<u8>,
+}
+
+impl InflateBufferDecoder {
+    fn new() -> Self {
+        Self {
+            decompress: Decompress::new(false),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl BufferDecoder for InflateBufferDecoder {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        // Output only goes into the spare capacity
+        self.output.reserve(DECODER_OUTPUT_SIZE);
+
+        let total_in = self.decompress.total_in();
+        self.decompress
+            .decompress_vec(input, &mut self.output, FlushDecompress::None)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Ok((self.decompress.total_in() - total_in) as usize)
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        &mut self.output
+    }
+}
+
+struct Deflate64BufferDecoder {
+    inflater: Box<InflaterManaged>,
+    output: Vec<u8>,
+}
+
+impl Deflate64BufferDecoder {
+    fn new() -> Self {
+        Self {
+            inflater: Box::new(InflaterManaged::new()),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl BufferDecoder for Deflate64BufferDecoder {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        let start = self.output.len();
+        self.output.resize(start + DECODER_OUTPUT_SIZE, 0);
+
+        let result = self.inflater.inflate(input, &mut self.output[start..]);
+        self.output.truncate(start + result.bytes_written);
+
+        if result.data_error {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid deflate64 data",
+            ));
+        }
+
+        Ok(result.bytes_consumed)
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        &mut self.output
+    }
+}
+
+/// Feeds what's written to a `BufferDecoder`, and writes its output to `inner`
+struct BufferDecoderWriter<D, W> {
+    decoder: D,
+    inner: W,
+}
+
+impl<D: BufferDecoder, W: AsyncWrite + Unpin> BufferDecoderWriter<D, W> {
+    fn new(decoder: D, inner: W) -> Self {
+        Self { decoder, inner }
+    }
+
+    /// Writes out the decoded data that's still buffered
+    fn poll_output(&mut self, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        let output = self.decoder.output();
+        while !output.is_empty() {
+            let written = task::ready!(Pin::new(&mut self.inner).poll_write(cx, output))?;
+            if written == 0 {
+                return task::Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            output.drain(..written);
+        }
+
+        task::Poll::Ready(Ok(()))
+    }
+}
+
+impl<D: BufferDecoder, W: AsyncWrite + Unpin> AsyncWrite for BufferDecoderWriter<D, W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            task::ready!(this.poll_output(cx))?;
+
+            // Decoders may hand out held back output before taking more input
+            let read = this.decoder.decode(buf)?;
+            if read > 0 || buf.is_empty() {
+                return task::Poll::Ready(Ok(read));
+            }
+
+            // Nothing is taken or given out anymore once the stream ended
+            if this.decoder.output().is_empty() {
+                return task::Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "data after the end of the compressed stream",
+                )));
+            }
+        }
+    }
+
+    /// Decoders can hold back output even once all of the input was given to them, so
+    /// they're drained until nothing comes out anymore
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            task::ready!(this.poll_output(cx))?;
+
+            this.decoder.decode(&[])?;
+            if this.decoder.output().is_empty() {
+                break;
+            }
+        }
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        task::ready!(Pin::new(&mut *this).poll_flush(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// ZIP archives store LZMA data behind a header of their own, rather than the one `.lzma`
+/// files have. This swaps it for the latter before the data reaches the decoder.
+struct ZipLzmaWriter<W> {
+    inner: W,
+    uncompressed_size: u64,
+    header: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> ZipLzmaWriter<W> {
+    /// `uncompressed_size` should be `u64::MAX` when the data has an end of stream marker
+    fn new(inner: W, uncompressed_size: u64) -> Self {
+        Self {
+            inner,
+            uncompressed_size,
+            header: Vec::with_capacity(ZIP_LZMA_HEADER_SIZE),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writes out what's left of the rewritten header
+    fn poll_pending(&mut self, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written = task::ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return task::Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.pending.drain(..written);
+        }
+
+        task::Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ZipLzmaWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        task::ready!(this.poll_pending(cx))?;
+
+        if this.header.len() < ZIP_LZMA_HEADER_SIZE {
+            let read = cmp::min(ZIP_LZMA_HEADER_SIZE - this.header.len(), buf.len());
+            this.header.extend_from_slice(&buf[..read]);
+
+            if this.header.len() == ZIP_LZMA_HEADER_SIZE {
+                let properties_size = u16::from_le_bytes([this.header[2], this.header[3]]);
+                if properties_size != LZMA_PROPERTIES_SIZE {
+                    return task::Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected lzma properties size {}", properties_size),
+                    )));
+                }
+
+                this.pending.extend_from_slice(&this.header[4..]);
+                this.pending
+                    .extend_from_slice(&this.uncompressed_size.to_le_bytes());
+            }
+
+            return task::Poll::Ready(Ok(read));
+        }
+
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        task::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        task::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
 trait AsyncWriteWrapper: AsyncWrite + Unpin + Send {}
 impl<T: AsyncWrite + Unpin + Send> AsyncWriteWrapper for T {}
 
 struct AsyncWriterWrapper<'a> {
-    id: String,
     path: String,
     zlib_state_file: std::fs::File,
     decoder: &'a mut Box<dyn DownloadDecoder>,
@@ -175,22 +545,43 @@ struct AsyncWriterWrapper<'a> {
 
 impl<'a> AsyncWriterWrapper<'a> {
     async fn new(
-        id: String,
+        state_dir: &Path,
         path: String,
         decoder: &'a mut Box<dyn DownloadDecoder>,
     ) -> Result<Self, DownloaderError> {
         let inner = decoder.get_mut();
         Ok(AsyncWriterWrapper {
-            id: id.to_owned(),
-            path: path.to_owned(),
             zlib_state_file: std::fs::OpenOptions::new()
                 .write(true)
                 .create(true)
-                .open(zstate_path(&id, &path)?)?,
+                .open(zstate_path(state_dir, &path)?)?,
+            path,
             decoder,
             inner,
         })
     }
+
+    /// Flushes everything decoded so far to disk and saves the decoder state next to it,
+    /// so the download can pick up from here if it gets interrupted
+    async fn checkpoint(&mut self) -> Result<(), DownloaderError> {
+        self.flush().await?;
+
+        let mut bytes = BytesMut::new();
+        self.decoder.save_state(&mut bytes);
+
+        self.zlib_state_file.seek(SeekFrom::Start(0))?;
+        self.zlib_state_file.set_len(0)?;
+        self.zlib_state_file.write_all(&bytes)?;
+        self.zlib_state_file.sync_data()?;
+
+        debug!(
+            "Saved state of {} at {}/{}",
+            self.path,
+            self.decoder.write_in_pos(),
+            self.decoder.write_out_pos()
+        );
+        Ok(())
+    }
 }
 
 impl<'a> AsyncWrite for AsyncWriterWrapper<'a> {
@@ -205,12 +596,9 @@ impl<'a> AsyncWrite for AsyncWriterWrapper<'a> {
             inner.poll_write(cx, buf)
         };
 
-        // State serialization is disabled for now.
-        // let mut bytes = BytesMut::new();
-        // self.decoder.save_state(&mut bytes);
-
-        // self.zlib_state_file.seek(SeekFrom::Start(0))?;
-        // self.zlib_state_file.write(&bytes)?;
+        if let task::Poll::Ready(Ok(written)) = poll_result {
+            self.decoder.advance(written);
+        }
 
         poll_result
     }
@@ -235,15 +623,57 @@ pub enum DownloadError {
     #[error("download failed ({0} bytes")]
     DownloadFailed(usize),
     #[error("failed to download chunk `{entry}`: {error}")]
-    ChunkDownload {
-        entry: String,
-        error: reqwest::Error,
-    },
+    ChunkDownload { entry: String, error: SourceError },
     #[error("failed to copy chunk `{entry}`: {error}")]
     ChunkCopy {
         entry: String,
         error: std::io::Error,
     },
+    #[error("size of `{entry}` doesn't match, expected {expected} bytes but got {actual}")]
+    Size {
+        entry: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("crc32 of `{entry}` doesn't match, expected {expected:08x} but got {actual:08x}")]
+    Crc {
+        entry: String,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+/// How much of a build is downloaded at the same time
+#[derive(Debug, Clone, Copy, Getters)]
+pub struct DownloadConcurrency {
+    /// Files downloaded at the same time
+    files: usize,
+    /// Ranges of a single file downloaded at the same time. They're fed to the decoder in
+    /// order, so a large file doesn't end up as one slow connection.
+    ranges: usize,
+    /// Files with more compressed bytes than this are downloaded in ranges of this size
+    range_size: u64,
+}
+
+impl DownloadConcurrency {
+    /// Zero is treated as one
+    pub fn new(files: usize, ranges: usize, range_size: u64) -> Self {
+        Self {
+            files: files.max(1),
+            ranges: ranges.max(1),
+            range_size: range_size.max(1),
+        }
+    }
+}
+
+impl Default for DownloadConcurrency {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_FILE_CONCURRENCY,
+            DEFAULT_RANGE_CONCURRENCY,
+            DEFAULT_RANGE_SIZE,
+        )
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -255,7 +685,7 @@ enum EntryDownloadState {
 }
 
 struct DownloadContext {
-    id: String,
+    state_dir: PathBuf,
     path: PathBuf,
 }
 
@@ -263,29 +693,32 @@ type BytesDownloadedCallback = Box<dyn Fn(usize) + Send + Sync>;
 
 struct EntryDownloadRequest<'a> {
     context: &'a DownloadContext,
-    url: &'a str,
+    source: Arc<dyn ByteSource>,
     entry: &'a ZipFileEntry,
-    client: Client,
     decoder: Box<dyn DownloadDecoder>,
     callback: Option<BytesDownloadedCallback>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    concurrency: DownloadConcurrency,
 }
 
 impl<'a> EntryDownloadRequest<'a> {
     pub fn new(
         context: &'a DownloadContext,
-        url: &'a str,
+        source: Arc<dyn ByteSource>,
         entry: &'a ZipFileEntry,
-        client: Client,
         decoder: Box<dyn DownloadDecoder>,
         callback: Option<BytesDownloadedCallback>,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        concurrency: DownloadConcurrency,
     ) -> Self {
         Self {
             context,
-            url,
+            source,
             entry,
-            client,
             decoder,
             callback,
+            rate_limiter,
+            concurrency,
         }
     }
 
@@ -294,50 +727,58 @@ impl<'a> EntryDownloadRequest<'a> {
         entry: &ZipFileEntry,
     ) -> Result<EntryDownloadState, DownloaderError> {
         let path = context.path.join(entry.name());
+        let part_path = part_path(&path);
+
+        // Anything still in a part file wasn't verified yet, so it's either resumed or redone
+        if part_path.exists() {
+            let part_size = tokio::fs::metadata(&part_path).await?.len() as i64;
 
-        let file_size = File::open(&path).await?.metadata().await?.len() as i64;
+            // A checkpoint is only left behind while the file is incomplete
+            let state_path = zstate_path(&context.state_dir, entry.name())?;
+            if !state_path.exists() || part_size == 0 {
+                return Ok(EntryDownloadState::Fresh);
+            }
+
+            // The download was interrupted before its first checkpoint. Stored files can pick
+            // up from what's on disk, unless it was preallocated. Compressed ones need the
+            // decoder state to continue.
+            let has_checkpoint = tokio::fs::metadata(&state_path).await?.len() > 0;
+            if !has_checkpoint
+                && (*entry.compression_type() != CompressionType::None
+                    || part_size >= *entry.uncompressed_size())
+            {
+                debug!("No checkpoint for {}, starting over", entry.name());
+                return Ok(EntryDownloadState::Fresh);
+            }
 
+            return Ok(EntryDownloadState::Resumable);
+        }
+
+        if !path.exists() {
+            return Ok(EntryDownloadState::Fresh);
+        }
+
+        let file_size = tokio::fs::metadata(&path).await?.len() as i64;
         if file_size == 0 {
             return Ok(EntryDownloadState::Fresh);
         }
 
+        // Files only get their final name after being verified, so the size is enough here
         let entry_size = *entry.uncompressed_size();
-        let size_match = entry_size == file_size;
-
-        if !size_match {
+        if entry_size != file_size {
             warn!("Size mismatch: {}/{}", entry_size, file_size);
-            if file_size > entry_size {
-                return Ok(EntryDownloadState::Borked);
-            }
-
             return Ok(EntryDownloadState::Borked);
         }
 
-        // We must be calculating the hash incorrectly or something
-        // let hash = match hash_file_crc32(&path) {
-        //     Ok(hash) => hash,
-        //     Err(_) => {
-        //         warn!("Failed to retrieve hash for file {}", entry.name());
-        //         0
-        //     }
-        // };
-
-        // let hash_match = *entry.crc32() != hash;
-        // if !hash_match {
-        //     warn!("Hash mismatch");
-        //     return EntryDownloadState::Borked;
-        // }
-
         Ok(EntryDownloadState::Complete)
     }
 
-    async fn download(&mut self) -> Result<(), DownloadError> {
+    async fn download(&mut self) -> Result<(), DownloaderError> {
         let mut tries = 0;
-        while tries < 5 {
-            // State serialization is disabled for now.
-            //let start = self.decoder.write_in_pos() as i64;
-
-            let start = 0;
+        loop {
+            // The decoder keeps track of how much of the entry it has consumed, be it from
+            // a previous attempt or from a restored checkpoint
+            let start = self.decoder.write_in_pos() as i64;
 
             debug!(
                 "Downloading {} from {} to {} ({})",
@@ -348,30 +789,53 @@ impl<'a> EntryDownloadRequest<'a> {
             );
             let end = *self.entry.compressed_size();
 
-            let result = self.download_range(start, end).await;
-            if result.is_ok() {
-                break;
-            }
+            let err = match self.download_range(start, end).await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
 
             tries += 1;
-        }
+            if tries >= ENTRY_TRIES {
+                error!(
+                    "Giving up on {} after {} tries: {}",
+                    self.entry.name(),
+                    tries,
+                    err
+                );
+                return Err(err);
+            }
 
-        Ok(())
+            let delay = ENTRY_RETRY_DELAY * 2u32.pow(tries - 1);
+            warn!(
+                "Download of {} failed ({}), retrying in {:?}",
+                self.entry.name(),
+                err,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
     }
 
     /// End is not inclusive
     pub async fn download_range(&mut self, start: i64, end: i64) -> Result<(), DownloaderError> {
         let offset = self.entry.data_offset();
-        let range = format!("bytes={}-{}", offset + start as i64, offset + end - 1);
+        let start = (offset + start) as u64;
+        let end = (offset + end) as u64;
 
-        let data = match self
-            .client
-            .get(self.url)
-            .header("range", range)
-            .send()
-            .await
-        {
-            Ok(res) => res,
+        let stream = if self.concurrency.ranges > 1 && end - start > self.concurrency.range_size {
+            Ok(read_range_parallel(
+                self.source.clone(),
+                start,
+                end,
+                self.concurrency.range_size,
+                self.concurrency.ranges,
+            ))
+        } else {
+            self.source.read_range(start, end).await
+        };
+
+        let stream = match stream {
+            Ok(stream) => stream,
             Err(err) => {
                 error!("Failed to download ({}): {}", self.entry.name(), err);
                 return Err(DownloaderError::Download(DownloadError::ChunkDownload {
@@ -381,30 +845,47 @@ impl<'a> EntryDownloadRequest<'a> {
             }
         };
 
-        let stream = data.bytes_stream();
         let counting_stream = ByteCountingStream::new(stream, self.callback.as_ref());
         let stream = counting_stream.into_async_read();
         let mut stream_reader = BufReader::new(stream.compat());
 
-        // State deserialization is disabled for now.
-        // let out_pos = self.decoder.write_out_pos();
-        // self.decoder.seek(SeekFrom::Start(out_pos));
-
         let mut wrapper = AsyncWriterWrapper::new(
-            self.context.id.to_owned(),
+            &self.context.state_dir,
             self.entry.name().to_owned(),
             &mut self.decoder,
         )
         .await?;
 
-        let result = tokio::io::copy(&mut stream_reader, &mut wrapper).await;
-        if let Err(err) = result {
-            return Err(DownloaderError::Download(DownloadError::ChunkCopy {
-                entry: self.entry.name().clone(),
-                error: err,
-            }));
+        let entry = self.entry.name();
+        let copy_error = |error: std::io::Error| {
+            DownloaderError::Download(DownloadError::ChunkCopy {
+                entry: entry.clone(),
+                error,
+            })
+        };
+
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut since_checkpoint = 0;
+        loop {
+            let read = stream_reader.read(&mut buf).await.map_err(copy_error)?;
+            if read == 0 {
+                break;
+            }
+
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire(read).await;
+            }
+
+            wrapper.write_all(&buf[..read]).await.map_err(copy_error)?;
+
+            since_checkpoint += read;
+            if since_checkpoint >= CHECKPOINT_INTERVAL {
+                wrapper.checkpoint().await?;
+                since_checkpoint = 0;
+            }
         }
 
+        wrapper.flush().await.map_err(copy_error)?;
         Ok(())
     }
 }
@@ -412,10 +893,23 @@ impl<'a> EntryDownloadRequest<'a> {
 #[derive(Getters)]
 pub struct ZipDownloader {
     id: String,
-    url: String,
     path: PathBuf,
-    client: Client,
+    source: Arc<dyn ByteSource>,
     manifest: ZipFile,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    preallocate: bool,
+    concurrency: DownloadConcurrency,
+    /// Machines on the network asked for files before the archive is
+    #[getter(skip)]
+    peers: Option<PeerClient>,
+    /// Files whose installed copy belongs to another build, having the right size doesn't
+    /// make them complete
+    #[getter(skip)]
+    outdated: HashSet<String>,
+    /// Where checkpoints of unfinished entries are kept, `None` for the downloader directory
+    /// of the offer
+    #[getter(skip)]
+    state_dir: Option<PathBuf>,
 }
 
 impl ZipDownloader {
@@ -424,6 +918,18 @@ impl ZipDownloader {
         zip_url: &str,
         path: P,
     ) -> Result<Self, DownloaderError>
+    where
+        PathBuf: From<P>,
+    {
+        Self::with_source(id, source_for(zip_url)?, path).await
+    }
+
+    /// Creates a downloader that reads the archive from `source` instead of a URL
+    pub async fn with_source<P: AsRef<Path>>(
+        id: &str,
+        source: Arc<dyn ByteSource>,
+        path: P,
+    ) -> Result<Self, DownloaderError>
     where
         PathBuf: From<P>,
     {
@@ -432,17 +938,60 @@ impl ZipDownloader {
             return Err(DownloaderError::PathNotAbsolute(path));
         }
 
-        let manifest = ZipFile::fetch(zip_url).await?;
+        let manifest = ZipFile::fetch_from(source.as_ref()).await?;
 
         Ok(Self {
             id: id.to_owned(),
-            url: zip_url.to_owned(),
             path,
-            client: Client::builder().build()?,
+            source,
             manifest,
+            rate_limiter: None,
+            preallocate: false,
+            concurrency: DownloadConcurrency::default(),
+            peers: None,
+            outdated: HashSet::new(),
+            state_dir: None,
         })
     }
 
+    /// Throttles every entry downloaded through this downloader with the given limiter
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) {
+        self.rate_limiter = Some(rate_limiter);
+    }
+
+    /// Reserves the full size of every file before writing to it, so running out of space
+    /// is noticed up front and the files end up less fragmented
+    pub fn set_preallocate(&mut self, preallocate: bool) {
+        self.preallocate = preallocate;
+    }
+
+    pub fn set_concurrency(&mut self, concurrency: DownloadConcurrency) {
+        self.concurrency = concurrency;
+    }
+
+    pub fn set_peers(&mut self, peers: PeerClient) {
+        self.peers = Some(peers);
+    }
+
+    /// Keeps the checkpoints somewhere else than the downloader directory of the offer
+    pub fn set_state_dir(&mut self, state_dir: PathBuf) {
+        self.state_dir = Some(state_dir);
+    }
+
+    fn state_dir(&self) -> Result<PathBuf, DownloaderError> {
+        match &self.state_dir {
+            Some(state_dir) => Ok(state_dir.clone()),
+            None => Ok(maxima_dir()?.join("temp/downloader").join(&self.id)),
+        }
+    }
+
+    /// Marks files as left over from another build. They're checked against their CRC32
+    /// instead of their size, and downloaded next to the old copy, which is only replaced
+    /// once the new one is complete.
+    pub fn set_outdated(&mut self, outdated: HashSet<String>) {
+        self.outdated = outdated;
+    }
+
     pub async fn read_zip_entry_bytes(
         &self,
         entry: &ZipFileEntry,
@@ -451,22 +1000,10 @@ impl ZipDownloader {
         let offset = entry.data_offset();
         let compressed_size = *entry.compressed_size();
 
-        let range_header = format!("bytes={}-{}", offset, offset + compressed_size - 1);
-
-        let response = self
-            .client
-            .get(&self.url)
-            .header("Range", range_header)
-            .send()
+        let compressed_data = self
+            .source
+            .read_range_bytes(*offset as u64, (offset + compressed_size) as u64)
             .await?;
-
-        if !response.status().is_success()
-            && response.status() != reqwest::StatusCode::PARTIAL_CONTENT
-        {
-            return Err(DownloaderError::Http(response.status()));
-        }
-
-        let compressed_data = response.bytes().await?;
         let decompressed_data = match entry.compression_type() {
             CompressionType::None => {
                 let entry_size = *entry.uncompressed_size() as u64;
@@ -519,6 +1056,10 @@ impl ZipDownloader {
 
         if *entry.uncompressed_size() == 0 {
             debug!("{} is empty", entry.name());
+            if !file_path.exists() {
+                File::create(&file_path).await?;
+            }
+
             return Ok(0);
         }
 
@@ -527,18 +1068,20 @@ impl ZipDownloader {
         debug!("Compressed Size: {}", entry.compressed_size());
         debug!("Offset: {}", offset);
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&file_path)
-            .await?;
-
         let context = DownloadContext {
-            id: self.id.to_owned(),
+            state_dir: self.state_dir()?,
             path: self.path.clone(),
         };
 
-        let state = EntryDownloadRequest::state(&context, entry).await?;
+        let mut state = EntryDownloadRequest::state(&context, entry).await?;
+        if state == EntryDownloadState::Complete
+            && self.outdated.contains(entry.name())
+            && Self::verify(entry, &file_path).await.is_err()
+        {
+            debug!("{} is from the installed build, replacing it", entry.name());
+            state = EntryDownloadState::Fresh;
+        }
+
         if state == EntryDownloadState::Complete {
             if let Some(callback) = callback {
                 callback(*entry.compressed_size() as usize);
@@ -547,39 +1090,296 @@ impl ZipDownloader {
         }
 
         if state == EntryDownloadState::Borked {
-            warn!("Found borked file {}", entry.name());
-            file.set_len(*entry.uncompressed_size() as u64).await?;
+            warn!("Found borked file {}, downloading it again", entry.name());
         }
 
-        let writer = tokio::io::BufWriter::new(file);
+        let part_path = part_path(&file_path);
+        let state_file = zstate_path(&context.state_dir, entry.name())?;
 
-        let mut decoder: Box<dyn DownloadDecoder> = match entry.compression_type() {
-            CompressionType::None => Box::new(NoopDecoder::new(writer)),
-            CompressionType::Deflate => Box::new(ZLibDeflateDecoder::new(writer)),
-        };
+        // Peers are only asked for whole files, progress made on the CDN isn't thrown away
+        let mut callback = callback;
+        if state != EntryDownloadState::Resumable {
+            let (done, counted) = self
+                .download_from_peers(entry, &part_path, callback.as_ref())
+                .await;
 
-        if state == EntryDownloadState::Resumable {
-            let state_file = zstate_path(&self.id, &entry.name())?;
-            if state_file.exists() {
-                let mut buf = Bytes::from(tokio::fs::read(state_file).await?);
-                decoder.restore_state(&mut buf);
-            } else {
-                tokio::fs::create_dir_all(state_file.safe_parent()?).await?;
+            if done {
+                tokio::fs::rename(&part_path, &file_path).await?;
+                if state_file.exists() {
+                    tokio::fs::remove_file(&state_file).await?;
+                }
+
+                if let Some(callback) = callback {
+                    callback((*entry.compressed_size() as usize).saturating_sub(counted));
+                }
+                return Ok(0);
             }
+
+            callback = callback.map(|callback| skip_counted(callback, counted));
         }
 
+        let decoder = if state == EntryDownloadState::Resumable {
+            let file = OpenOptions::new().write(true).open(&part_path).await?;
+            let file_size = file.metadata().await?.len();
+            let mut decoder = Self::create_decoder(entry, file)?;
+
+            let mut buf = Bytes::from(tokio::fs::read(&state_file).await?);
+            if buf.is_empty() {
+                // Only stored files get here, everything on disk was copied straight from the archive
+                let mut state = BytesMut::new();
+                state.put_u64(file_size);
+                buf = state.freeze();
+            }
+
+            decoder.restore_state(&mut buf);
+
+            let out_pos = decoder.write_out_pos();
+            if out_pos <= file_size {
+                debug!(
+                    "Resuming {} at {}/{}",
+                    entry.name(),
+                    decoder.write_in_pos(),
+                    out_pos
+                );
+                decoder.seek(SeekFrom::Start(out_pos))?;
+
+                if let Some(callback) = &callback {
+                    callback(decoder.write_in_pos() as usize);
+                }
+
+                decoder
+            } else {
+                warn!(
+                    "Checkpoint of {} is ahead of the file on disk, starting over",
+                    entry.name()
+                );
+                Self::create_decoder(entry, self.create_part_file(entry, &part_path).await?)?
+            }
+        } else {
+            // Whatever checkpoint is left belongs to an older attempt
+            if state_file.exists() {
+                tokio::fs::remove_file(&state_file).await?;
+            }
+
+            Self::create_decoder(entry, self.create_part_file(entry, &part_path).await?)?
+        };
+
         let mut request = EntryDownloadRequest::new(
             &context,
-            &self.url,
+            self.source.clone(),
             entry,
-            self.client.clone(),
             decoder,
             callback,
+            self.rate_limiter.clone(),
+            self.concurrency,
         );
 
         request.download().await?;
+        drop(request);
+
+        if let Err(err) = Self::verify(entry, &part_path).await {
+            // Neither the data nor the checkpoint can be trusted anymore
+            let _ = tokio::fs::remove_file(&part_path).await;
+            if state_file.exists() {
+                let _ = tokio::fs::remove_file(&state_file).await;
+            }
+
+            return Err(err);
+        }
+
+        tokio::fs::rename(&part_path, &file_path).await?;
+
+        if state_file.exists() {
+            tokio::fs::remove_file(&state_file).await?;
+        }
+
         Ok(0)
     }
+
+    /// Tries to get `entry` from each peer until one of them has a copy matching the central
+    /// directory. Returns whether the part file now holds the entry, and how many bytes were
+    /// passed to `callback`. Peers send the extracted file, its progress is scaled to the
+    /// compressed size the CDN would have sent, and never counts more than one attempt.
+    async fn download_from_peers(
+        &self,
+        entry: &ZipFileEntry,
+        part_path: &Path,
+        callback: Option<&BytesDownloadedCallback>,
+    ) -> (bool, usize) {
+        let peers = match &self.peers {
+            Some(peers) => peers,
+            None => return (false, 0),
+        };
+
+        let compressed = *entry.compressed_size() as u64;
+        let uncompressed = (*entry.uncompressed_size() as u64).max(1);
+        let mut counted = 0;
+
+        for peer in peers.available_peers().await {
+            let mut received = 0u64;
+            let progress = |bytes: usize| {
+                received += bytes as u64;
+                let scaled = (received.saturating_mul(compressed) / uncompressed).min(compressed);
+                if scaled as usize > counted {
+                    if let Some(callback) = callback {
+                        callback(scaled as usize - counted);
+                    }
+                    counted = scaled as usize;
+                }
+            };
+
+            let result = peers
+                .download(
+                    peer,
+                    entry.name(),
+                    part_path,
+                    self.rate_limiter.as_deref(),
+                    progress,
+                )
+                .await;
+            if let Err(err) = result {
+                debug!("Peer {} doesn't have {}: {}", peer, entry.name(), err);
+                continue;
+            }
+
+            match Self::verify(entry, part_path).await {
+                Ok(()) => {
+                    debug!("Got {} from peer {}", entry.name(), peer);
+                    return (true, counted);
+                }
+                Err(err) => warn!(
+                    "Copy of {} from peer {} is bad: {}",
+                    entry.name(),
+                    peer,
+                    err
+                ),
+            }
+        }
+
+        if part_path.exists() {
+            let _ = tokio::fs::remove_file(part_path).await;
+        }
+
+        (false, counted)
+    }
+
+    /// Creates an empty part file for `entry`, preallocating it if enabled
+    async fn create_part_file(
+        &self,
+        entry: &ZipFileEntry,
+        part_path: &Path,
+    ) -> Result<File, DownloaderError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(part_path)
+            .await?;
+
+        if self.preallocate {
+            file.set_len(*entry.uncompressed_size() as u64).await?;
+        }
+
+        Ok(file)
+    }
+
+    /// Makes sure a finished file matches the size and CRC32 from the central directory
+    async fn verify(entry: &ZipFileEntry, path: &Path) -> Result<(), DownloaderError> {
+        let expected = *entry.uncompressed_size() as u64;
+        let actual = tokio::fs::metadata(path).await?.len();
+        if actual != expected {
+            return Err(DownloaderError::Download(DownloadError::Size {
+                entry: entry.name().clone(),
+                expected,
+                actual,
+            }));
+        }
+
+        let path = path.to_owned();
+        let crc32 = tokio::task::spawn_blocking(move || hash_file_crc32(path))
+            .await
+            .map_err(io::Error::other)??;
+        if crc32 != *entry.crc32() {
+            return Err(DownloaderError::Download(DownloadError::Crc {
+                entry: entry.name().clone(),
+                expected: *entry.crc32(),
+                actual: crc32,
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn create_decoder(
+        entry: &ZipFileEntry,
+        file: File,
+    ) -> Result<Box<dyn DownloadDecoder>, DownloaderError> {
+        let writer = tokio::io::BufWriter::new(file);
+
+        Ok(match entry.compression_type() {
+            CompressionType::None => Box::new(NoopDecoder::new(writer)),
+            CompressionType::Deflate => Box::new(ZLibDeflateDecoder::new(writer)),
+            CompressionType::Deflate64 => {
+                let decoder = Deflate64BufferDecoder::new();
+                Box::new(StreamDecoder::new(BufferDecoderWriter::new(
+                    decoder, writer,
+                )))
+            }
+            CompressionType::BZip2 => {
+                let decoder = BzDecoder::new(Vec::new());
+                Box::new(StreamDecoder::new(BufferDecoderWriter::new(
+                    decoder, writer,
+                )))
+            }
+            CompressionType::Lzma => {
+                let uncompressed_size = if *entry.lzma_end_marker() {
+                    u64::MAX
+                } else {
+                    *entry.uncompressed_size() as u64
+                };
+
+                let stream = XzStream::new_lzma_decoder(u64::MAX).map_err(io::Error::from)?;
+                let decoder = XzDecoder::new_stream(Vec::new(), stream);
+                Box::new(StreamDecoder::new(ZipLzmaWriter::new(
+                    BufferDecoderWriter::new(decoder, writer),
+                    uncompressed_size,
+                )))
+            }
+            CompressionType::Zstd => {
+                let decoder = ZstdDecoder::new(Vec::new())?;
+                Box::new(StreamDecoder::new(BufferDecoderWriter::new(
+                    decoder, writer,
+                )))
+            }
+            CompressionType::Unknown(_) => {
+                return Err(DownloaderError::CompressionType(
+                    entry.compression_type().to_owned(),
+                ))
+            }
+        })
+    }
+}
+
+/// Leaves out the first `counted` bytes, which were already reported while a peer was
+/// sending the file
+fn skip_counted(callback: BytesDownloadedCallback, counted: usize) -> BytesDownloadedCallback {
+    if counted == 0 {
+        return callback;
+    }
+
+    let skip = AtomicUsize::new(counted);
+    Box::new(move |bytes| {
+        let skipped = skip
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
+                Some(x.saturating_sub(bytes))
+            })
+            .unwrap_or_default()
+            .min(bytes);
+
+        if bytes > skipped {
+            callback(bytes - skipped);
+        }
+    })
 }
 
 struct ByteCountingStream<'a, S> {
@@ -590,7 +1390,7 @@ struct ByteCountingStream<'a, S> {
 
 impl<'a, S> ByteCountingStream<'a, S>
 where
-    S: Stream<Item = Result<bytes::Bytes, reqwest::Error>>,
+    S: Stream<Item = Result<bytes::Bytes, io::Error>>,
 {
     fn new(inner: S, callback: Option<&'a BytesDownloadedCallback>) -> Self {
         ByteCountingStream {
@@ -607,7 +1407,7 @@ where
 
 impl<'a, S> Stream for ByteCountingStream<'a, S>
 where
-    S: Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Unpin,
+    S: Stream<Item = Result<bytes::Bytes, io::Error>> + Unpin,
 {
     type Item = Result<bytes::Bytes, tokio::io::Error>;
 
@@ -636,3 +1436,403 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use crate::content::{diff::diff_builds, source::FileSource, zip::ZipError};
+
+    use super::*;
+
+    /// Builds an archive with a local extra field on every entry, so the data doesn't start
+    /// where the central directory suggests it does. Deflated entries use data descriptors.
+    fn build_archive(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut central_directory = Vec::new();
+
+        for (name, data, deflate) in files {
+            let crc = crc32fast::hash(data);
+            let (method, compressed) = if *deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                (8u16, encoder.finish().unwrap())
+            } else {
+                (0u16, data.to_vec())
+            };
+
+            let local_header_offset = archive.len() as u32;
+            let flags: u16 = if *deflate { 1 << 3 } else { 0 };
+            let extra_field = [0xFEu8, 0xCA, 4, 0, 0, 0, 0, 0]; // Padding
+
+            // With a data descriptor, the sizes and CRC32 come after the data
+            let (local_crc, local_compressed, local_uncompressed) = if *deflate {
+                (0, 0, 0)
+            } else {
+                (crc, compressed.len() as u32, data.len() as u32)
+            };
+
+            archive.extend_from_slice(&0x04034b50u32.to_le_bytes());
+            archive.extend_from_slice(&20u16.to_le_bytes()); // Version needed
+            archive.extend_from_slice(&flags.to_le_bytes());
+            archive.extend_from_slice(&method.to_le_bytes());
+            archive.extend_from_slice(&0u32.to_le_bytes()); // Modified time and date
+            archive.extend_from_slice(&local_crc.to_le_bytes());
+            archive.extend_from_slice(&local_compressed.to_le_bytes());
+            archive.extend_from_slice(&local_uncompressed.to_le_bytes());
+            archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            archive.extend_from_slice(&(extra_field.len() as u16).to_le_bytes());
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(&extra_field);
+            archive.extend_from_slice(&compressed);
+
+            if *deflate {
+                archive.extend_from_slice(&0x08074b50u32.to_le_bytes());
+                archive.extend_from_slice(&crc.to_le_bytes());
+                archive.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                archive.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            }
+
+            central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            central_directory.extend_from_slice(&20u16.to_le_bytes()); // Version
+            central_directory.extend_from_slice(&20u16.to_le_bytes()); // Version needed
+            central_directory.extend_from_slice(&flags.to_le_bytes());
+            central_directory.extend_from_slice(&method.to_le_bytes());
+            central_directory.extend_from_slice(&0u32.to_le_bytes()); // Modified time and date
+            central_directory.extend_from_slice(&crc.to_le_bytes());
+            central_directory.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central_directory.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+            central_directory.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+            central_directory.extend_from_slice(&0u16.to_le_bytes()); // Disk number
+            central_directory.extend_from_slice(&0u16.to_le_bytes()); // Internal attr.
+            central_directory.extend_from_slice(&0u32.to_le_bytes()); // External attr.
+            central_directory.extend_from_slice(&local_header_offset.to_le_bytes());
+            central_directory.extend_from_slice(name.as_bytes());
+        }
+
+        let cd_offset = archive.len() as u32;
+        archive.extend_from_slice(&central_directory);
+
+        archive.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes()); // Disk number
+        archive.extend_from_slice(&0u16.to_le_bytes()); // Disk with central directory
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        archive.extend_from_slice(&cd_offset.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+
+        archive
+    }
+
+    #[tokio::test]
+    async fn download_from_local_archive() -> Result<(), DownloaderError> {
+        let root = std::env::temp_dir().join(format!("maxima-downloader-{}", std::process::id()));
+        let install_dir = root.join("install");
+        std::fs::create_dir_all(&install_dir)?;
+
+        let stored: Vec<u8> = (0..16 * 1024).map(|x| (x % 251) as u8).collect();
+        let deflated = b"Maxima ".repeat(4096);
+
+        let archive_path = root.join("build.zip");
+        std::fs::write(
+            &archive_path,
+            build_archive(&[
+                ("Data/stored.bin", stored.as_slice(), false),
+                ("Data/deflated.txt", deflated.as_slice(), true),
+            ]),
+        )?;
+
+        let source = Arc::new(FileSource::new(archive_path));
+        let mut downloader =
+            ZipDownloader::with_source("maxima-test-offer", source, &install_dir).await?;
+        downloader.set_state_dir(root.join("state"));
+        downloader.set_preallocate(true);
+        // Small enough for the stored file to be read in parallel ranges
+        downloader.set_concurrency(DownloadConcurrency::new(2, 4, 4096));
+        assert_eq!(downloader.manifest().entries().len(), 2);
+
+        for entry in downloader.manifest().entries() {
+            downloader.download_single_file(entry, None).await?;
+        }
+
+        assert_eq!(std::fs::read(install_dir.join("Data/stored.bin"))?, stored);
+        assert_eq!(
+            std::fs::read(install_dir.join("Data/deflated.txt"))?,
+            deflated
+        );
+        assert!(!install_dir.join("Data/stored.bin.part").exists());
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_nested_legacy_package() -> Result<(), DownloaderError> {
+        let root = std::env::temp_dir().join(format!("maxima-legacy-{}", std::process::id()));
+        let install_dir = root.join("install");
+        std::fs::create_dir_all(&install_dir)?;
+
+        let executable: Vec<u8> = (0..16 * 1024).map(|x| (x % 241) as u8).collect();
+        let manifest = br#"<game gameVersion="1.0.0.0" manifestVersion="1.0"></game>"#;
+
+        let archive_path = root.join("build.zip");
+        std::fs::write(
+            &archive_path,
+            build_archive(&[
+                ("Dead Space 2/", b"".as_slice(), false),
+                (
+                    "Dead Space 2/__Installer/installerdata.xml",
+                    manifest.as_slice(),
+                    true,
+                ),
+                ("Dead Space 2\\deadspace2.exe", executable.as_slice(), false),
+            ]),
+        )?;
+
+        let source = Arc::new(FileSource::new(archive_path));
+        let mut downloader =
+            ZipDownloader::with_source("maxima-test-legacy", source, &install_dir).await?;
+        downloader.set_state_dir(root.join("state"));
+
+        let mut names: Vec<&str> = downloader
+            .manifest()
+            .entries()
+            .iter()
+            .map(|x| x.name().as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["__Installer/installerdata.xml", "deadspace2.exe"]);
+
+        for entry in downloader.manifest().entries() {
+            downloader.download_single_file(entry, None).await?;
+        }
+
+        assert_eq!(
+            std::fs::read(install_dir.join("deadspace2.exe"))?,
+            executable
+        );
+        assert!(install_dir.join("__Installer/installerdata.xml").is_file());
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replace_changed_files() -> Result<(), DownloaderError> {
+        let root = std::env::temp_dir().join(format!("maxima-update-{}", std::process::id()));
+        let install_dir = root.join("install");
+        std::fs::create_dir_all(&install_dir)?;
+
+        let same = vec![1u8; 16 * 1024];
+        let old = vec![2u8; 4096];
+        let new = vec![3u8; 4096];
+
+        let installed_path = root.join("installed.zip");
+        std::fs::write(
+            &installed_path,
+            build_archive(&[
+                ("same.bin", same.as_slice(), false),
+                ("changed.bin", old.as_slice(), false),
+                ("removed.bin", old.as_slice(), true),
+            ]),
+        )?;
+        let target_path = root.join("target.zip");
+        std::fs::write(
+            &target_path,
+            build_archive(&[
+                ("same.bin", same.as_slice(), false),
+                ("changed.bin", new.as_slice(), true),
+                ("added.bin", new.as_slice(), false),
+            ]),
+        )?;
+
+        let mut installed = ZipDownloader::with_source(
+            "maxima-test-update",
+            Arc::new(FileSource::new(installed_path)),
+            &install_dir,
+        )
+        .await?;
+        installed.set_state_dir(root.join("state"));
+        for entry in installed.manifest().entries() {
+            installed.download_single_file(entry, None).await?;
+        }
+
+        let mut target = ZipDownloader::with_source(
+            "maxima-test-update",
+            Arc::new(FileSource::new(target_path)),
+            &install_dir,
+        )
+        .await?;
+        target.set_state_dir(root.join("state"));
+
+        let diff = diff_builds(installed.manifest(), target.manifest());
+        let names = |entries: &[ZipFileEntry]| -> Vec<String> {
+            entries.iter().map(|x| x.name().to_owned()).collect()
+        };
+        assert_eq!(names(&diff.added), ["added.bin"]);
+        assert_eq!(names(&diff.changed), ["changed.bin"]);
+        assert_eq!(names(&diff.unchanged), ["same.bin"]);
+        assert_eq!(diff.removed, ["removed.bin"]);
+
+        // The old file has the same size, only its CRC32 tells it apart
+        target.set_outdated(names(&diff.changed).into_iter().collect());
+        for entry in diff.entries_to_download() {
+            target.download_single_file(entry, None).await?;
+        }
+
+        assert_eq!(std::fs::read(install_dir.join("changed.bin"))?, new);
+        assert_eq!(std::fs::read(install_dir.join("added.bin"))?, new);
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_escaping_names() -> Result<(), DownloaderError> {
+        let root = std::env::temp_dir().join(format!("maxima-escape-{}", std::process::id()));
+        std::fs::create_dir_all(&root)?;
+
+        // Archives have to be big enough for the end of central directory search
+        let executable = vec![0u8; 16 * 1024];
+        for name in ["..\\..\\.bashrc", "/etc/profile", "C:\\Windows\\win.ini"] {
+            let archive_path = root.join("build.zip");
+            std::fs::write(
+                &archive_path,
+                build_archive(&[
+                    ("game.exe", executable.as_slice(), false),
+                    (name, b"".as_slice(), false),
+                ]),
+            )?;
+
+            let source = Arc::new(FileSource::new(archive_path));
+            let result =
+                ZipDownloader::with_source("maxima-test-escape", source, &root.join("install"))
+                    .await;
+            assert!(
+                matches!(
+                    result,
+                    Err(DownloaderError::Zip(ZipError::UnsafeEntryName(_)))
+                ),
+                "{} was accepted",
+                name
+            );
+        }
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    /// Writes `compressed` to `writer` in small pieces, so the decoders get partial input
+    async fn decode_in_chunks<W: AsyncWrite + Unpin>(mut writer: W, compressed: &[u8]) -> W {
+        for chunk in compressed.chunks(1000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+
+        writer.flush().await.unwrap();
+        writer
+    }
+
+    #[tokio::test]
+    async fn decode_other_methods() {
+        let data = b"Maxima ".repeat(64 * 1024);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let writer = BufferDecoderWriter::new(InflateBufferDecoder::new(), Vec::new());
+        let writer = decode_in_chunks(writer, &compressed).await;
+        assert_eq!(writer.inner, data);
+
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let writer = BufferDecoderWriter::new(BzDecoder::new(Vec::new()), Vec::new());
+        let writer = decode_in_chunks(writer, &compressed).await;
+        assert_eq!(writer.inner, data);
+
+        let compressed = zstd::encode_all(data.as_slice(), 0).unwrap();
+        let writer = BufferDecoderWriter::new(ZstdDecoder::new(Vec::new()).unwrap(), Vec::new());
+        let writer = decode_in_chunks(writer, &compressed).await;
+        assert_eq!(writer.inner, data);
+
+        // `.lzma` files have the properties followed by the size, ZIP archives put a version
+        // and the size of the properties in front instead
+        let options = xz2::stream::LzmaOptions::new_preset(6).unwrap();
+        let mut encoder = xz2::write::XzEncoder::new_stream(
+            Vec::new(),
+            XzStream::new_lzma_encoder(&options).unwrap(),
+        );
+        encoder.write_all(&data).unwrap();
+        let lzma = encoder.finish().unwrap();
+        let mut compressed = vec![9, 20, 5, 0];
+        compressed.extend_from_slice(&lzma[..5]);
+        compressed.extend_from_slice(&lzma[13..]);
+        let stream = XzStream::new_lzma_decoder(u64::MAX).unwrap();
+        let writer = ZipLzmaWriter::new(
+            BufferDecoderWriter::new(XzDecoder::new_stream(Vec::new(), stream), Vec::new()),
+            data.len() as u64,
+        );
+        let writer = decode_in_chunks(writer, &compressed).await;
+        assert_eq!(writer.inner.inner, data);
+
+        // Stored blocks are the same in Deflate64
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let writer = BufferDecoderWriter::new(Deflate64BufferDecoder::new(), Vec::new());
+        let writer = decode_in_chunks(writer, &compressed).await;
+        assert_eq!(writer.inner, data);
+    }
+
+    #[tokio::test]
+    async fn reject_trailing_data() {
+        let data = b"Maxima ".repeat(1024);
+
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed.extend_from_slice(b"trailing");
+
+        let mut writer = BufferDecoderWriter::new(BzDecoder::new(Vec::new()), Vec::new());
+        let err = writer.write_all(&compressed).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&data).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed.extend_from_slice(b"trailing");
+
+        let mut writer = BufferDecoderWriter::new(Deflate64BufferDecoder::new(), Vec::new());
+        let err = writer.write_all(&compressed).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut writer = BufferDecoderWriter::new(InflateBufferDecoder::new(), Vec::new());
+        let err = writer.write_all(&compressed).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn skip_peer_progress() {
+        let total = Arc::new(AtomicUsize::new(0));
+        let callback_total = total.clone();
+        let callback = skip_counted(
+            Box::new(move |bytes| {
+                callback_total.fetch_add(bytes, Ordering::SeqCst);
+            }),
+            1500,
+        );
+
+        // The CDN starts over from the first byte, what the peer sent is only counted once
+        callback(1000);
+        assert_eq!(total.load(Ordering::SeqCst), 0);
+        callback(1000);
+        assert_eq!(total.load(Ordering::SeqCst), 500);
+        callback(1000);
+        assert_eq!(total.load(Ordering::SeqCst), 1500);
+    }
+}
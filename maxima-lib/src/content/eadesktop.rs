@@ -0,0 +1,644 @@
+//! EA Desktop finds the install of an offer through its `installCheckOverride`, a registry
+//! value holding the install directory followed by a file that has to be in it, like
+//! `[HKEY_LOCAL_MACHINE\SOFTWARE\EA Games\Dead Space 2\Install Dir]__Installer\installerdata.xml`.
+//! Next to it, `__Installer` holds the state of the install: which offer and build the files
+//! belong to, and whether the download finished. Writing both makes installs done by Maxima
+//! show up in EA Desktop, and reading them finds installs EA Desktop started.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use derive_getters::Getters;
+use log::debug;
+use thiserror::Error;
+
+use crate::gameinfo::GameInstallInfo;
+
+/// Install state, relative to the install directory
+pub const INSTALLER_STATE_PATH: &str = "__Installer/installstate.ini";
+
+#[derive(Error, Debug)]
+pub enum EaDesktopError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("`{0}` is not a registry install check")]
+    InvalidInstallCheck(String),
+}
+
+/// Parsed `installCheckOverride` of an offer
+#[derive(Debug, Clone, PartialEq, Getters)]
+pub struct InstallCheck {
+    /// Key relative to `HKEY_LOCAL_MACHINE`
+    key: String,
+    /// Value of the key holding the install directory
+    value: String,
+    /// File relative to the install directory, with forward slashes
+    file: String,
+}
+
+impl InstallCheck {
+    pub fn parse(check: &str) -> Result<Self, EaDesktopError> {
+        let invalid = || EaDesktopError::InvalidInstallCheck(check.to_owned());
+
+        let check = check.trim().strip_prefix('[').ok_or_else(invalid)?;
+        let (path, file) = check.split_once(']').ok_or_else(invalid)?;
+        let (hive, path) = path.split_once('\\').ok_or_else(invalid)?;
+        if !hive.eq_ignore_ascii_case("HKEY_LOCAL_MACHINE") && !hive.eq_ignore_ascii_case("HKLM") {
+            return Err(invalid());
+        }
+
+        let (key, value) = path.rsplit_once('\\').ok_or_else(invalid)?;
+        let file = file.replace('\\', "/");
+
+        Ok(Self {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            file: file.trim_start_matches('/').to_owned(),
+        })
+    }
+}
+
+/// State EA Desktop keeps in `__Installer`, as `key=value` lines
+#[derive(Debug, Clone, PartialEq, Getters)]
+pub struct InstallerState {
+    offer_id: String,
+    /// Missing for installs that predate the build being recorded
+    build_id: Option<String>,
+    /// False while the download is still running or was stopped
+    complete: bool,
+}
+
+impl InstallerState {
+    pub fn new(offer_id: String, build_id: Option<String>, complete: bool) -> Self {
+        Self {
+            offer_id,
+            build_id,
+            complete,
+        }
+    }
+
+    /// State of the install in `path`, `None` if there isn't one
+    pub fn read(path: &Path) -> Result<Option<Self>, EaDesktopError> {
+        let content = match fs::read_to_string(path.join(INSTALLER_STATE_PATH)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut offer_id = None;
+        let mut build_id = None;
+        let mut complete = false;
+        for (key, value) in content.lines().filter_map(|x| x.split_once('=')) {
+            let value = value.trim();
+            match key.trim() {
+                "offerId" => offer_id = Some(value.to_owned()),
+                "buildId" if !value.is_empty() => build_id = Some(value.to_owned()),
+                "state" => complete = value.eq_ignore_ascii_case("Installed"),
+                _ => {}
+            }
+        }
+
+        let Some(offer_id) = offer_id else {
+            debug!("{} has no offer, ignoring it", INSTALLER_STATE_PATH);
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            offer_id,
+            build_id,
+            complete,
+        }))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), EaDesktopError> {
+        let state_path = path.join(INSTALLER_STATE_PATH);
+        if let Some(parent) = state_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let state = if self.complete {
+            "Installed"
+        } else {
+            "Downloading"
+        };
+
+        let content = format!(
+            "offerId={}\nbuildId={}\nstate={}\n",
+            self.offer_id,
+            self.build_id.as_deref().unwrap_or_default(),
+            state
+        );
+        fs::write(state_path, content)?;
+        Ok(())
+    }
+
+    /// Install info of an install taken over from EA Desktop
+    pub fn install_info(&self, path: PathBuf, wine_prefix: Option<PathBuf>) -> GameInstallInfo {
+        let mut install_info = GameInstallInfo::new(path, wine_prefix);
+        install_info.offer_id = Some(self.offer_id.clone());
+        install_info.build_id = self.build_id.clone();
+        install_info
+    }
+}
+
+/// What EA Desktop knows about the install of an offer
+#[derive(Debug, Clone, PartialEq)]
+pub enum EaInstallState {
+    NotInstalled,
+    /// The install check file is there, EA Desktop considers the game installed
+    Installed(PathBuf),
+    /// The install directory exists without the install check file, or `__Installer` says the
+    /// download didn't finish
+    Partial(PathBuf),
+}
+
+/// Registry EA Desktop keeps its install keys in
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstallRegistry {
+    /// The registry of this machine
+    #[cfg(windows)]
+    Native,
+    /// `system.reg` of a wine prefix, for EA Desktop running under wine. Wine keeps the
+    /// registry in memory while it runs, so nothing may be running in the prefix while
+    /// it's written.
+    Wine(PathBuf),
+}
+
+impl InstallRegistry {
+    /// Registry of the prefix at `prefix`. Proton prefixes have the wine prefix in `pfx`.
+    pub fn wine_prefix(prefix: &Path) -> Self {
+        let pfx = prefix.join("pfx");
+        let dir = if pfx.join("system.reg").exists() {
+            pfx
+        } else {
+            prefix.to_path_buf()
+        };
+
+        Self::Wine(dir.join("system.reg"))
+    }
+
+    pub fn read(&self, key: &str, value: &str) -> Result<Option<String>, EaDesktopError> {
+        match self {
+            #[cfg(windows)]
+            Self::Native => {
+                use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+                let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+                let result = hklm
+                    .open_subkey(key)
+                    .and_then(|key| key.get_value::<String, _>(value));
+
+                match result {
+                    Ok(data) => Ok(Some(data)),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(err) => Err(err.into()),
+                }
+            }
+            Self::Wine(path) => {
+                let file = WineRegistryFile::load(path)?;
+
+                // 32-bit programs in a 64-bit prefix have their keys under Wow6432Node
+                let data = file
+                    .get(key, value)
+                    .or_else(|| wow64_key(key).and_then(|key| file.get(&key, value)));
+                Ok(data)
+            }
+        }
+    }
+
+    pub fn write(&self, key: &str, value: &str, data: &str) -> Result<(), EaDesktopError> {
+        match self {
+            #[cfg(windows)]
+            Self::Native => {
+                use winreg::{
+                    enums::{HKEY_LOCAL_MACHINE, KEY_WRITE},
+                    RegKey,
+                };
+
+                let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+                let (key, _) = hklm.create_subkey_with_flags(key, KEY_WRITE)?;
+                key.set_value(value, &data)?;
+                Ok(())
+            }
+            Self::Wine(path) => {
+                let mut file = WineRegistryFile::load(path)?;
+                file.set(key, value, data);
+                file.save(path)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Converts an install directory to how EA Desktop stores it, with a trailing separator
+    fn registry_path(&self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        match self {
+            #[cfg(windows)]
+            Self::Native => format!("{}\\", path.trim_end_matches(['\\', '/'])),
+            Self::Wine(_) => format!("Z:{}\\", path.trim_end_matches('/').replace('/', "\\")),
+        }
+    }
+
+    fn install_path(&self, data: &str) -> PathBuf {
+        match self {
+            #[cfg(windows)]
+            Self::Native => PathBuf::from(data.trim_end_matches(['\\', '/'])),
+            Self::Wine(system_reg) => {
+                let data = data.replace('\\', "/");
+                let (drive, rest) = match data.split_once(':') {
+                    Some((drive, rest)) if drive.len() == 1 => (drive.to_lowercase(), rest),
+                    _ => return PathBuf::from(data),
+                };
+
+                // Every drive is a link in `dosdevices`, Z: is the root of the file system
+                let root = if drive == "z" {
+                    PathBuf::from("/")
+                } else {
+                    system_reg
+                        .with_file_name("dosdevices")
+                        .join(format!("{}:", drive))
+                };
+
+                root.join(rest.trim_matches('/'))
+            }
+        }
+    }
+}
+
+/// Looks up where EA Desktop has `check` installed
+pub fn install_state(
+    registry: &InstallRegistry,
+    check: &InstallCheck,
+) -> Result<EaInstallState, EaDesktopError> {
+    let Some(data) = registry.read(&check.key, &check.value)? else {
+        return Ok(EaInstallState::NotInstalled);
+    };
+
+    let path = registry.install_path(&data);
+    if !path.is_dir() {
+        debug!("{} points to {}, which is gone", check.key, path.display());
+        return Ok(EaInstallState::NotInstalled);
+    }
+
+    // DiP games have the install check file in the manifest, which is one of the first
+    // files downloaded
+    let complete = InstallerState::read(&path)?.map_or(true, |state| state.complete);
+    if complete && path.join(&check.file).is_file() {
+        Ok(EaInstallState::Installed(path))
+    } else {
+        Ok(EaInstallState::Partial(path))
+    }
+}
+
+/// Points the install check at `path` and writes the install state there, so EA Desktop
+/// finds the game
+pub fn register_install(
+    registry: &InstallRegistry,
+    check: &InstallCheck,
+    path: &Path,
+    state: &InstallerState,
+) -> Result<(), EaDesktopError> {
+    state.write(path)?;
+
+    let data = registry.registry_path(path);
+    debug!("Setting {}\\{} to {}", check.key, check.value, data);
+    registry.write(&check.key, &check.value, &data)
+}
+
+fn wow64_key(key: &str) -> Option<String> {
+    let (root, rest) = key.split_once('\\')?;
+    if !root.eq_ignore_ascii_case("software") || rest.to_lowercase().starts_with("wow6432node") {
+        return None;
+    }
+
+    Some(format!("{}\\Wow6432Node\\{}", root, rest))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(next) => result.push(next),
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+/// Splits `"name"=data` into the unescaped name and the data
+fn split_value_line(line: &str) -> Option<(String, &str)> {
+    let rest = line.strip_prefix('"')?;
+
+    let mut escaped = false;
+    let end = rest.char_indices().find_map(|(idx, c)| match c {
+        '\\' if !escaped => {
+            escaped = true;
+            None
+        }
+        '"' if !escaped => Some(idx),
+        _ => {
+            escaped = false;
+            None
+        }
+    })?;
+
+    let data = rest[end + 1..].strip_prefix('=')?;
+    Some((unescape(&rest[..end]), data))
+}
+
+/// Text registry wine keeps in a prefix, every key is relative to `HKEY_LOCAL_MACHINE`
+struct WineRegistryFile {
+    lines: Vec<String>,
+}
+
+impl WineRegistryFile {
+    fn load(path: &Path) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                lines: vec![
+                    "WINE REGISTRY Version 2".to_owned(),
+                    ";; All keys relative to \\\\Machine".to_owned(),
+                ],
+            });
+        }
+
+        let content = fs::read_to_string(path)?;
+        Ok(Self {
+            lines: content.lines().map(str::to_owned).collect(),
+        })
+    }
+
+    /// Lines of the section of `key`, from its header up to the next one
+    fn section(&self, key: &str) -> Option<(usize, usize)> {
+        let start = self.lines.iter().position(|line| {
+            let Some(header) = line.strip_prefix('[') else {
+                return false;
+            };
+
+            header
+                .rfind(']')
+                .is_some_and(|end| unescape(&header[..end]).eq_ignore_ascii_case(key))
+        })?;
+
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|line| line.starts_with('['))
+            .map_or(self.lines.len(), |idx| start + 1 + idx);
+
+        Some((start, end))
+    }
+
+    fn value_line(&self, (start, end): (usize, usize), value: &str) -> Option<usize> {
+        (start + 1..end).find(|idx| {
+            split_value_line(&self.lines[*idx])
+                .is_some_and(|(name, _)| name.eq_ignore_ascii_case(value))
+        })
+    }
+
+    /// String values only, EA Desktop doesn't use anything else for installs
+    fn get(&self, key: &str, value: &str) -> Option<String> {
+        let section = self.section(key)?;
+        let line = &self.lines[self.value_line(section, value)?];
+        let (_, data) = split_value_line(line)?;
+
+        let data = data.strip_prefix('"')?.strip_suffix('"')?;
+        Some(unescape(data))
+    }
+
+    fn set(&mut self, key: &str, value: &str, data: &str) {
+        let line = format!("\"{}\"=\"{}\"", escape(value), escape(data));
+
+        let Some(section) = self.section(key) else {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default();
+
+            self.lines.push(String::new());
+            self.lines.push(format!("[{}] {}", escape(key), time));
+            self.lines.push(line);
+            return;
+        };
+
+        if let Some(idx) = self.value_line(section, value) {
+            self.lines[idx] = line;
+            return;
+        }
+
+        // After the last line of the section, before the blank line separating it from the next
+        let (start, end) = section;
+        let last = (start..end)
+            .rev()
+            .find(|idx| !self.lines[*idx].trim().is_empty())
+            .unwrap_or(start);
+        self.lines.insert(last + 1, line);
+    }
+
+    /// Written to a temporary file first, so a crash can't leave the prefix without a registry
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = path.with_extension("reg.tmp");
+        fs::write(&tmp_path, self.lines.join("\n") + "\n")?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEAD_SPACE_2: &str =
+        "[HKEY_LOCAL_MACHINE\\SOFTWARE\\EA Games\\Dead Space 2\\Install Dir]__Installer\\installerdata.xml";
+    const DEAD_SPACE_2_OFFER: &str = "Origin.OFR.50.0000384";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("maxima-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_install_check() {
+        let check = InstallCheck::parse(DEAD_SPACE_2).unwrap();
+        assert_eq!(check.key(), "SOFTWARE\\EA Games\\Dead Space 2");
+        assert_eq!(check.value(), "Install Dir");
+        assert_eq!(check.file(), "__Installer/installerdata.xml");
+
+        assert!(InstallCheck::parse("C:\\Games\\Dead Space 2").is_err());
+    }
+
+    #[test]
+    fn wine_registry_round_trip() -> Result<(), EaDesktopError> {
+        let dir = temp_dir("wine-registry");
+        let path = dir.join("system.reg");
+        fs::write(
+            &path,
+            "WINE REGISTRY Version 2\n\
+             ;; All keys relative to \\\\Machine\n\
+             \n\
+             [Software\\\\Wow6432Node\\\\EA Games\\\\Dead Space 2] 1700000000\n\
+             #time=1da0f3c2b4e6a10\n\
+             \"Install Dir\"=\"Z:\\\\old\\\\Dead Space 2\\\\\"\n\
+             \"Locale\"=\"en_US\"\n\
+             \n\
+             [Software\\\\Wine] 1700000000\n\
+             \"Version\"=\"win10\"\n",
+        )?;
+
+        let registry = InstallRegistry::Wine(path.clone());
+        let key = "SOFTWARE\\EA Games\\Dead Space 2";
+
+        // Only the Wow6432Node key exists, like after a 32-bit installer ran in a 64-bit prefix
+        assert_eq!(
+            registry.read(key, "Install Dir")?.as_deref(),
+            Some("Z:\\old\\Dead Space 2\\")
+        );
+
+        let wow64 = "Software\\Wow6432Node\\EA Games\\Dead Space 2";
+        registry.write(wow64, "Install Dir", "Z:\\games\\Dead Space 2\\")?;
+        registry.write(wow64, "DisplayName", "Dead Space\u{2122} 2")?;
+        registry.write("Software\\EA Games\\BFBC2", "Install Dir", "C:\\BFBC2\\")?;
+
+        assert_eq!(
+            registry.read(wow64, "install dir")?.as_deref(),
+            Some("Z:\\games\\Dead Space 2\\")
+        );
+        assert_eq!(registry.read(wow64, "Locale")?.as_deref(), Some("en_US"));
+        assert_eq!(
+            registry.read(wow64, "DisplayName")?.as_deref(),
+            Some("Dead Space\u{2122} 2")
+        );
+        assert_eq!(
+            registry
+                .read("Software\\EA Games\\BFBC2", "Install Dir")?
+                .as_deref(),
+            Some("C:\\BFBC2\\")
+        );
+        assert_eq!(
+            registry.read("Software\\Wine", "Version")?.as_deref(),
+            Some("win10")
+        );
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_state_in_wine_prefix() -> Result<(), EaDesktopError> {
+        let dir = temp_dir("ea-install-state");
+        let registry = InstallRegistry::wine_prefix(&dir);
+        let check = InstallCheck::parse(DEAD_SPACE_2)?;
+
+        assert_eq!(
+            install_state(&registry, &check)?,
+            EaInstallState::NotInstalled
+        );
+
+        let install_dir = dir.join("Dead Space 2");
+        let downloading = InstallerState::new(DEAD_SPACE_2_OFFER.to_owned(), None, false);
+        register_install(&registry, &check, &install_dir, &downloading)?;
+        assert_eq!(
+            install_state(&registry, &check)?,
+            EaInstallState::Partial(install_dir.clone())
+        );
+
+        // The manifest is there before the download finished
+        fs::write(install_dir.join("__Installer/installerdata.xml"), "<game/>")?;
+        assert_eq!(
+            install_state(&registry, &check)?,
+            EaInstallState::Partial(install_dir.clone())
+        );
+
+        let installed = InstallerState::new(DEAD_SPACE_2_OFFER.to_owned(), None, true);
+        register_install(&registry, &check, &install_dir, &installed)?;
+        assert_eq!(
+            install_state(&registry, &check)?,
+            EaInstallState::Installed(install_dir.clone())
+        );
+
+        // Installs EA Desktop finished before recording its state
+        fs::remove_file(install_dir.join(INSTALLER_STATE_PATH))?;
+        assert_eq!(
+            install_state(&registry, &check)?,
+            EaInstallState::Installed(install_dir)
+        );
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn installer_state_round_trip() -> Result<(), EaDesktopError> {
+        let dir = temp_dir("installer-state");
+        assert_eq!(InstallerState::read(&dir)?, None);
+
+        let state = InstallerState::new(
+            DEAD_SPACE_2_OFFER.to_owned(),
+            Some("1.0.0.2".to_owned()),
+            true,
+        );
+        state.write(&dir)?;
+        assert_eq!(InstallerState::read(&dir)?, Some(state));
+
+        let state = InstallerState::new(DEAD_SPACE_2_OFFER.to_owned(), None, false);
+        state.write(&dir)?;
+        assert_eq!(InstallerState::read(&dir)?, Some(state));
+
+        // Written by EA Desktop, with keys Maxima doesn't know
+        fs::write(
+            dir.join(INSTALLER_STATE_PATH),
+            "offerId=Origin.OFR.50.0000384\r\nlocale=en_US\r\nbuildId=1.0.0.2\r\nstate=Installed\r\n",
+        )?;
+        let state = InstallerState::read(&dir)?.unwrap();
+        assert_eq!(state.offer_id(), DEAD_SPACE_2_OFFER);
+        assert_eq!(state.build_id().as_deref(), Some("1.0.0.2"));
+        assert!(state.complete());
+
+        fs::write(dir.join(INSTALLER_STATE_PATH), "state=Installed\n")?;
+        assert_eq!(InstallerState::read(&dir)?, None);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn adopted_install_records_offer_and_build() -> Result<(), EaDesktopError> {
+        let dir = temp_dir("adopted-install");
+        InstallerState::new(
+            DEAD_SPACE_2_OFFER.to_owned(),
+            Some("1.0.0.2".to_owned()),
+            true,
+        )
+        .write(&dir)?;
+
+        let state = InstallerState::read(&dir)?.unwrap();
+        let install_info = state.install_info(dir.clone(), None);
+        assert_eq!(install_info.path, dir);
+        assert_eq!(install_info.offer_id.as_deref(), Some(DEAD_SPACE_2_OFFER));
+        assert_eq!(install_info.build_id.as_deref(), Some("1.0.0.2"));
+        assert!(!install_info.incomplete);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::util::native::maxima_dir;
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use log::{error, info, warn};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -36,3 +36,47 @@ pub fn get_exclusion_list(slug: &str) -> GlobSet {
         .build()
         .unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap()) // Returns an empty GlobSet on failure
 }
+
+/// Files of the languages that weren't picked. The includes of a DiP manifest can overlap, so
+/// files that are also part of a picked language are kept.
+pub struct LanguageExclusions {
+    excluded: GlobSet,
+    kept: GlobSet,
+}
+
+impl LanguageExclusions {
+    pub fn new(excluded: &[String], kept: &[String]) -> Self {
+        Self {
+            excluded: get_pattern_list(excluded),
+            kept: get_pattern_list(kept),
+        }
+    }
+
+    /// Leaves nothing out
+    pub fn none() -> Self {
+        Self::new(&[], &[])
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.excluded.is_match(name) && !self.kept.is_match(name)
+    }
+}
+
+/// Builds file patterns from a game's DiP manifest, like the files of a language or of a
+/// progressive install chunk. Windows paths don't care about case, so neither do these.
+pub fn get_pattern_list(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        match GlobBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => warn!("Invalid file pattern '{}': {}", pattern, err),
+        }
+    }
+
+    builder
+        .build()
+        .unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap())
+}
@@ -0,0 +1,147 @@
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+/// How many seconds worth of bytes can be spent at once after being idle
+const BURST_SECONDS: f64 = 1.0;
+
+/// Window used to measure the peak throughput, which is what utilization is relative to
+/// when no explicit limit is set
+const PEAK_WINDOW: Duration = Duration::from_secs(1);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+
+    window_start: Instant,
+    window_bytes: u64,
+    peak_rate: f64,
+}
+
+/// Token bucket shared by every entry request of a download. The limit and utilization
+/// can be changed at any time and apply to the next acquired chunk.
+pub struct RateLimiter {
+    /// Bytes per second, 0 means unlimited
+    limit: AtomicU64,
+    /// Bit pattern of an f32 in the range of 0.0 to 1.0
+    utilization: AtomicU32,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        let now = Instant::now();
+
+        Self {
+            limit: AtomicU64::new(limit.unwrap_or(0)),
+            utilization: AtomicU32::new(1.0f32.to_bits()),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last_refill: now,
+                window_start: now,
+                window_bytes: 0,
+                peak_rate: 0.0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        match self.limit.load(Ordering::SeqCst) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit.store(limit.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    pub fn utilization(&self) -> f32 {
+        f32::from_bits(self.utilization.load(Ordering::SeqCst))
+    }
+
+    /// Scales the available bandwidth. Games use this through the LSX to
+    /// throttle background downloads while they're running.
+    pub fn set_utilization(&self, utilization: f32) {
+        let utilization = if utilization.is_nan() {
+            1.0
+        } else {
+            utilization.clamp(0.0, 1.0)
+        };
+
+        self.utilization
+            .store(utilization.to_bits(), Ordering::SeqCst);
+    }
+
+    fn rate(&self, peak_rate: f64) -> Option<f64> {
+        let utilization = self.utilization() as f64;
+
+        if let Some(limit) = self.limit() {
+            return Some(limit as f64 * utilization);
+        }
+
+        // Without an explicit limit, utilization is relative to the fastest we've seen
+        if utilization < 1.0 && peak_rate > 0.0 {
+            return Some(peak_rate * utilization);
+        }
+
+        None
+    }
+
+    /// Waits until `amount` bytes may be consumed
+    pub async fn acquire(&self, amount: usize) {
+        let amount = amount as f64;
+
+        loop {
+            let (wait, acquired) = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+
+                let window = now - bucket.window_start;
+                if window >= PEAK_WINDOW {
+                    let rate = bucket.window_bytes as f64 / window.as_secs_f64();
+                    bucket.peak_rate = bucket.peak_rate.max(rate);
+                    bucket.window_start = now;
+                    bucket.window_bytes = 0;
+                }
+
+                let rate = match self.rate(bucket.peak_rate) {
+                    Some(rate) => rate,
+                    None => {
+                        bucket.window_bytes += amount as u64;
+                        bucket.last_refill = now;
+                        return;
+                    }
+                };
+
+                // Downloading has been paused entirely, check back later
+                if rate < 1.0 {
+                    bucket.last_refill = now;
+                    (Duration::from_secs(1), false)
+                } else {
+                    let elapsed = (now - bucket.last_refill).as_secs_f64();
+                    bucket.tokens = (bucket.tokens + elapsed * rate).min(rate * BURST_SECONDS);
+                    bucket.last_refill = now;
+
+                    // Tokens may go negative, which makes later callers wait off the debt
+                    bucket.tokens -= amount;
+                    bucket.window_bytes += amount as u64;
+
+                    let debt = (-bucket.tokens).max(0.0);
+                    (Duration::from_secs_f64(debt / rate), true)
+                }
+            };
+
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+
+            if acquired {
+                return;
+            }
+        }
+    }
+}
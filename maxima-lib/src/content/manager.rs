@@ -1,15 +1,18 @@
 use std::{
-    path::PathBuf,
+    collections::HashSet,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
+use chrono::Utc;
 use derive_builder::Builder;
 use derive_getters::Getters;
 use futures::StreamExt;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -18,23 +21,47 @@ use tokio_util::sync::CancellationToken;
 
 use crate::{
     content::{
-        downloader::{DownloadError, ZipDownloader},
-        exclusion::get_exclusion_list,
-        zip::{self, CompressionType, ZipError, ZipFileEntry},
-        ContentService,
+        diff::{diff_builds, BuildDiff},
+        downloader::{DownloadConcurrency, DownloadError, ZipDownloader},
+        eadesktop::{
+            self, EaDesktopError, EaInstallState, InstallCheck, InstallRegistry, InstallerState,
+        },
+        exclusion::{get_exclusion_list, LanguageExclusions},
+        limiter::RateLimiter,
+        mirror::cdn_source,
+        mover::{InstallMover, MoveError},
+        peer::PeerClient,
+        progress::{DownloadPhase, DownloadProgress, ProgressCounters, ThroughputMeter},
+        progressive::ChunkProgress,
+        source::{source_for, ByteSource, SourceError},
+        uninstall::{self, UninstallOptions},
+        update::AvailableUpdate,
+        verify::{self, FileVerifyResult, FileVerifyStatus},
+        zip::{CompressionType, ZipError, ZipFile, ZipFileEntry},
+        ContentService, DownloadUrlRefresher,
     },
     core::{
         auth::storage::LockedAuthStorage,
-        manifest::{self, ManifestError, MANIFEST_RELATIVE_PATH},
-        service_layer::ServiceLayerError,
+        library::{LibraryError, OwnedOffer},
+        manifest::{self, dip::DiPManifest, ManifestError, DEFAULT_LOCALE, MANIFEST_RELATIVE_PATH},
+        service_layer::{ServiceAddonOffer, ServiceLayerError},
         MaximaEvent,
     },
-    gameinfo::GameInstallInfo,
-    util::native::{maxima_dir, NativeError},
+    gameinfo::{load_game_info_from_json, GameInstallInfo, InstalledDlc},
+    util::native::{available_space, maxima_dir, NativeError},
 };
 
 const QUEUE_FILE: &str = "download_queue.json";
 
+/// Rounds of retries for files that failed to download, after the first attempt
+const RETRY_ROUNDS: u32 = 3;
+/// Wait before the first retry round, doubled after every round
+const RETRY_ROUND_DELAY: Duration = Duration::from_secs(5);
+/// How often `MaximaEvent::DownloadProgress` is emitted while a download is running
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Finished installs remembered by the queue, older ones are forgotten
+const MAX_COMPLETED: usize = 100;
+
 #[derive(Default, Builder, Getters, Clone, Serialize, Deserialize, PartialEq)]
 pub struct QueuedGame {
     offer_id: String,
@@ -42,14 +69,111 @@ pub struct QueuedGame {
     path: PathBuf,
     slug: String,
     wine_prefix: Option<PathBuf>,
+    /// Install from this archive instead of the CDN. Can be an HTTP(S) URL, a `file://` URL or
+    /// an absolute path to a local or mounted build
+    #[builder(default)]
+    #[serde(default)]
+    source: Option<String>,
+    /// Locales to install, the first one is what the game runs in. Every language is
+    /// installed when this is empty.
+    #[builder(default)]
+    #[serde(default)]
+    languages: Vec<String>,
+    /// Set for add-ons. They're installed into the directory of the game with this offer, and
+    /// recorded in its game info under the same slug.
+    #[builder(default)]
+    #[serde(default)]
+    base_offer_id: Option<String>,
+    /// Why the last attempt to install this failed. The queue skips it until it's retried.
+    #[builder(default)]
+    #[serde(default)]
+    failure: Option<String>,
+}
+
+impl QueuedGame {
+    pub fn is_dlc(&self) -> bool {
+        self.base_offer_id.is_some()
+    }
+
+    /// Locale touchup is run with
+    pub fn locale(&self) -> &str {
+        self.languages
+            .first()
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_LOCALE)
+    }
+
+    /// Reads the build archive of this game. Downloads from the CDN get a new URL when the
+    /// signed one expires halfway through, and move between the configured mirrors.
+    async fn archive_source(
+        &self,
+        content_service: &ContentService,
+    ) -> Result<Arc<dyn ByteSource>, DownloaderError> {
+        if let Some(source) = &self.source {
+            debug!("Source: {}", source);
+            return Ok(source_for(source)?);
+        }
+
+        let url = match content_service
+            .download_url(&self.offer_id, Some(&self.build_id))
+            .await
+        {
+            Ok(url) => url,
+            Err(err) => {
+                warn!(
+                    "No download of build {} of {} ({}), looking for a legacy package",
+                    self.build_id, self.slug, err
+                );
+                return self.legacy_archive_source(content_service).await;
+            }
+        };
+        debug!("URL: {}", url.url());
+
+        let refresher =
+            DownloadUrlRefresher::new(content_service.clone(), &self.offer_id, &self.build_id);
+        Ok(cdn_source(
+            url.url(),
+            content_service.mirrors(),
+            Arc::new(refresher),
+        )?)
+    }
+
+    /// Reads the package of a pre-DiP build, resolved from the legacy download metadata of
+    /// the offer
+    async fn legacy_archive_source(
+        &self,
+        content_service: &ContentService,
+    ) -> Result<Arc<dyn ByteSource>, DownloaderError> {
+        let download = content_service
+            .legacy_download(&self.offer_id, &self.build_id)
+            .await?;
+        let version = download.build_release_version();
+        info!("Using legacy package {} of {}", version, self.slug);
+
+        let url = content_service
+            .download_url(&self.offer_id, Some(version))
+            .await?;
+        debug!("URL: {}", url.url());
+
+        let refresher = DownloadUrlRefresher::new(content_service.clone(), &self.offer_id, version);
+        Ok(cdn_source(
+            url.url(),
+            content_service.mirrors(),
+            Arc::new(refresher),
+        )?)
+    }
 }
 
 #[derive(Default, Getters, Serialize, Deserialize)]
 pub struct DownloadQueue {
     current: Option<QueuedGame>,
+    /// Nothing is downloaded while the queue is paused, `current` is kept so it can be resumed
     paused: bool,
 
+    /// Installed in order, the first game is the next one to be installed. Games whose
+    /// install failed stay here, but are skipped until they're retried.
     queued: Vec<QueuedGame>,
+    /// The most recent installs that finished, up to `MAX_COMPLETED`
     completed: Vec<QueuedGame>,
 }
 
@@ -63,9 +187,39 @@ pub enum ContentManagerError {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    ServiceLayer(#[from] ServiceLayerError),
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+    #[error(transparent)]
+    QueuedGameBuilder(#[from] QueuedGameBuilderError),
+    #[error(transparent)]
+    Move(#[from] MoveError),
+    #[error(transparent)]
+    EaDesktop(#[from] EaDesktopError),
 
     #[error("download in progress, you must cancel it before starting a new one")]
     DownloadInProgress,
+    #[error("`{0}` is not installed")]
+    NotInstalled(String),
+    #[error("`{0}` is not in the download queue")]
+    NotQueued(String),
+    #[error("the install of `{0}` hasn't failed")]
+    NotFailed(String),
+    #[error("refusing to delete `{0}`, it doesn't look like a game install or Wine prefix")]
+    UnsafeUninstall(PathBuf),
+    #[error("the build of `{0}` is unknown, it wasn't installed by Maxima")]
+    UnknownBuild(String),
+    #[error("`{0}` doesn't support changing its language")]
+    LanguageChangeUnsupported(String),
+    #[error("`{0}` has no build that can be downloaded")]
+    NoLiveBuild(String),
+    #[error("`{0}` has no install check, EA Desktop can't find its installs")]
+    NoInstallCheck(String),
+    #[error("no EA Desktop registry is set")]
+    NoEaDesktopRegistry,
+    #[error("{1} files of `{0}` couldn't be repaired")]
+    RepairFailed(String, usize),
 }
 
 #[derive(Error, Debug)]
@@ -84,6 +238,8 @@ pub enum DownloaderError {
     Native(#[from] NativeError),
     #[error(transparent)]
     Manifest(#[from] ManifestError),
+    #[error(transparent)]
+    Source(#[from] SourceError),
 
     #[error("path `{0}` is not absolute")]
     PathNotAbsolute(PathBuf),
@@ -93,6 +249,12 @@ pub enum DownloaderError {
     EntrySize { requested: u64, entry: usize },
     #[error("unsupported compression type `{0:?}`")]
     CompressionType(CompressionType),
+    #[error(
+        "not enough disk space, {required} bytes are required but only {available} are available"
+    )]
+    InsufficientSpace { required: u64, available: u64 },
+    #[error("language `{language}` is not available, the build supports {supported}")]
+    UnsupportedLanguage { language: String, supported: String },
 }
 
 impl DownloadQueue {
@@ -118,187 +280,728 @@ impl DownloadQueue {
     }
 
     pub fn push_to_current(&mut self, game: QueuedGame) {
-        if let Some(current) = &self.current {
-            self.queued.push(current.clone());
+        if let Some(current) = self.current.take() {
+            self.queued.insert(0, current);
+        }
+
+        self.current = Some(game);
+    }
+
+    /// Whether `offer_id` is being downloaded or waiting to be
+    pub fn contains(&self, offer_id: &str) -> bool {
+        self.current.iter().any(|x| x.offer_id == offer_id)
+            || self.queued.iter().any(|x| x.offer_id == offer_id)
+    }
+
+    fn push_completed(&mut self, game: QueuedGame) {
+        self.completed.push(game);
+        if self.completed.len() > MAX_COMPLETED {
+            self.completed.drain(..self.completed.len() - MAX_COMPLETED);
         }
+    }
+
+    /// Whether there's a game in the queue that can be installed next
+    fn has_next(&self) -> bool {
+        self.queued.iter().any(|x| x.failure.is_none())
+    }
 
-        self.current = Some(game.clone());
+    fn pop_next(&mut self) -> Option<QueuedGame> {
+        let index = self.queued.iter().position(|x| x.failure.is_none())?;
+        Some(self.queued.remove(index))
     }
 }
 
-pub struct GameDownloader {
-    offer_id: String,
-    slug: String,
-    path: PathBuf,
-    wine_prefix: Option<PathBuf>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadResult {
+    Finished,
+    Failed(InstallFailure),
+}
+
+/// Why an install stopped before it finished
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum InstallFailure {
+    /// Names of the files that still couldn't be downloaded after retrying
+    #[error("{} files couldn't be downloaded", .0.len())]
+    Files(Vec<String>),
+    #[error(
+        "not enough disk space, {required} bytes are required but only {available} are available"
+    )]
+    InsufficientSpace { required: u64, available: u64 },
+    /// Anything else, like the build not being found or touchup failing
+    #[error("{0}")]
+    Error(String),
+}
 
+impl From<&DownloaderError> for InstallFailure {
+    fn from(err: &DownloaderError) -> Self {
+        match err {
+            DownloaderError::InsufficientSpace {
+                required,
+                available,
+            } => Self::InsufficientSpace {
+                required: *required,
+                available: *available,
+            },
+            err => Self::Error(err.to_string()),
+        }
+    }
+}
+
+/// Sizes of an install, shown to the user before they confirm it
+#[derive(Debug, Clone, Getters)]
+pub struct InstallSize {
+    /// Compressed bytes that will be downloaded
+    download_size: u64,
+    /// Size of the downloaded files once they're extracted
+    install_size: u64,
+    /// Space still needed on disk, files that are already there are taken into account
+    required_space: u64,
+    /// Free space on the disk the game is installed to, `None` if it couldn't be found out
+    available_space: Option<u64>,
+}
+
+impl InstallSize {
+    /// `outdated` files are replaced once their new version is complete, so the space they
+    /// take up can't be counted on
+    async fn new(path: &Path, entries: &[ZipFileEntry], outdated: &HashSet<String>) -> Self {
+        let mut download_size = 0;
+        let mut install_size = 0;
+        let mut required_space = 0;
+
+        for entry in entries {
+            let uncompressed = *entry.uncompressed_size() as u64;
+            let on_disk = if outdated.contains(entry.name()) {
+                0
+            } else {
+                fs::metadata(path.join(entry.name()))
+                    .await
+                    .map(|x| x.len())
+                    .unwrap_or(0)
+            };
+
+            download_size += *entry.compressed_size() as u64;
+            install_size += uncompressed;
+            required_space += uncompressed.saturating_sub(on_disk);
+        }
+
+        let available_space = available_space(path);
+        if available_space.is_none() {
+            warn!(
+                "Couldn't find out the free space at {}, skipping the check",
+                path.display()
+            );
+        }
+
+        Self {
+            download_size,
+            install_size,
+            required_space,
+            available_space,
+        }
+    }
+
+    /// True when the free space is unknown, the install is tried anyway
+    pub fn fits(&self) -> bool {
+        self.available_space
+            .map_or(true, |available| self.required_space <= available)
+    }
+
+    /// Fails with `DownloaderError::InsufficientSpace` if the install doesn't fit on the disk
+    pub fn check(&self) -> Result<(), DownloaderError> {
+        let Some(available) = self.available_space else {
+            return Ok(());
+        };
+
+        if self.required_space > available {
+            return Err(DownloaderError::InsufficientSpace {
+                required: self.required_space,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Everything a download has to do, worked out from the central directory of the build
+struct DownloadPlan {
     downloader: Arc<ZipDownloader>,
     entries: Vec<ZipFileEntry>,
+    /// Files of the installed build that are no longer part of the new one
+    removed: Vec<String>,
+    size: InstallSize,
+    /// Set for progressive installs, `entries` are sorted so the chunks download in order
+    chunks: Option<Arc<ChunkProgress>>,
+}
+
+pub struct GameDownloader {
+    game: QueuedGame,
+    content_service: ContentService,
+    rate_limiter: Arc<RateLimiter>,
+    preallocate: bool,
+    concurrency: DownloadConcurrency,
 
     cancel_token: CancellationToken,
-    completed_bytes: Arc<AtomicUsize>,
-    total_count: usize,
-    total_bytes: usize,
+    progress: Arc<ProgressCounters>,
     notify: Arc<Notify>,
+    result: Arc<Mutex<Option<DownloadResult>>>,
+    /// Set once a progressive install can be played
+    playable: AtomicBool,
 }
 
 impl GameDownloader {
-    pub async fn new(
+    /// Nothing is fetched until `download` is called, the build is looked up as part of the download
+    pub fn new(
         content_service: &ContentService,
         game: &QueuedGame,
-    ) -> Result<Self, DownloaderError> {
-        let url = content_service
-            .download_url(&game.offer_id, Some(&game.build_id))
-            .await?;
-
-        debug!("URL: {}", url.url());
+        rate_limiter: Arc<RateLimiter>,
+        preallocate: bool,
+        concurrency: DownloadConcurrency,
+    ) -> Self {
+        GameDownloader {
+            game: game.clone(),
+            content_service: content_service.clone(),
+            rate_limiter,
+            preallocate,
+            concurrency,
+            cancel_token: CancellationToken::new(),
+            progress: Arc::new(ProgressCounters::default()),
+            notify: Arc::new(Notify::new()),
+            result: Arc::new(Mutex::new(None)),
+            playable: AtomicBool::new(false),
+        }
+    }
 
-        let downloader = ZipDownloader::new(&game.offer_id, &url.url(), &game.path).await?;
+    async fn plan(
+        content_service: &ContentService,
+        game: &QueuedGame,
+        rate_limiter: Arc<RateLimiter>,
+        preallocate: bool,
+        concurrency: DownloadConcurrency,
+    ) -> Result<DownloadPlan, DownloaderError> {
+        let source = game.archive_source(content_service).await?;
+        let mut downloader =
+            match ZipDownloader::with_source(&game.offer_id, source, &game.path).await {
+                Ok(downloader) => downloader,
+                // Pre-DiP games may only have their legacy package
+                Err(err) if game.source.is_none() => {
+                    warn!(
+                        "No build archive of {} found ({}), looking for a legacy package",
+                        game.slug, err
+                    );
+                    let source = game.legacy_archive_source(content_service).await?;
+                    ZipDownloader::with_source(&game.offer_id, source, &game.path).await?
+                }
+                Err(err) => return Err(err),
+            };
+        downloader.set_rate_limiter(rate_limiter);
+        downloader.set_preallocate(preallocate);
+        downloader.set_concurrency(concurrency);
+
+        // Peers serve builds by their ID, which archives from elsewhere might not match
+        if game.source.is_none() && !content_service.peers().is_empty() {
+            downloader.set_peers(PeerClient::new(
+                content_service.peers(),
+                &game.offer_id,
+                &game.build_id,
+            ));
+        }
 
         let mut entries = Vec::new();
+        let mut changed = HashSet::new();
+        let mut removed = Vec::new();
 
         let exclusion_list = get_exclusion_list(game.slug.as_str());
+        let build_manifest = Self::build_manifest(&downloader, game).await;
+        let language_list = Self::language_exclusions(build_manifest.as_ref(), game)?;
+        // The manifest of an add-on would replace the one of its base game
+        let excluded = |name: &str| {
+            exclusion_list.is_match(name)
+                || language_list.is_match(name)
+                || (game.is_dlc() && name.eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH))
+        };
+
+        if let Some(diff) = Self::build_diff(content_service, game, downloader.manifest()).await {
+            info!(
+                "Differential update of {}: {} added, {} changed, {} removed, {} unchanged",
+                game.slug,
+                diff.added.len(),
+                diff.changed.len(),
+                diff.removed.len(),
+                diff.unchanged.len()
+            );
+
+            // Unchanged files are trusted as long as they're still there
+            let missing = diff
+                .unchanged
+                .iter()
+                .filter(|x| !game.path.join(x.name()).exists());
+
+            for ele in diff.entries_to_download().chain(missing) {
+                if excluded(ele.name()) {
+                    continue;
+                }
+                entries.push(ele.clone());
+            }
 
-        for ele in downloader.manifest().entries() {
-            // TODO: Filtering
-            if exclusion_list.is_match(&ele.name()) {
-                // info!("Excluding file from download: {}", ele.name()); Spams if a lot of files are excluded
-                continue;
+            changed = diff.changed.iter().map(|x| x.name().to_owned()).collect();
+            removed = diff
+                .removed
+                .into_iter()
+                .filter(|x| !exclusion_list.is_match(x))
+                .collect();
+        } else {
+            for ele in downloader.manifest().entries() {
+                if excluded(ele.name()) {
+                    // info!("Excluding file from download: {}", ele.name()); Spams if a lot of files are excluded
+                    continue;
+                }
+                entries.push(ele.clone());
             }
-            entries.push(ele.clone());
         }
 
-        let total_count = entries.len();
-        let total_bytes = entries
-            .iter()
-            .map(|x| *x.compressed_size() as usize)
-            .sum::<usize>()
-            + 1; // Add 1 to account for running touchup at the end. Bad solution, but we're a bit rushed
-
-        Ok(GameDownloader {
-            offer_id: game.offer_id.to_owned(),
-            slug: game.slug.to_owned(),
-            path: game.path.to_owned(),
-            wine_prefix: game.wine_prefix.clone(),
+        // Files of languages that are no longer wanted, left over from before a language change
+        removed.extend(
+            downloader
+                .manifest()
+                .entries()
+                .iter()
+                .filter(|x| language_list.is_match(x.name()) && !exclusion_list.is_match(x.name()))
+                .filter(|x| game.path.join(x.name()).is_file())
+                .map(|x| x.name().to_owned()),
+        );
+
+        let chunks = build_manifest
+            .as_ref()
+            .filter(|_| !game.is_dlc())
+            .and_then(|x| ChunkProgress::new(&game.offer_id, x, &mut entries))
+            .map(Arc::new);
+
+        let size = InstallSize::new(&game.path, &entries, &changed).await;
+        // The old version of a file may have the same size, which would pass as complete
+        downloader.set_outdated(changed);
+
+        Ok(DownloadPlan {
             downloader: Arc::new(downloader),
             entries,
-            cancel_token: CancellationToken::new(),
-            completed_bytes: Arc::new(AtomicUsize::new(0)),
-            total_count,
-            total_bytes,
-            notify: Arc::new(Notify::new()),
+            removed,
+            size,
+            chunks,
         })
     }
 
+    /// Reads the DiP manifest out of the build, before any of it is installed. `None` for
+    /// builds without one, like the ones of pre-DiP games.
+    async fn build_manifest(downloader: &ZipDownloader, game: &QueuedGame) -> Option<DiPManifest> {
+        let entry = downloader
+            .manifest()
+            .entries()
+            .iter()
+            .find(|x| x.name().eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH))?;
+
+        let manifest = match downloader
+            .read_zip_entry_bytes(entry, *entry.uncompressed_size() as u64)
+            .await
+        {
+            Ok(bytes) => DiPManifest::parse(bytes.to_vec()),
+            Err(err) => {
+                warn!("Failed to read the manifest of {}: {}", game.slug, err);
+                return None;
+            }
+        };
+
+        match manifest {
+            Ok(manifest) => Some(manifest),
+            Err(err) => {
+                debug!("{} has no DiP manifest: {}", game.slug, err);
+                None
+            }
+        }
+    }
+
+    /// Finds the files of languages that weren't picked. Builds without a DiP manifest get
+    /// every language.
+    fn language_exclusions(
+        manifest: Option<&DiPManifest>,
+        game: &QueuedGame,
+    ) -> Result<LanguageExclusions, DownloaderError> {
+        if game.languages.is_empty() {
+            return Ok(LanguageExclusions::none());
+        }
+
+        let Some(manifest) = manifest else {
+            warn!(
+                "{} has no DiP manifest, installing every language",
+                game.slug
+            );
+            return Ok(LanguageExclusions::none());
+        };
+
+        let supported = manifest.supported_languages();
+        if !supported.is_empty() {
+            for language in &game.languages {
+                if !supported.iter().any(|x| x.eq_ignore_ascii_case(language)) {
+                    return Err(DownloaderError::UnsupportedLanguage {
+                        language: language.clone(),
+                        supported: supported.join(", "),
+                    });
+                }
+            }
+        }
+
+        let patterns = manifest.language_exclusions(&game.languages);
+        debug!(
+            "Installing {} in {}, leaving out {} file patterns",
+            game.slug,
+            game.languages.join(", "),
+            patterns.len()
+        );
+        Ok(LanguageExclusions::new(
+            &patterns,
+            &manifest.language_inclusions(&game.languages),
+        ))
+    }
+
+    /// Compares the installed build with the one about to be downloaded. Returns `None` when
+    /// the whole build has to be checked instead, which is the case for fresh installs, installs
+    /// of unknown builds, and games that don't support differential updates.
+    async fn build_diff(
+        content_service: &ContentService,
+        game: &QueuedGame,
+        target: &ZipFile,
+    ) -> Option<BuildDiff> {
+        let install_info = load_game_info_from_json(&game.slug).ok()?;
+        if install_info.path != game.path
+            || install_info.offer_id.as_ref() != Some(&game.offer_id)
+            || install_info.incomplete
+        {
+            return None;
+        }
+
+        let installed_build = install_info.build_id()?;
+        if installed_build == game.build_id {
+            return None;
+        }
+
+        if !Self::differential_update_enabled(&game.path).await {
+            info!(
+                "{} doesn't support differential updates, checking every file",
+                game.slug
+            );
+            return None;
+        }
+
+        let url = match content_service
+            .download_url(&game.offer_id, Some(installed_build))
+            .await
+        {
+            Ok(url) => url,
+            Err(err) => {
+                warn!(
+                    "Failed to get URL of installed build {}: {}",
+                    installed_build, err
+                );
+                return None;
+            }
+        };
+
+        let installed = match ZipFile::fetch(url.url()).await {
+            Ok(zip) => zip,
+            Err(err) => {
+                warn!(
+                    "Failed to fetch installed build {}: {}",
+                    installed_build, err
+                );
+                return None;
+            }
+        };
+
+        Some(diff_builds(&installed, target))
+    }
+
+    /// Only games whose manifest asks for it get differential updates
+    async fn differential_update_enabled(path: &Path) -> bool {
+        match manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await {
+            Ok(manifest) => manifest
+                .feature_flags()
+                .map(|x| x.attr_enableDifferentialUpdate)
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
     pub fn download(&self) {
-        let (downloader_arc, entries, cancel_token, completed_bytes, notify) =
-            self.prepare_download_vars();
-        let total_count = self.total_count;
-        let slug = self.slug.clone();
-        let game_install_info = GameInstallInfo::new(self.path.clone(), self.wine_prefix.clone());
+        let content_service = self.content_service.clone();
+        let game = self.game.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let preallocate = self.preallocate;
+        let concurrency = self.concurrency;
+        let cancel_token = self.cancel_token.clone();
+        let progress = self.progress.clone();
+        let notify = self.notify.clone();
+        let result = self.result.clone();
+
         tokio::spawn(async move {
             let dl = GameDownloader::start_downloads(
-                total_count,
-                downloader_arc,
-                entries,
-                cancel_token,
-                completed_bytes,
-                notify,
-                slug,
-                game_install_info,
+                &content_service,
+                &game,
+                rate_limiter,
+                preallocate,
+                concurrency,
+                &cancel_token,
+                &progress,
+                &result,
             )
             .await;
             if let Err(err) = dl {
-                error!("Error when downloading!: `{:?}", err)
+                error!("Error when downloading!: `{:?}", err);
+                *result.lock().unwrap() = Some(DownloadResult::Failed((&err).into()));
             }
-        });
-    }
 
-    fn prepare_download_vars(
-        &self,
-    ) -> (
-        Arc<ZipDownloader>,
-        Vec<ZipFileEntry>,
-        CancellationToken,
-        Arc<AtomicUsize>,
-        Arc<Notify>,
-    ) {
-        (
-            self.downloader.clone(),
-            self.entries.clone(),
-            self.cancel_token.clone(),
-            self.completed_bytes.clone(),
-            self.notify.clone(),
-        )
+            if result.lock().unwrap().is_some() {
+                notify.notify_one();
+            }
+        });
     }
 
     async fn start_downloads(
-        total_count: usize,
-        downloader_arc: Arc<ZipDownloader>,
-        entries: Vec<ZipFileEntry>,
-        cancel_token: CancellationToken,
-        completed_bytes: Arc<AtomicUsize>,
-        notify: Arc<Notify>,
-        slug: String,
-        game_install_info: GameInstallInfo,
+        content_service: &ContentService,
+        game: &QueuedGame,
+        rate_limiter: Arc<RateLimiter>,
+        preallocate: bool,
+        concurrency: DownloadConcurrency,
+        cancel_token: &CancellationToken,
+        progress: &Arc<ProgressCounters>,
+        result: &Mutex<Option<DownloadResult>>,
     ) -> Result<(), DownloaderError> {
-        let mut handles = Vec::with_capacity(total_count);
+        progress.set_phase(DownloadPhase::FetchingManifest);
+
+        let plan = Self::plan(
+            content_service,
+            game,
+            rate_limiter,
+            preallocate,
+            concurrency,
+        )
+        .await?;
+        if cancel_token.is_cancelled() {
+            return Ok(());
+        }
 
-        for i in 0..total_count {
-            let downloader = downloader_arc.clone();
-            let ele = entries[i].clone();
+        info!(
+            "Downloading {} bytes of {}, {} bytes once installed",
+            plan.size.download_size, game.slug, plan.size.install_size
+        );
+        plan.size.check()?;
+
+        let downloader_arc = plan.downloader;
+        *progress.source.lock().unwrap() = Some(downloader_arc.source().clone());
+        *progress.chunks.lock().unwrap() = plan.chunks.clone();
+        progress
+            .files_total
+            .store(plan.entries.len(), Ordering::SeqCst);
+        progress
+            .bytes_total
+            .store(plan.size.download_size as usize, Ordering::SeqCst);
+        progress.set_phase(DownloadPhase::Downloading);
+
+        let slug = &game.slug;
+        let mut pending = plan.entries;
+        let mut round = 0;
+        loop {
+            let failed =
+                Self::download_entries(&downloader_arc, pending, cancel_token, progress).await;
+
+            if cancel_token.is_cancelled() || failed.is_empty() {
+                break;
+            }
 
-            let cancel_token = cancel_token.clone();
-            let completed_bytes = completed_bytes.clone();
+            if round >= RETRY_ROUNDS {
+                error!("{} files of {} failed to download", failed.len(), slug);
+                let names = failed.iter().map(|x| x.name().to_owned()).collect();
+                *result.lock().unwrap() =
+                    Some(DownloadResult::Failed(InstallFailure::Files(names)));
+                return Ok(());
+            }
 
-            handles.push(async move {
-                if ele.name().contains("Cleanup") {
-                    info!("Ele: {:?}", ele);
-                }
+            let delay = RETRY_ROUND_DELAY * 2u32.pow(round);
+            round += 1;
+            warn!(
+                "{} files of {} failed to download, retrying in {:?}",
+                failed.len(),
+                slug,
+                delay
+            );
 
-                tokio::select! {
-                    result = downloader.download_single_file(&ele, Some(Box::new(move |bytes| {
-                        completed_bytes.fetch_add(bytes, Ordering::SeqCst);
-                    }))) => {
-                        if let Err(err) = result {
-                            error!("File download failed: {}", err);
-                        }
-                    },
-                    _ = cancel_token.cancelled() => {
-                        info!("Download of {} cancelled", ele.name());
-                    },
-                }
-            });
-        }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = cancel_token.cancelled() => break,
+            }
 
-        let _results = futures::stream::iter(handles)
-            .buffer_unordered(16)
-            .collect::<Vec<_>>()
-            .await;
+            pending = failed;
+        }
 
         let path = downloader_arc.path();
 
-        game_install_info.save_to_json(&slug);
+        if cancel_token.is_cancelled() {
+            return Ok(());
+        }
+
+        for name in plan.removed {
+            let file = path.join(&name);
+            if !file.is_file() {
+                continue;
+            }
+
+            debug!("Removing {}, it's not part of the new build", name);
+            if let Err(err) = fs::remove_file(&file).await {
+                warn!("Failed to remove {}: {}", name, err);
+            }
+        }
+
+        Self::save_install_info(game, false);
         info!("Files downloaded");
 
         #[cfg(windows)]
-        // Touchup will be run on linux/mac when first running the game, so we don't need to run it here
-        {
+        // Touchup will be run on linux/mac when first running the game, so we don't need to run it here.
+        // Add-ons are covered by the touchup of their base game, and some pre-DiP builds have
+        // no manifest and nothing to set up.
+        if !game.is_dlc() && path.join(MANIFEST_RELATIVE_PATH).exists() {
             info!("Running touchup...");
+            progress.set_phase(DownloadPhase::Touchup);
             let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await?;
-            manifest.run_touchup(path, &slug).await?;
+            manifest.run_touchup(path, slug, game.locale()).await?;
         }
 
         info!("Installation finished!");
 
-        completed_bytes.fetch_add(1, Ordering::SeqCst);
-
-        notify.notify_one();
+        *result.lock().unwrap() = Some(DownloadResult::Finished);
         Ok(())
     }
 
+    /// Writes the game info, which is what makes the game show up as installed. Add-ons are
+    /// added to the game info of their base game. `incomplete` installs are playable, but not
+    /// everything has been downloaded yet.
+    fn save_install_info(game: &QueuedGame, incomplete: bool) {
+        let previous = load_game_info_from_json(&game.slug).ok();
+
+        if game.is_dlc() {
+            let Some(mut install_info) = previous else {
+                warn!(
+                    "{} was installed, but {} is no longer installed to record it",
+                    game.offer_id, game.slug
+                );
+                return;
+            };
+
+            install_info.add_dlc(InstalledDlc {
+                offer_id: game.offer_id.clone(),
+                build_id: game.build_id.clone(),
+                installed_at: Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            });
+            install_info.save_to_json(&game.slug);
+            return;
+        }
+
+        let mut game_install_info =
+            GameInstallInfo::new(game.path.clone(), game.wine_prefix.clone());
+        game_install_info.offer_id = Some(game.offer_id.clone());
+        game_install_info.build_id = Some(game.build_id.clone());
+        game_install_info.languages = game.languages.clone();
+        game_install_info.incomplete = incomplete;
+
+        // Add-ons stay installed when the game is updated or repaired in place
+        if let Some(previous) = previous.filter(|x| x.path == game.path) {
+            game_install_info.dlc = previous.dlc;
+        }
+
+        game_install_info.save_to_json(&game.slug);
+    }
+
+    /// Lets a progressive install be launched once its required chunks are in. Returns `true`
+    /// the first time that happens.
+    fn check_playable(&self) -> bool {
+        if self.playable.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        // Chunks without anything to download are installed from the start, at least one file
+        // has to be in before the game is handed out
+        let playable = self.chunk_progress().is_some_and(|x| x.playable());
+        if !playable || self.is_done() || self.progress.files_done.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+
+        info!(
+            "{} can be played while the rest of it downloads",
+            self.game.slug
+        );
+        self.playable.store(true, Ordering::SeqCst);
+
+        // An update keeps the game info of the installed build until it's done
+        let installed = load_game_info_from_json(&self.game.slug)
+            .is_ok_and(|x| x.path == self.game.path && !x.incomplete);
+        if !installed {
+            Self::save_install_info(&self.game, true);
+        }
+        true
+    }
+
+    /// Downloads the given entries, returning the ones that failed
+    async fn download_entries(
+        downloader_arc: &Arc<ZipDownloader>,
+        entries: Vec<ZipFileEntry>,
+        cancel_token: &CancellationToken,
+        progress: &Arc<ProgressCounters>,
+    ) -> Vec<ZipFileEntry> {
+        let handles = entries.into_iter().map(|ele| {
+            let downloader = downloader_arc.clone();
+            let cancel_token = cancel_token.clone();
+            let progress = progress.clone();
+
+            async move {
+                let counted = Arc::new(AtomicUsize::new(0));
+                let callback_counted = counted.clone();
+                let callback_progress = progress.clone();
+
+                tokio::select! {
+                    result = downloader.download_single_file(&ele, Some(Box::new(move |bytes| {
+                        callback_progress.bytes_done.fetch_add(bytes, Ordering::SeqCst);
+                        callback_counted.fetch_add(bytes, Ordering::SeqCst);
+                    }))) => {
+                        match result {
+                            Ok(_) => {
+                                progress.files_done.fetch_add(1, Ordering::SeqCst);
+                                if let Some(chunks) = progress.chunks() {
+                                    chunks.entry_done(&ele);
+                                }
+                                None
+                            },
+                            Err(err) => {
+                                error!("File download failed: {}", err);
+                                // The progress of this file is counted again when it's retried
+                                progress.bytes_done.fetch_sub(counted.load(Ordering::SeqCst), Ordering::SeqCst);
+                                Some(ele.clone())
+                            }
+                        }
+                    },
+                    _ = cancel_token.cancelled() => {
+                        info!("Download of {} cancelled", ele.name());
+                        None
+                    },
+                }
+            }
+        });
+
+        futures::stream::iter(handles)
+            .buffer_unordered(*downloader_arc.concurrency().files())
+            .filter_map(|x| async move { x })
+            .collect()
+            .await
+    }
+
     pub fn cancel(&self) {
-        info!("Pausing installation of {}", self.offer_id);
+        info!("Pausing installation of {}", self.game.offer_id);
         self.cancel_token.cancel();
     }
 
@@ -307,24 +1010,69 @@ impl GameDownloader {
     }
 
     pub fn is_done(&self) -> bool {
-        self.completed_bytes.load(Ordering::SeqCst) == self.total_bytes
+        self.result.lock().unwrap().is_some()
+    }
+
+    /// How the download ended, `None` while it's still running
+    pub fn result(&self) -> Option<DownloadResult> {
+        self.result.lock().unwrap().clone()
     }
 
     pub fn percentage_done(&self) -> f64 {
-        let completed = self.completed_bytes.load(Ordering::SeqCst);
-        (completed as f64 / self.total_bytes as f64) * 100.0
+        let total = self.bytes_total();
+        if total == 0 {
+            return 0.0;
+        }
+
+        (self.bytes_downloaded() as f64 / total as f64) * 100.0
     }
 
     pub fn bytes_downloaded(&self) -> usize {
-        self.completed_bytes.load(Ordering::SeqCst)
+        self.progress.bytes_done.load(Ordering::SeqCst)
     }
 
+    /// Zero until the central directory of the build has been fetched
     pub fn bytes_total(&self) -> usize {
-        self.total_bytes
+        self.progress.bytes_total.load(Ordering::SeqCst)
+    }
+
+    pub fn files_downloaded(&self) -> usize {
+        self.progress.files_done.load(Ordering::SeqCst)
+    }
+
+    pub fn files_total(&self) -> usize {
+        self.progress.files_total.load(Ordering::SeqCst)
+    }
+
+    pub fn phase(&self) -> DownloadPhase {
+        self.progress.phase()
     }
 
     pub fn offer_id(&self) -> &String {
-        &self.offer_id
+        &self.game.offer_id
+    }
+
+    /// Chunks of a progressive install, `None` for other games and until the build has been read
+    pub fn chunk_progress(&self) -> Option<Arc<ChunkProgress>> {
+        self.progress.chunks()
+    }
+
+    fn progress(&self, meter: &mut ThroughputMeter) -> DownloadProgress {
+        let bytes_done = self.bytes_downloaded();
+        let bytes_total = self.bytes_total();
+        let speed = meter.sample(bytes_done);
+
+        DownloadProgress {
+            offer_id: self.game.offer_id.clone(),
+            phase: self.phase(),
+            bytes_done,
+            bytes_total,
+            files_done: self.files_downloaded(),
+            files_total: self.files_total(),
+            speed,
+            eta: meter.eta(bytes_total.saturating_sub(bytes_done)),
+            mirror: self.progress.mirror(),
+        }
     }
 }
 
@@ -333,21 +1081,121 @@ pub struct ContentManager {
     queue: DownloadQueue,
     service: ContentService,
     current: Option<GameDownloader>,
+    rate_limiter: Arc<RateLimiter>,
+    /// Queue updates that games want installed automatically as soon as they're found
+    auto_update: bool,
+    /// Reserve the full size of files before downloading them
+    preallocate: bool,
+    concurrency: DownloadConcurrency,
+    /// Where finished installs are registered for EA Desktop, `None` to not share installs
+    ea_desktop: Option<InstallRegistry>,
+
+    #[getter(skip)]
+    throughput: ThroughputMeter,
+    #[getter(skip)]
+    last_progress: Option<Instant>,
+    #[getter(skip)]
+    auth: LockedAuthStorage,
+    /// The saved queue is restarted on the first `update` after logging in
+    #[getter(skip)]
+    resume_pending: bool,
 }
 
 impl ContentManager {
-    pub async fn new(auth: LockedAuthStorage, _resume: bool) -> Result<Self, ContentManagerError> {
+    /// When `resume` is set, the download that was running when Maxima was last closed is
+    /// restarted as soon as there's an account to download with.
+    pub async fn new(auth: LockedAuthStorage, resume: bool) -> Result<Self, ContentManagerError> {
+        let queue = DownloadQueue::load().await?;
+        let resume_pending =
+            resume && !queue.paused && (queue.current.is_some() || !queue.queued.is_empty());
+
         Ok(Self {
-            queue: DownloadQueue::load().await?,
-            service: ContentService::new(auth),
+            queue,
+            service: ContentService::new(auth.clone()),
             current: None,
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            auto_update: false,
+            preallocate: false,
+            concurrency: DownloadConcurrency::default(),
+            ea_desktop: None,
+            throughput: ThroughputMeter::default(),
+            last_progress: None,
+            auth,
+            resume_pending,
         })
     }
 
+    /// Base URLs like `http://lancache.local` that serve the same builds as the CDN. They're
+    /// tried first, the CDN is used when none of them work.
+    pub fn set_mirrors(&mut self, mirrors: Vec<String>) {
+        info!("Using download mirrors {:?}", mirrors);
+        self.service.set_mirrors(mirrors);
+    }
+
+    /// Base URLs like `http://192.168.1.20:31040` of other machines running a
+    /// `peer::PeerServer`. Files are downloaded from them first when they have the build.
+    pub fn set_peers(&mut self, peers: Vec<String>) {
+        info!("Using download peers {:?}", peers);
+        self.service.set_peers(peers);
+    }
+
+    pub fn set_auto_update(&mut self, auto_update: bool) {
+        self.auto_update = auto_update;
+    }
+
+    /// Applies to downloads started after this is called
+    pub fn set_preallocate(&mut self, preallocate: bool) {
+        self.preallocate = preallocate;
+    }
+
+    /// Applies to downloads started after this is called
+    pub fn set_concurrency(&mut self, concurrency: DownloadConcurrency) {
+        info!(
+            "Downloading {} files at a time, in {} ranges of {} bytes",
+            concurrency.files(),
+            concurrency.ranges(),
+            concurrency.range_size()
+        );
+        self.concurrency = concurrency;
+    }
+
+    /// Sharing installs with EA Desktop is off until a registry is set. EA Desktop under wine
+    /// has its registry in its own prefix, which isn't known to Maxima.
+    pub fn set_ea_desktop_registry(&mut self, registry: Option<InstallRegistry>) {
+        info!("Sharing installs with EA Desktop in {:?}", registry);
+        self.ea_desktop = registry;
+    }
+
+    /// Sets the bandwidth limit in bytes per second for all downloads, `None` removes it
+    pub fn set_rate_limit(&self, bytes_per_second: Option<u64>) {
+        info!("Setting download rate limit to {:?} B/s", bytes_per_second);
+        self.rate_limiter.set_limit(bytes_per_second);
+    }
+
+    /// Scales the bandwidth used by downloads, from 0.0 (paused) to 1.0 (full speed)
+    pub fn set_downloader_utilization(&self, utilization: f32) {
+        debug!("Setting downloader utilization to {}", utilization);
+        self.rate_limiter.set_utilization(utilization);
+    }
+
+    /// Chunks of the progressive install of `offer_id`, while it's being downloaded
+    pub fn chunk_progress(&self, offer_id: &str) -> Option<Arc<ChunkProgress>> {
+        self.current
+            .as_ref()
+            .filter(|x| x.offer_id() == offer_id)
+            .and_then(|x| x.chunk_progress())
+    }
+
+    /// Adds `game` to the end of the queue, installing it right away if nothing else is queued
     pub async fn add_install(&mut self, game: QueuedGame) -> Result<(), ContentManagerError> {
-        if self.queue.queued.is_empty() && self.queue.current == None && self.current.is_none() {
+        if !self.queue.paused
+            && !self.queue.has_next()
+            && self.queue.current == None
+            && self.current.is_none()
+        {
             self.install_now(game).await?;
         } else {
+            self.queue.queued.retain(|x| x.offer_id != game.offer_id);
             self.queue.queued.push(game);
             self.queue.save().await?;
         }
@@ -355,22 +1203,155 @@ impl ContentManager {
         Ok(())
     }
 
+    /// Installs `game` right away. The current download is stopped and put at the front
+    /// of the queue, and the queue is resumed if it was paused.
     pub async fn install_now(&mut self, game: QueuedGame) -> Result<(), ContentManagerError> {
         if let Some(current) = &self.current {
             current.cancel();
             self.current = None;
         }
 
-        if let Some(current) = &self.queue.current {
-            if current == &game {
+        self.queue.paused = false;
+        self.queue.queued.retain(|x| x.offer_id != game.offer_id);
+
+        if let Some(current) = self.queue.current.take() {
+            if current.offer_id != game.offer_id {
+                self.queue.queued.insert(0, current);
+            }
+        }
+
+        self.install_direct(game).await?;
+        Ok(())
+    }
+
+    /// Stops the current download and keeps the queue from moving on until `resume` is
+    /// called. Downloaded files are kept, so resuming continues where the download stopped.
+    pub async fn pause(&mut self) -> Result<(), ContentManagerError> {
+        if let Some(current) = self.current.take() {
+            current.cancel();
+        }
+
+        info!("Pausing download queue");
+        self.queue.paused = true;
+        self.queue.save().await
+    }
+
+    /// Continues the current download, or starts the next one in the queue
+    pub async fn resume(&mut self) -> Result<(), ContentManagerError> {
+        info!("Resuming download queue");
+        self.queue.paused = false;
+
+        if self.current.is_none() {
+            match self.queue.current.clone() {
+                Some(game) => self.install_direct(game).await?,
+                None => self.start_next().await?,
+            }
+        }
+
+        self.queue.save().await
+    }
+
+    /// Lets the queue pick up a failed install again. Files that were already downloaded are
+    /// kept, so the download continues where it stopped.
+    pub async fn retry(&mut self, offer_id: &str) -> Result<(), ContentManagerError> {
+        let game = self
+            .queue
+            .queued
+            .iter_mut()
+            .find(|x| x.offer_id == offer_id && x.failure.is_some())
+            .ok_or_else(|| ContentManagerError::NotFailed(offer_id.to_owned()))?;
+
+        info!("Retrying the install of {}", game.slug);
+        game.failure = None;
+
+        self.start_next().await?;
+        self.queue.save().await
+    }
+
+    /// Takes `offer_id` out of the queue, cancelling its download if it's the current one.
+    /// Files that were already downloaded are left alone, use `uninstall` to delete them.
+    pub async fn remove(&mut self, offer_id: &str) -> Result<(), ContentManagerError> {
+        if self
+            .queue
+            .current
+            .as_ref()
+            .is_some_and(|x| x.offer_id == offer_id)
+        {
+            if let Some(current) = self.current.take() {
+                current.cancel();
+            }
+
+            self.queue.current = None;
+            self.start_next().await?;
+        } else {
+            let len = self.queue.queued.len();
+            self.queue.queued.retain(|x| x.offer_id != offer_id);
+            if self.queue.queued.len() == len {
+                return Err(ContentManagerError::NotQueued(offer_id.to_owned()));
+            }
+        }
+
+        self.queue.save().await
+    }
+
+    /// Moves a queued game to `index`, 0 being the next game to be installed. Indices past
+    /// the end move the game to the back. The current download isn't affected.
+    pub async fn move_to(
+        &mut self,
+        offer_id: &str,
+        index: usize,
+    ) -> Result<(), ContentManagerError> {
+        let position = self
+            .queue
+            .queued
+            .iter()
+            .position(|x| x.offer_id == offer_id)
+            .ok_or_else(|| ContentManagerError::NotQueued(offer_id.to_owned()))?;
+
+        let game = self.queue.queued.remove(position);
+        let index = index.min(self.queue.queued.len());
+        self.queue.queued.insert(index, game);
+        self.queue.save().await
+    }
+
+    /// Forgets about the games that finished installing
+    pub async fn clear_completed(&mut self) -> Result<(), ContentManagerError> {
+        self.queue.completed.clear();
+        self.queue.save().await
+    }
+
+    /// Restarts the saved queue. Files that were partially written by the interrupted
+    /// download are picked up where they were left off.
+    async fn restore_queue(&mut self) -> Result<MaximaEvent, ContentManagerError> {
+        match self.queue.current.clone() {
+            Some(game) => {
+                info!("Resuming download of {}", game.slug);
                 self.install_direct(game).await?;
-                return Ok(());
             }
+            None => self.start_next().await?,
+        }
+
+        Ok(MaximaEvent::DownloadQueueRestored {
+            current: self.queue.current.as_ref().map(|x| x.offer_id.clone()),
+            queued: self
+                .queue
+                .queued
+                .iter()
+                .map(|x| x.offer_id.clone())
+                .collect(),
+        })
+    }
 
-            self.queue.queued.push(current.clone());
+    /// Starts the next game in the queue, unless the queue is paused or busy
+    async fn start_next(&mut self) -> Result<(), ContentManagerError> {
+        if self.queue.paused || self.current.is_some() || self.queue.current.is_some() {
+            return Ok(());
+        }
+
+        if let Some(game) = self.queue.pop_next() {
+            self.install_direct(game).await?;
         }
 
-        self.install_direct(game).await?;
         Ok(())
     }
 
@@ -382,29 +1363,646 @@ impl ContentManager {
         self.queue.current = Some(game.clone());
         self.queue.save().await?;
 
-        let downloader = GameDownloader::new(&self.service, &game).await?;
+        let downloader = GameDownloader::new(
+            &self.service,
+            &game,
+            self.rate_limiter.clone(),
+            self.preallocate,
+            self.concurrency,
+        );
         downloader.download();
         self.current = Some(downloader);
+        self.throughput = ThroughputMeter::default();
+        self.last_progress = None;
         Ok(())
     }
 
-    pub(crate) async fn update(&mut self) -> Result<Option<MaximaEvent>, ContentManagerError> {
-        let mut event = None;
+    /// Works out how much `game` downloads and how much space it takes up, without
+    /// downloading anything. Updates only count the files that changed.
+    pub async fn install_size(
+        &self,
+        game: &QueuedGame,
+    ) -> Result<InstallSize, ContentManagerError> {
+        let plan = GameDownloader::plan(
+            &self.service,
+            game,
+            self.rate_limiter.clone(),
+            self.preallocate,
+            self.concurrency,
+        )
+        .await?;
+        Ok(plan.size)
+    }
 
-        if let Some(current) = &self.current {
-            if current.is_done() {
-                event = Some(MaximaEvent::InstallFinished(current.offer_id.to_owned()));
+    /// Checks the installed files of `game` against the central directory of its build.
+    /// When `repair` is set, missing or corrupt files are downloaded again.
+    pub async fn verify(
+        &self,
+        game: &QueuedGame,
+        repair: bool,
+    ) -> Result<Vec<FileVerifyResult>, ContentManagerError> {
+        let source = game.archive_source(&self.service).await?;
+        let downloader = ZipDownloader::with_source(&game.offer_id, source, &game.path).await?;
+
+        let exclusion_list = get_exclusion_list(game.slug.as_str());
+        let build_manifest = GameDownloader::build_manifest(&downloader, game).await;
+        let language_list = GameDownloader::language_exclusions(build_manifest.as_ref(), game)?;
+        let entries: Vec<ZipFileEntry> = downloader
+            .manifest()
+            .entries()
+            .iter()
+            .filter(|x| !exclusion_list.is_match(x.name()) && !language_list.is_match(x.name()))
+            .filter(|x| !(game.is_dlc() && x.name().eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH)))
+            .cloned()
+            .collect();
+
+        info!("Verifying {} files of {}", entries.len(), game.slug);
+        let mut results = verify::verify_entries(&game.path, &entries).await;
+
+        if !repair {
+            return Ok(results);
+        }
+
+        for result in results.iter_mut().filter(|x| !x.status.is_ok()) {
+            let entry = match entries.iter().find(|x| x.name() == &result.name) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            info!("Repairing {} ({:?})", result.name, result.status);
+
+            // The downloader considers any file with the right size complete, so get rid of it first
+            let path = game.path.join(&result.name);
+            if path.exists() {
+                fs::remove_file(&path).await?;
+            }
+
+            if let Err(err) = downloader.download_single_file(entry, None).await {
+                result.status = FileVerifyStatus::RepairFailed(err.to_string());
+                continue;
+            }
+
+            result.status = match verify::verify_entry(&game.path, entry).await {
+                FileVerifyStatus::Ok => FileVerifyStatus::Repaired,
+                status => FileVerifyStatus::RepairFailed(format!("{:?}", status)),
+            };
+        }
+
+        Ok(results)
+    }
+
+    /// Compares the installed version of `offer` with its live build
+    pub async fn check_for_update(
+        &self,
+        offer: &OwnedOffer,
+    ) -> Result<Option<AvailableUpdate>, ContentManagerError> {
+        if !offer.is_installed().await {
+            return Ok(None);
+        }
+
+        let installed_version = match offer.installed_version().await {
+            Ok(version) => version,
+            Err(err) => {
+                debug!("Can't check {} for updates: {}", offer.slug(), err);
+                return Ok(None);
+            }
+        };
+
+        let builds = self.service.available_builds(offer.offer_id()).await?;
+        let live = match builds.live_build() {
+            Some(build) => build,
+            None => return Ok(None),
+        };
+
+        let install_info = load_game_info_from_json(offer.slug()).ok();
+        if install_info.as_ref().is_some_and(|x| x.incomplete) {
+            debug!("{} is still being installed", offer.slug());
+            return Ok(None);
+        }
+
+        let installed_build = install_info
+            .as_ref()
+            .filter(|x| x.offer_id.as_ref() == Some(offer.offer_id()))
+            .and_then(|x| x.build_id());
+
+        let flags = match offer.local_manifest().await {
+            Ok(Some(manifest)) => manifest.feature_flags(),
+            _ => None,
+        };
+
+        Ok(AvailableUpdate::new(
+            offer,
+            installed_version,
+            installed_build,
+            live,
+            flags,
+        ))
+    }
+
+    /// Adds the update to the download queue, unless it's already there
+    pub async fn queue_update(
+        &mut self,
+        update: &AvailableUpdate,
+    ) -> Result<(), ContentManagerError> {
+        let already_queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.offer_id == update.offer_id && x.build_id == update.build_id);
+        if already_queued {
+            return Ok(());
+        }
+
+        let install_info = load_game_info_from_json(&update.slug)
+            .map_err(|_| ContentManagerError::NotInstalled(update.slug.clone()))?;
+
+        info!(
+            "Queueing update of {} from {} to {}",
+            update.slug, update.installed_version, update.available_version
+        );
+
+        let game = QueuedGameBuilder::default()
+            .offer_id(update.offer_id.clone())
+            .build_id(update.build_id.clone())
+            .path(install_info.path())
+            .slug(update.slug.clone())
+            .wine_prefix(install_info.wine_prefix())
+            .languages(install_info.languages.clone())
+            .build()?;
+
+        self.add_install(game).await
+    }
+
+    /// Add-ons of `base` in the store. Which ones are owned is part of each offer.
+    pub async fn available_dlc(
+        &self,
+        base: &OwnedOffer,
+    ) -> Result<Vec<ServiceAddonOffer>, ContentManagerError> {
+        Ok(self
+            .service
+            .addons(base.offer().primary_master_title_id())
+            .await?)
+    }
+
+    /// Queues the live build of `dlc_offer_id`, an add-on of `base`, to be installed into the
+    /// directory of the base game. It's installed in the same languages as the game.
+    pub async fn install_dlc(
+        &mut self,
+        base: &OwnedOffer,
+        dlc_offer_id: &str,
+    ) -> Result<(), ContentManagerError> {
+        let slug = base.slug();
+        let install_info = load_game_info_from_json(slug)
+            .map_err(|_| ContentManagerError::NotInstalled(slug.to_owned()))?;
+
+        let builds = self.service.available_builds(dlc_offer_id).await?;
+        let build = builds
+            .live_build()
+            .ok_or_else(|| ContentManagerError::NoLiveBuild(dlc_offer_id.to_owned()))?;
+
+        info!(
+            "Queueing {} build {} into {}",
+            dlc_offer_id,
+            build.build_id(),
+            install_info.path.display()
+        );
+
+        let game = QueuedGameBuilder::default()
+            .offer_id(dlc_offer_id.to_owned())
+            .build_id(build.build_id().to_owned())
+            .path(install_info.path())
+            .slug(slug.to_owned())
+            .wine_prefix(install_info.wine_prefix())
+            .languages(install_info.languages.clone())
+            .base_offer_id(Some(base.offer_id().to_owned()))
+            .build()?;
+
+        self.add_install(game).await
+    }
+
+    /// Points the install check of `offer` at its install, so EA Desktop finds it. Does
+    /// nothing when no EA Desktop registry is set or the offer has no install check.
+    pub async fn register_with_ea_desktop(
+        &self,
+        offer: &OwnedOffer,
+    ) -> Result<(), ContentManagerError> {
+        let Some(registry) = &self.ea_desktop else {
+            return Ok(());
+        };
+        let Some(check) = offer.offer().install_check_override() else {
+            return Ok(());
+        };
+
+        let check = InstallCheck::parse(check)?;
+        let install_info = load_game_info_from_json(offer.slug())
+            .map_err(|_| ContentManagerError::NotInstalled(offer.slug().to_owned()))?;
+
+        let state = InstallerState::new(
+            install_info
+                .offer_id
+                .clone()
+                .unwrap_or_else(|| offer.offer_id().to_owned()),
+            install_info.build_id.clone(),
+            !install_info.incomplete,
+        );
+        eadesktop::register_install(registry, &check, &install_info.path, &state)?;
+
+        #[cfg(unix)]
+        crate::unix::wine::invalidate_mx_wine_registry().await;
+        Ok(())
+    }
+
+    /// Takes over the install EA Desktop has of `offer`. Finished installs are only recorded,
+    /// with the build from `__Installer` or the one matching the installed version. Partial
+    /// ones are verified against the live build and the missing or corrupt files are downloaded
+    /// into the same directory, keeping what EA Desktop already downloaded.
+    pub async fn adopt_ea_desktop_install(
+        &mut self,
+        offer: &OwnedOffer,
+        wine_prefix: Option<PathBuf>,
+    ) -> Result<EaInstallState, ContentManagerError> {
+        let registry = self
+            .ea_desktop
+            .as_ref()
+            .ok_or(ContentManagerError::NoEaDesktopRegistry)?;
+        let check = offer
+            .offer()
+            .install_check_override()
+            .as_ref()
+            .ok_or_else(|| ContentManagerError::NoInstallCheck(offer.slug().to_owned()))?;
+
+        let state = eadesktop::install_state(registry, &InstallCheck::parse(check)?)?;
+        match &state {
+            EaInstallState::NotInstalled => {}
+            EaInstallState::Installed(path) => {
+                info!("Adopting {} installed in {}", offer.slug(), path.display());
+
+                let state = match InstallerState::read(path)? {
+                    Some(state) if state.offer_id() == offer.offer_id() => state,
+                    _ => InstallerState::new(
+                        offer.offer_id().to_owned(),
+                        self.installed_build_id(offer.offer_id(), path).await,
+                        true,
+                    ),
+                };
+
+                state
+                    .install_info(path.clone(), wine_prefix)
+                    .save_to_json(offer.slug());
+            }
+            EaInstallState::Partial(path) => {
+                let builds = self.service.available_builds(offer.offer_id()).await?;
+                let build = builds
+                    .live_build()
+                    .ok_or_else(|| ContentManagerError::NoLiveBuild(offer.offer_id().to_owned()))?;
+
+                info!(
+                    "Finishing download of {} build {} in {}",
+                    offer.slug(),
+                    build.build_id(),
+                    path.display()
+                );
+
+                let game = QueuedGameBuilder::default()
+                    .offer_id(offer.offer_id().to_owned())
+                    .build_id(build.build_id().to_owned())
+                    .path(path.clone())
+                    .slug(offer.slug().to_owned())
+                    .wine_prefix(wine_prefix)
+                    .build()?;
+
+                // EA Desktop preallocates files, so a file with the right size can still be
+                // half empty. Everything is hashed instead of trusting the sizes like a resume does.
+                let results = self.verify(&game, true).await?;
+                let failed = results.iter().filter(|x| !x.status.is_ok()).count();
+                if failed > 0 {
+                    return Err(ContentManagerError::RepairFailed(
+                        offer.slug().to_owned(),
+                        failed,
+                    ));
+                }
+
+                GameDownloader::save_install_info(&game, false);
+                self.register_with_ea_desktop(offer).await?;
+
+                #[cfg(windows)]
+                if path.join(MANIFEST_RELATIVE_PATH).exists() {
+                    let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH))
+                        .await
+                        .map_err(DownloaderError::from)?;
+                    manifest
+                        .run_touchup(path, offer.slug(), game.locale())
+                        .await
+                        .map_err(DownloaderError::from)?;
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Build of the install in `path`, looked up by the version in its manifest. EA Desktop
+    /// doesn't record the build for installs that predate `__Installer` having a state.
+    async fn installed_build_id(&self, offer_id: &str, path: &Path) -> Option<String> {
+        let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH))
+            .await
+            .ok()?;
+        let version = manifest.version()?;
+
+        let builds = match self.service.available_builds(offer_id).await {
+            Ok(builds) => builds,
+            Err(err) => {
+                warn!("Failed to look up the build of {}: {}", offer_id, err);
+                return None;
+            }
+        };
+
+        let build = builds.build(&version);
+        if build.is_none() {
+            debug!("{} has no build with version {}", offer_id, version);
+        }
+
+        build.map(|x| x.build_id().to_owned())
+    }
+
+    /// Switches an installed game to `languages`. The installed build is queued again, which
+    /// fetches the files of new languages, removes the ones of dropped languages and runs
+    /// touchup with the new locale.
+    pub async fn change_language(
+        &mut self,
+        slug: &str,
+        languages: Vec<String>,
+    ) -> Result<(), ContentManagerError> {
+        let install_info = load_game_info_from_json(slug)
+            .map_err(|_| ContentManagerError::NotInstalled(slug.to_owned()))?;
+
+        let (offer_id, build_id) = match (&install_info.offer_id, &install_info.build_id) {
+            (Some(offer_id), Some(build_id)) => (offer_id.clone(), build_id.clone()),
+            _ => return Err(ContentManagerError::UnknownBuild(slug.to_owned())),
+        };
+
+        if let Ok(manifest) = manifest::read(install_info.path.join(MANIFEST_RELATIVE_PATH)).await {
+            let supported = manifest
+                .feature_flags()
+                .map(|x| x.attr_languageChangeSupportEnabled)
+                .unwrap_or(true);
+            if !supported {
+                return Err(ContentManagerError::LanguageChangeUnsupported(
+                    slug.to_owned(),
+                ));
+            }
+        }
+
+        info!(
+            "Changing language of {} from {:?} to {:?}",
+            slug, install_info.languages, languages
+        );
+
+        let game = QueuedGameBuilder::default()
+            .offer_id(offer_id)
+            .build_id(build_id)
+            .path(install_info.path())
+            .slug(slug.to_owned())
+            .wine_prefix(install_info.wine_prefix())
+            .languages(languages)
+            .build()?;
+
+        self.add_install(game).await
+    }
+
+    /// Prepares moving an installed game to `destination`. Games that are queued or being
+    /// downloaded can't be moved, since the queue still points to the old location.
+    pub async fn install_mover(
+        &self,
+        slug: &str,
+        destination: PathBuf,
+    ) -> Result<InstallMover, ContentManagerError> {
+        let queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.slug == slug);
+
+        if queued {
+            return Err(ContentManagerError::DownloadInProgress);
+        }
+
+        Ok(InstallMover::new(slug, destination).await?)
+    }
+
+    /// Removes `offer` from the queue, and deletes its files. Only files that are part of the
+    /// installed build are deleted, unless the build can't be fetched, in which case the whole
+    /// install directory is removed if it's safe to do so.
+    pub async fn uninstall(
+        &mut self,
+        offer: &OwnedOffer,
+        options: UninstallOptions,
+    ) -> Result<(), ContentManagerError> {
+        let slug = offer.slug();
+        let offer_id = offer.offer_id();
+
+        let install_info = load_game_info_from_json(slug)
+            .map_err(|_| ContentManagerError::NotInstalled(slug.to_owned()))?;
+        let path = install_info.path();
+
+        // Add-ons being installed into the game go with it
+        if self
+            .queue
+            .current
+            .as_ref()
+            .is_some_and(|x| &x.offer_id == offer_id || &x.slug == slug)
+        {
+            if let Some(current) = self.current.take() {
+                current.cancel();
+            }
+            self.queue.current = None;
+        }
+
+        self.queue
+            .queued
+            .retain(|x| &x.offer_id != offer_id && &x.slug != slug);
+        self.queue.save().await?;
+
+        info!("Uninstalling {} from {}", slug, path.display());
+
+        let build_id = match install_info.build_id() {
+            Some(build_id) => Some(build_id.to_owned()),
+            None => match self.service.available_builds(offer_id).await {
+                Ok(builds) => builds.live_build().map(|x| x.build_id().to_owned()),
+                Err(err) => {
+                    warn!("Failed to look up the build of {}: {}", slug, err);
+                    None
+                }
+            },
+        };
+
+        let manifest = match build_id {
+            Some(build_id) => match self.service.download_url(offer_id, Some(&build_id)).await {
+                Ok(url) => ZipFile::fetch(url.url()).await.ok(),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        if path.exists() {
+            for dlc in &install_info.dlc {
+                self.remove_dlc_files(&path, dlc).await?;
+            }
+
+            if let Some(manifest) = manifest {
+                let names: Vec<String> = manifest
+                    .entries()
+                    .iter()
+                    .map(|x| x.name().to_owned())
+                    .collect();
+                uninstall::remove_files(&path, &names).await?;
+            } else if uninstall::is_safe_to_remove(&path)? {
+                warn!(
+                    "Couldn't fetch the file list of {}, removing the install directory",
+                    slug
+                );
+                fs::remove_dir_all(&path).await?;
+            } else {
+                return Err(ContentManagerError::UnsafeUninstall(path));
+            }
+        }
+
+        if options.remove_licenses {
+            let content_id = offer.offer().content_id();
+            let license_dir = crate::ooa::get_license_dir(Some(slug))?;
+            for name in [
+                format!("{}.dlf", content_id),
+                format!("{}_cached.dlf", content_id),
+            ] {
+                let license = license_dir.join(name);
+                if license.exists() {
+                    fs::remove_file(license).await?;
+                }
+            }
+        }
+
+        #[cfg(unix)]
+        if options.remove_wine_prefix {
+            if let Some(prefix) = install_info.wine_prefix() {
+                if prefix.exists() {
+                    if !uninstall::is_safe_to_remove_prefix(&prefix)? {
+                        return Err(ContentManagerError::UnsafeUninstall(prefix));
+                    }
+
+                    info!("Removing wine prefix {}", prefix.display());
+                    fs::remove_dir_all(prefix).await?;
+                }
+            }
+        }
+
+        let downloader_state = maxima_dir()?.join("temp/downloader").join(offer_id);
+        if downloader_state.exists() {
+            fs::remove_dir_all(downloader_state).await?;
+        }
+
+        let game_info = maxima_dir()?
+            .join("gameinfo")
+            .join(format!("{}.json", slug));
+        if game_info.exists() {
+            fs::remove_file(game_info).await?;
+        }
+
+        info!("Uninstalled {}", slug);
+        Ok(())
+    }
+
+    /// Deletes the files of an add-on installed into `path`. Add-ons whose build can't be
+    /// fetched are left for the removal of the install directory.
+    async fn remove_dlc_files(
+        &self,
+        path: &Path,
+        dlc: &InstalledDlc,
+    ) -> Result<(), ContentManagerError> {
+        let manifest = match self
+            .service
+            .download_url(&dlc.offer_id, Some(&dlc.build_id))
+            .await
+        {
+            Ok(url) => ZipFile::fetch(url.url()).await.ok(),
+            Err(_) => None,
+        };
+
+        if let Some(manifest) = manifest {
+            info!("Removing add-on {}", dlc.offer_id);
+            let names: Vec<String> = manifest
+                .entries()
+                .iter()
+                .filter(|x| !x.name().eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH))
+                .map(|x| x.name().to_owned())
+                .collect();
+            uninstall::remove_files(path, &names).await?;
+        } else {
+            warn!("Couldn't fetch the file list of add-on {}", dlc.offer_id);
+        }
+
+        let downloader_state = maxima_dir()?.join("temp/downloader").join(&dlc.offer_id);
+        if downloader_state.exists() {
+            fs::remove_dir_all(downloader_state).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn update(&mut self) -> Result<Vec<MaximaEvent>, ContentManagerError> {
+        let mut events = Vec::new();
+
+        if self.resume_pending && self.auth.lock().await.current().is_some() {
+            self.resume_pending = false;
+            events.push(self.restore_queue().await?);
+        }
+
+        let Some(current) = &self.current else {
+            return Ok(events);
+        };
+
+        let result = current.result();
+        let due = !matches!(self.last_progress, Some(last) if last.elapsed() < PROGRESS_INTERVAL);
+        if due || result.is_some() {
+            events.push(MaximaEvent::DownloadProgress(
+                current.progress(&mut self.throughput),
+            ));
+            self.last_progress = Some(Instant::now());
+        }
+
+        let offer_id = current.offer_id().to_owned();
+        if current.check_playable() {
+            events.push(MaximaEvent::InstallPlayable(offer_id.clone()));
+        }
+
+        match result {
+            None => {}
+            Some(DownloadResult::Finished) => {
+                events.push(MaximaEvent::InstallFinished(offer_id));
                 self.current = None;
-                self.queue.current = None;
+                if let Some(game) = self.queue.current.take() {
+                    self.queue.push_completed(game);
+                }
 
-                if let Some(game) = self.queue.queued.pop() {
-                    self.install_now(game).await?;
+                self.start_next().await?;
+                self.queue.save().await?;
+            }
+            Some(DownloadResult::Failed(reason)) => {
+                // Kept at the back of the queue, the rest of it goes on until this is retried
+                self.current = None;
+                if let Some(mut game) = self.queue.current.take() {
+                    game.failure = Some(reason.to_string());
+                    self.queue.queued.push(game);
                 }
 
+                events.push(MaximaEvent::InstallFailed { offer_id, reason });
+
+                self.start_next().await?;
                 self.queue.save().await?;
             }
         }
 
-        Ok(event)
+        Ok(events)
     }
 }
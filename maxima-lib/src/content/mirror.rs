@@ -0,0 +1,307 @@
+use std::{
+    cmp::Reverse,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use tokio::time::Sleep;
+use url::Url;
+
+use crate::content::source::{
+    ByteSource, ByteStream, HttpSource, SourceError, UrlRefresher, DOWNLOAD_CLIENT, STALL_TIMEOUT,
+};
+
+/// Failures in a row after which a mirror is only used when all others fail too
+const FAILOVER_ERRORS: u32 = 2;
+/// How much a finished range counts towards the measured throughput of its mirror
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+
+#[derive(Default)]
+struct MirrorStats {
+    /// Bytes per second, smoothed over the ranges read from the mirror
+    throughput: f64,
+    errors: u64,
+    consecutive_errors: u32,
+}
+
+/// One of the places a build archive can be downloaded from
+pub struct Mirror {
+    name: String,
+    source: Arc<dyn ByteSource>,
+    stats: Mutex<MirrorStats>,
+}
+
+impl Mirror {
+    pub fn new(name: &str, source: Arc<dyn ByteSource>) -> Self {
+        Self {
+            name: name.to_owned(),
+            source,
+            stats: Mutex::new(MirrorStats::default()),
+        }
+    }
+
+    fn record_success(&self, bytes: u64, elapsed: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.consecutive_errors = 0;
+
+        if bytes == 0 || elapsed.is_zero() {
+            return;
+        }
+
+        let throughput = bytes as f64 / elapsed.as_secs_f64();
+        stats.throughput = if stats.throughput == 0.0 {
+            throughput
+        } else {
+            THROUGHPUT_SMOOTHING * throughput + (1.0 - THROUGHPUT_SMOOTHING) * stats.throughput
+        };
+    }
+
+    fn record_error(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.errors += 1;
+        stats.consecutive_errors += 1;
+    }
+}
+
+/// Reads an archive from whichever of its mirrors works best. Requests stick to one mirror
+/// until it keeps failing or stalls, then move on to the healthiest of the others.
+pub struct MirrorSource {
+    mirrors: Vec<Arc<Mirror>>,
+    active: AtomicUsize,
+}
+
+impl MirrorSource {
+    /// The first mirror is used until there's a reason not to
+    pub fn new(mirrors: Vec<Mirror>) -> Self {
+        Self {
+            mirrors: mirrors.into_iter().map(Arc::new).collect(),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Indices of the mirrors in the order they should be tried
+    fn order(&self) -> Vec<usize> {
+        let active = self.active.load(Ordering::SeqCst);
+
+        let mut order: Vec<usize> = (0..self.mirrors.len()).collect();
+        order.sort_by_key(|idx| {
+            let stats = self.mirrors[*idx].stats.lock().unwrap();
+            (
+                stats.consecutive_errors >= FAILOVER_ERRORS,
+                *idx != active,
+                stats.consecutive_errors,
+                Reverse(stats.throughput as u64),
+            )
+        });
+
+        order
+    }
+
+    fn set_active(&self, idx: usize) {
+        let previous = self.active.swap(idx, Ordering::SeqCst);
+        if previous != idx {
+            let from = &self.mirrors[previous];
+            info!(
+                "Switching from mirror {} ({} errors so far) to {}",
+                from.name,
+                from.stats.lock().unwrap().errors,
+                self.mirrors[idx].name
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl ByteSource for MirrorSource {
+    async fn len(&self) -> Result<u64, SourceError> {
+        let mut last_error = SourceError::NoMirrors;
+        for idx in self.order() {
+            let mirror = &self.mirrors[idx];
+            match mirror.source.len().await {
+                Ok(len) => {
+                    self.set_active(idx);
+                    return Ok(len);
+                }
+                Err(err) => {
+                    warn!("Mirror {} failed: {}", mirror.name, err);
+                    mirror.record_error();
+                    last_error = err;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn read_range(&self, start: u64, end: u64) -> Result<ByteStream, SourceError> {
+        let mut last_error = SourceError::NoMirrors;
+        for idx in self.order() {
+            let mirror = &self.mirrors[idx];
+            match mirror.source.read_range(start, end).await {
+                Ok(stream) => {
+                    self.set_active(idx);
+                    return Ok(Box::pin(MonitoredStream::new(stream, mirror.clone())));
+                }
+                Err(err) => {
+                    warn!("Mirror {} failed: {}", mirror.name, err);
+                    mirror.record_error();
+                    last_error = err;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn disk_starts(&self) -> Result<Vec<u64>, SourceError> {
+        let active = self.active.load(Ordering::SeqCst);
+        self.mirrors[active].source.disk_starts().await
+    }
+
+    fn mirror(&self) -> Option<String> {
+        let active = self.active.load(Ordering::SeqCst);
+        Some(self.mirrors[active].name.clone())
+    }
+}
+
+/// Keeps track of how a mirror is doing while a range is read from it, ending the stream
+/// with an error if the mirror stops sending data
+struct MonitoredStream {
+    inner: ByteStream,
+    mirror: Arc<Mirror>,
+    stall: Pin<Box<Sleep>>,
+    started: Instant,
+    bytes: u64,
+    done: bool,
+}
+
+impl MonitoredStream {
+    fn new(inner: ByteStream, mirror: Arc<Mirror>) -> Self {
+        Self {
+            inner,
+            mirror,
+            stall: Box::pin(tokio::time::sleep(STALL_TIMEOUT)),
+            started: Instant::now(),
+            bytes: 0,
+            done: false,
+        }
+    }
+}
+
+impl Stream for MonitoredStream {
+    type Item = Result<bytes::Bytes, io::Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        if self.done {
+            return task::Poll::Ready(None);
+        }
+
+        match self.inner.poll_next_unpin(cx) {
+            task::Poll::Ready(Some(Ok(chunk))) => {
+                self.bytes += chunk.len() as u64;
+                let deadline = tokio::time::Instant::now() + STALL_TIMEOUT;
+                self.stall.as_mut().reset(deadline);
+                task::Poll::Ready(Some(Ok(chunk)))
+            }
+            task::Poll::Ready(Some(Err(err))) => {
+                self.done = true;
+                self.mirror.record_error();
+                task::Poll::Ready(Some(Err(err)))
+            }
+            task::Poll::Ready(None) => {
+                self.done = true;
+                self.mirror
+                    .record_success(self.bytes, self.started.elapsed());
+                task::Poll::Ready(None)
+            }
+            task::Poll::Pending => {
+                if self.stall.as_mut().poll(cx).is_pending() {
+                    return task::Poll::Pending;
+                }
+
+                warn!("Mirror {} stalled", self.mirror.name);
+                self.done = true;
+                self.mirror.record_error();
+                task::Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("mirror {} stalled", self.mirror.name),
+                ))))
+            }
+        }
+    }
+}
+
+/// Moves `url` over to the host of `base`, keeping its path and query. A path in `base` is
+/// put in front of the one from `url`.
+fn rebase_url(url: &Url, base: &Url) -> Url {
+    let mut rebased = base.clone();
+    let prefix = base.path().trim_end_matches('/');
+    rebased.set_path(&format!("{}{}", prefix, url.path()));
+    rebased.set_query(url.query());
+    rebased
+}
+
+fn mirror_name(url: &Url) -> String {
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => url.to_string(),
+    }
+}
+
+/// Gets a new URL for a mirror by refreshing the original and moving it over to the mirror
+struct RebasedRefresher {
+    inner: Arc<dyn UrlRefresher>,
+    base: Url,
+}
+
+#[async_trait]
+impl UrlRefresher for RebasedRefresher {
+    async fn refresh(&self) -> Result<String, SourceError> {
+        let url = Url::parse(&self.inner.refresh().await?)?;
+        Ok(rebase_url(&url, &self.base).to_string())
+    }
+}
+
+/// Reads a build from the CDN `url` came from, as well as from each of the `mirrors`. These
+/// are base URLs like `http://lancache.local`, which serve the same paths as the CDN.
+pub fn cdn_source(
+    url: &str,
+    mirrors: &[String],
+    refresher: Arc<dyn UrlRefresher>,
+) -> Result<Arc<dyn ByteSource>, SourceError> {
+    let primary = Url::parse(url)?;
+
+    let mut candidates = Vec::with_capacity(mirrors.len() + 1);
+    for base in mirrors {
+        let base = Url::parse(base)?;
+        let source = HttpSource::new(
+            DOWNLOAD_CLIENT.clone(),
+            rebase_url(&primary, &base).as_str(),
+        )
+        .with_refresher(Arc::new(RebasedRefresher {
+            inner: refresher.clone(),
+            base: base.clone(),
+        }));
+        candidates.push(Mirror::new(&mirror_name(&base), Arc::new(source)));
+    }
+
+    // Configured mirrors are usually closer, the CDN is what's left when they don't work
+    let source = HttpSource::new(DOWNLOAD_CLIENT.clone(), url).with_refresher(refresher);
+    candidates.push(Mirror::new(&mirror_name(&primary), Arc::new(source)));
+
+    Ok(Arc::new(MirrorSource::new(candidates)))
+}
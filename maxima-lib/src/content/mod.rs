@@ -1,25 +1,50 @@
 use std::time::Duration;
 
+use async_trait::async_trait;
+use log::info;
+
+use crate::content::source::{SourceError, UrlRefresher};
 use crate::core::{
     auth::storage::LockedAuthStorage,
     cache::DynamicCache,
+    locale::Locale,
     service_layer::{
+        ServiceAddonOffer, ServiceAddonSearchRequestBuilder, ServiceAddonSearchResultRoot,
         ServiceAvailableBuild, ServiceAvailableBuilds, ServiceAvailableBuildsBuilder,
         ServiceAvailableBuildsRequestBuilder, ServiceDownloadUrlMetadata,
-        ServiceDownloadUrlRequestBuilder, ServiceLayerClient, ServiceLayerError,
-        SERVICE_REQUEST_AVAILABLEBUILDS, SERVICE_REQUEST_DOWNLOADURL,
+        ServiceDownloadUrlRequestBuilder, ServiceGetLegacyCatalogDefsRequestBuilder,
+        ServiceLayerClient, ServiceLayerError, ServiceLegacyDownloadMetadata, ServiceLegacyOffer,
+        SERVICE_REQUEST_ADDONSEARCH, SERVICE_REQUEST_AVAILABLEBUILDS, SERVICE_REQUEST_DOWNLOADURL,
+        SERVICE_REQUEST_GETLEGACYCATALOGDEFS,
     },
 };
 
+pub mod diff;
 pub mod downloader;
+pub mod eadesktop;
 pub mod exclusion;
+pub mod limiter;
 pub mod manager;
+pub mod mirror;
+pub mod mover;
+pub mod peer;
+pub mod progress;
+pub mod progressive;
+pub mod source;
+pub mod uninstall;
+pub mod update;
+pub mod verify;
 pub mod zip;
 pub mod zlib;
 
+#[derive(Clone)]
 pub struct ContentService {
     service_layer: ServiceLayerClient,
     request_cache: DynamicCache<String>,
+    /// Base URLs serving the same builds as the CDN, tried before it
+    mirrors: Vec<String>,
+    /// Base URLs of other machines serving their installs, see `peer::PeerServer`
+    peers: Vec<String>,
 }
 
 impl ContentService {
@@ -33,9 +58,29 @@ impl ContentService {
         Self {
             service_layer: ServiceLayerClient::new(auth),
             request_cache,
+            mirrors: Vec::new(),
+            peers: Vec::new(),
         }
     }
 
+    pub fn mirrors(&self) -> &Vec<String> {
+        &self.mirrors
+    }
+
+    pub fn set_mirrors(&mut self, mirrors: Vec<String>) {
+        self.mirrors = mirrors;
+    }
+
+    pub fn peers(&self) -> &Vec<String> {
+        &self.peers
+    }
+
+    pub fn set_peers(&mut self, peers: Vec<String>) {
+        self.peers = peers;
+    }
+
+    /// Builds of pre-DiP games aren't listed by the service, those are taken from the legacy
+    /// download metadata of the offer instead
     pub async fn available_builds(
         &self,
         offer_id: &str,
@@ -55,6 +100,13 @@ impl ContentService {
             )
             .await?;
 
+        let builds = if builds.is_empty() {
+            let downloads = self.legacy_downloads(offer_id).await?;
+            downloads.iter().map(ServiceAvailableBuild::from).collect()
+        } else {
+            builds
+        };
+
         let builds = ServiceAvailableBuildsBuilder::default()
             .builds(builds)
             .build()?;
@@ -62,16 +114,97 @@ impl ContentService {
         Ok(builds)
     }
 
+    /// Download metadata of an offer from before Download-In-Place
+    pub async fn legacy_downloads(
+        &self,
+        offer_id: &str,
+    ) -> Result<Vec<ServiceLegacyDownloadMetadata>, ServiceLayerError> {
+        let cache_key = "legacy_downloads_".to_owned() + offer_id;
+        if let Some(cached) = self.request_cache.get(&cache_key) {
+            return Ok(cached);
+        }
+
+        let offers: Vec<ServiceLegacyOffer> = self
+            .service_layer
+            .request(
+                SERVICE_REQUEST_GETLEGACYCATALOGDEFS,
+                ServiceGetLegacyCatalogDefsRequestBuilder::default()
+                    .offer_ids(vec![offer_id.to_owned()])
+                    .locale(Locale::EnUs)
+                    .build()?,
+            )
+            .await?;
+
+        let downloads = offers
+            .into_iter()
+            .find(|x| x.offer_id() == offer_id)
+            .map(|x| x.downloads().clone())
+            .unwrap_or_default();
+
+        self.request_cache.insert(cache_key, downloads.clone());
+        Ok(downloads)
+    }
+
+    /// Picks the legacy download of `build_id`, which is its release version, or the live one
+    /// when there's no such build
+    pub async fn legacy_download(
+        &self,
+        offer_id: &str,
+        build_id: &str,
+    ) -> Result<ServiceLegacyDownloadMetadata, ServiceLayerError> {
+        let downloads = self.legacy_downloads(offer_id).await?;
+        downloads
+            .iter()
+            .find(|x| x.build_release_version() == build_id)
+            .or_else(|| downloads.iter().find(|x| x.download_type() == "LIVE"))
+            .cloned()
+            .ok_or(ServiceLayerError::MissingField)
+    }
+
+    /// Add-ons of the title with `master_title_id`, owned or not. Not cached, since buying
+    /// one changes the result.
+    pub async fn addons(
+        &self,
+        master_title_id: &str,
+    ) -> Result<Vec<ServiceAddonOffer>, ServiceLayerError> {
+        let result: ServiceAddonSearchResultRoot = self
+            .service_layer
+            .request(
+                SERVICE_REQUEST_ADDONSEARCH,
+                ServiceAddonSearchRequestBuilder::default()
+                    .platform(String::new())
+                    .category_id(String::new())
+                    .master_title_id(master_title_id.to_owned())
+                    .offer_ids(Vec::new())
+                    .build()?,
+            )
+            .await?;
+
+        Ok(result.addonSearch().addonOffers().clone())
+    }
+
     pub async fn download_url(
         &self,
         offer_id: &str,
         build_id: Option<&str>,
     ) -> Result<ServiceDownloadUrlMetadata, ServiceLayerError> {
-        let cache_key = "download_url_".to_owned() + offer_id + "_" + build_id.unwrap_or("live");
+        let cache_key = Self::download_url_cache_key(offer_id, build_id);
         if let Some(cached) = self.request_cache.get(&cache_key) {
             return Ok(cached);
         }
 
+        self.refresh_download_url(offer_id, build_id).await
+    }
+
+    /// Requests a new download URL, skipping and then replacing the cached one. The URLs are
+    /// signed and expire, so downloads outliving them need a fresh one to continue.
+    pub async fn refresh_download_url(
+        &self,
+        offer_id: &str,
+        build_id: Option<&str>,
+    ) -> Result<ServiceDownloadUrlMetadata, ServiceLayerError> {
+        let cache_key = Self::download_url_cache_key(offer_id, build_id);
+
         let url: ServiceDownloadUrlMetadata = self
             .service_layer
             .request(
@@ -86,4 +219,39 @@ impl ContentService {
         self.request_cache.insert(cache_key, url.clone());
         Ok(url)
     }
+
+    fn download_url_cache_key(offer_id: &str, build_id: Option<&str>) -> String {
+        "download_url_".to_owned() + offer_id + "_" + build_id.unwrap_or("live")
+    }
+}
+
+/// Gets a new download URL for a build from the service layer once the old one expires
+pub struct DownloadUrlRefresher {
+    service: ContentService,
+    offer_id: String,
+    build_id: String,
+}
+
+impl DownloadUrlRefresher {
+    pub fn new(service: ContentService, offer_id: &str, build_id: &str) -> Self {
+        Self {
+            service,
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl UrlRefresher for DownloadUrlRefresher {
+    async fn refresh(&self) -> Result<String, SourceError> {
+        info!("Refreshing download URL of {}", self.offer_id);
+        let url = self
+            .service
+            .refresh_download_url(&self.offer_id, Some(&self.build_id))
+            .await
+            .map_err(|err| SourceError::Refresh(err.to_string()))?;
+
+        Ok(url.url().to_owned())
+    }
 }
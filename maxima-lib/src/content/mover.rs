@@ -0,0 +1,299 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_recursion::async_recursion;
+use derive_getters::Getters;
+use log::{info, warn};
+use thiserror::Error;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    gameinfo::{load_game_info_from_json, GameInstallInfo, GameVersionError},
+    util::native::NativeError,
+};
+
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum MoveError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    GameVersion(#[from] GameVersionError),
+    #[error(transparent)]
+    Native(#[from] NativeError),
+
+    #[error("path `{0}` is not absolute")]
+    PathNotAbsolute(PathBuf),
+    #[error("`{0}` already exists and is not empty")]
+    DestinationNotEmpty(PathBuf),
+    #[error("can't move `{0}` into itself")]
+    DestinationInsideSource(PathBuf),
+    #[error("move was cancelled")]
+    Cancelled,
+}
+
+/// Relocates an installed game to another directory. The game is renamed when possible,
+/// and copied file by file when the destination is on a different filesystem. The
+/// original is only deleted once the copy is complete and the game info points to it.
+#[derive(Getters)]
+pub struct InstallMover {
+    slug: String,
+    source: PathBuf,
+    destination: PathBuf,
+    total_bytes: u64,
+
+    #[getter(skip)]
+    moved_bytes: Arc<AtomicU64>,
+    #[getter(skip)]
+    cancel_token: CancellationToken,
+}
+
+impl InstallMover {
+    pub async fn new(slug: &str, destination: PathBuf) -> Result<Self, MoveError> {
+        if !destination.is_absolute() {
+            return Err(MoveError::PathNotAbsolute(destination));
+        }
+
+        let source = load_game_info_from_json(slug)?.path();
+        if destination.starts_with(&source) {
+            return Err(MoveError::DestinationInsideSource(destination));
+        }
+
+        if destination.exists()
+            && fs::read_dir(&destination)
+                .await?
+                .next_entry()
+                .await?
+                .is_some()
+        {
+            return Err(MoveError::DestinationNotEmpty(destination));
+        }
+
+        let total_bytes = dir_size(&source).await?;
+
+        Ok(Self {
+            slug: slug.to_owned(),
+            source,
+            destination,
+            total_bytes,
+            moved_bytes: Arc::new(AtomicU64::new(0)),
+            cancel_token: CancellationToken::new(),
+        })
+    }
+
+    pub fn moved_bytes(&self) -> u64 {
+        self.moved_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn percentage_done(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+
+        (self.moved_bytes() as f64 / self.total_bytes as f64) * 100.0
+    }
+
+    /// Stops the move. Whatever was copied so far is deleted, the original is left alone.
+    pub fn cancel(&self) {
+        info!("Cancelling move of {}", self.slug);
+        self.cancel_token.cancel();
+    }
+
+    pub async fn run(&self) -> Result<GameInstallInfo, MoveError> {
+        info!(
+            "Moving {} from {} to {}",
+            self.slug,
+            self.source.display(),
+            self.destination.display()
+        );
+
+        let mut install_info = load_game_info_from_json(&self.slug)?;
+
+        if let Some(parent) = self.destination.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // An empty destination directory would make the rename fail on some platforms
+        if self.destination.exists() {
+            fs::remove_dir(&self.destination).await?;
+        }
+
+        let renamed = match fs::rename(&self.source, &self.destination).await {
+            Ok(()) => true,
+            Err(err) => {
+                info!("Couldn't rename install ({}), copying instead", err);
+                false
+            }
+        };
+
+        if renamed {
+            self.moved_bytes.store(self.total_bytes, Ordering::SeqCst);
+        } else {
+            let result = copy_dir(
+                &self.source,
+                &self.destination,
+                &self.moved_bytes,
+                &self.cancel_token,
+            )
+            .await;
+
+            let result = result.and_then(|_| {
+                if self.cancel_token.is_cancelled() {
+                    Err(MoveError::Cancelled)
+                } else {
+                    Ok(())
+                }
+            });
+
+            if let Err(err) = result {
+                if let Err(err) = fs::remove_dir_all(&self.destination).await {
+                    warn!("Failed to clean up {}: {}", self.destination.display(), err);
+                }
+
+                return Err(err);
+            }
+        }
+
+        install_info.path = self.destination.clone();
+        if let Err(err) = install_info.try_save_to_json(&self.slug) {
+            // Put everything back where the game info says it is
+            if renamed {
+                fs::rename(&self.destination, &self.source).await?;
+            } else {
+                fs::remove_dir_all(&self.destination).await?;
+            }
+
+            return Err(err.into());
+        }
+
+        if !renamed {
+            if let Err(err) = fs::remove_dir_all(&self.source).await {
+                warn!("Failed to remove {}: {}", self.source.display(), err);
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use crate::unix::wine::{invalidate_mx_wine_registry, setup_wine_registry};
+
+            if let Err(err) = setup_wine_registry(Some(&self.slug)).await {
+                warn!("Failed to set up wine registry after moving: {}", err);
+            }
+
+            invalidate_mx_wine_registry().await;
+        }
+
+        info!("Moved {} to {}", self.slug, self.destination.display());
+        Ok(install_info)
+    }
+}
+
+#[async_recursion]
+async fn dir_size(path: &Path) -> Result<u64, MoveError> {
+    let mut size = 0;
+
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_type = entry.file_type().await?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path()).await?;
+        } else if file_type.is_file() {
+            size += entry.metadata().await?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[async_recursion]
+async fn copy_dir(
+    from: &Path,
+    to: &Path,
+    moved_bytes: &AtomicU64,
+    cancel_token: &CancellationToken,
+) -> Result<(), MoveError> {
+    fs::create_dir_all(to).await?;
+
+    let mut entries = fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if cancel_token.is_cancelled() {
+            return Err(MoveError::Cancelled);
+        }
+
+        let file_type = entry.file_type().await?;
+        let target = to.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target, moved_bytes, cancel_token).await?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target).await?;
+        } else {
+            copy_file(&entry.path(), &target, moved_bytes, cancel_token).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn copy_file(
+    from: &Path,
+    to: &Path,
+    moved_bytes: &AtomicU64,
+    cancel_token: &CancellationToken,
+) -> Result<(), MoveError> {
+    let mut reader = fs::File::open(from).await?;
+    let mut writer = fs::File::create(to).await?;
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+
+    loop {
+        if cancel_token.is_cancelled() {
+            return Err(MoveError::Cancelled);
+        }
+
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        writer.write_all(&buffer[..read]).await?;
+        moved_bytes.fetch_add(read as u64, Ordering::SeqCst);
+    }
+
+    writer.sync_all().await?;
+
+    let metadata = reader.metadata().await?;
+    fs::set_permissions(to, metadata.permissions()).await?;
+    if let Ok(modified) = metadata.modified() {
+        let _ = filetime::set_file_mtime(to, filetime::FileTime::from_system_time(modified));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn copy_symlink(from: &Path, to: &Path) -> Result<(), MoveError> {
+    let target = fs::read_link(from).await?;
+    fs::symlink(target, to).await?;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn copy_symlink(from: &Path, to: &Path) -> Result<(), MoveError> {
+    let target = fs::read_link(from).await?;
+    if fs::metadata(from).await?.is_dir() {
+        fs::symlink_dir(target, to).await?;
+    } else {
+        fs::symlink_file(target, to).await?;
+    }
+    Ok(())
+}
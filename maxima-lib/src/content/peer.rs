@@ -0,0 +1,549 @@
+use std::{
+    io::{self, SeekFrom},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use log::{debug, info, warn};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, OnceCell},
+};
+use url::Url;
+
+use crate::{
+    content::{limiter::RateLimiter, source::DOWNLOAD_CLIENT},
+    gameinfo::GameInstallInfo,
+    util::native::{maxima_dir, NativeError},
+};
+
+/// Port peers listen on unless told otherwise
+pub const DEFAULT_PEER_PORT: u16 = 31040;
+
+/// How long the list of installed builds is reused before looking at the disk again
+const BUILD_INDEX_TTL: Duration = Duration::from_secs(30);
+/// How long a peer may take to answer a request, or to send the next part of a file
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest request or header line a peer may send
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+/// Most headers a peer may send with a request
+const MAX_HEADERS: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum PeerError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Native(#[from] NativeError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+
+    #[error("peer responded with {0}")]
+    Http(StatusCode),
+    #[error("peer didn't respond in time")]
+    Timeout,
+    #[error("request line or header is too long")]
+    LineTooLong,
+}
+
+/// A build installed on this machine, which can be handed out to peers
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PeerBuild {
+    offer_id: String,
+    build_id: String,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// Looks through the game info of everything installed by Maxima. Games without an offer
+/// and build, like ones installed by EA Desktop, can't be matched against a request.
+fn installed_builds() -> Result<Vec<PeerBuild>, PeerError> {
+    let dir = maxima_dir()?.join("gameinfo");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut builds = Vec::new();
+    for file in std::fs::read_dir(dir)? {
+        let path = file?.path();
+        if path.extension().map(|ext| ext != "json").unwrap_or(true) {
+            continue;
+        }
+
+        let info: GameInstallInfo = match std::fs::read_to_string(&path)
+            .map_err(PeerError::from)
+            .and_then(|json| Ok(serde_json::from_str(&json)?))
+        {
+            Ok(info) => info,
+            Err(err) => {
+                warn!("Failed to read {}: {}", path.display(), err);
+                continue;
+            }
+        };
+
+        // Some files of playable installs that are still downloading are missing
+        if info.incomplete {
+            continue;
+        }
+
+        // Add-ons are installed into the directory of their base game
+        builds.extend(info.dlc.into_iter().map(|dlc| PeerBuild {
+            offer_id: dlc.offer_id,
+            build_id: dlc.build_id,
+            path: info.path.clone(),
+        }));
+
+        if let (Some(offer_id), Some(build_id)) = (info.offer_id, info.build_id) {
+            builds.push(PeerBuild {
+                offer_id,
+                build_id,
+                path: info.path,
+            });
+        }
+    }
+
+    Ok(builds)
+}
+
+/// Decodes `%XX` escapes in a path segment
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Parses a `bytes=start-end` range header into a range that doesn't include `end`
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+    let start: u64 = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => len,
+        end => end.parse::<u64>().ok()?.checked_add(1)?.min(len),
+    };
+
+    if start >= end {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+/// Reads a line of at most `MAX_LINE_LENGTH` bytes, returning 0 at the end of the stream
+async fn read_line<R: AsyncBufReadExt + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+) -> Result<usize, PeerError> {
+    let read = reader.take(MAX_LINE_LENGTH).read_line(line).await?;
+    if read as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        return Err(PeerError::LineTooLong);
+    }
+
+    Ok(read)
+}
+
+async fn respond<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: StatusCode,
+    headers: &[(&str, String)],
+) -> Result<(), PeerError> {
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default()
+    );
+
+    for (name, value) in headers {
+        response += &format!("{}: {}\r\n", name, value);
+    }
+
+    if !headers.iter().any(|(name, _)| *name == "Content-Length") {
+        response += "Content-Length: 0\r\n";
+    }
+
+    response += "\r\n";
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Serves the files of completed installs to other Maxima instances on the network.
+///
+/// `GET /builds` lists the offer and build IDs available, files are at
+/// `/builds/{offer_id}/{build_id}/{path in the build}` and support range requests.
+pub struct PeerServer {
+    listener: TcpListener,
+    builds: Mutex<Option<(Instant, Vec<PeerBuild>)>>,
+}
+
+impl PeerServer {
+    pub async fn bind(addr: &str) -> Result<Self, PeerError> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            builds: Mutex::new(None),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, PeerError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts peers until the task is dropped
+    pub async fn run(self) -> Result<(), PeerError> {
+        info!("Serving installed builds on {}", self.local_addr()?);
+
+        let server = Arc::new(self);
+        loop {
+            let (socket, addr) = server.listener.accept().await?;
+            let server = server.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = server.handle(socket).await {
+                    debug!("Peer request from {} failed: {}", addr, err);
+                }
+            });
+        }
+    }
+
+    async fn builds(&self) -> Result<Vec<PeerBuild>, PeerError> {
+        let mut builds = self.builds.lock().await;
+        if let Some((loaded, builds)) = builds.as_ref() {
+            if loaded.elapsed() < BUILD_INDEX_TTL {
+                return Ok(builds.clone());
+            }
+        }
+
+        let installed = installed_builds()?;
+        *builds = Some((Instant::now(), installed.clone()));
+        Ok(installed)
+    }
+
+    /// Reads the request line and the range header, if there is one. `None` when the request
+    /// has too many headers.
+    async fn read_request<R: AsyncBufReadExt + Unpin>(
+        reader: &mut R,
+    ) -> Result<Option<(String, Option<String>)>, PeerError> {
+        let mut line = String::new();
+        read_line(reader, &mut line).await?;
+
+        let mut range = None;
+        for _ in 0..MAX_HEADERS {
+            let mut header = String::new();
+            if read_line(reader, &mut header).await? == 0 || header.trim().is_empty() {
+                return Ok(Some((line, range)));
+            }
+
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("range") {
+                    range = Some(value.trim().to_owned());
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn handle(&self, mut socket: TcpStream) -> Result<(), PeerError> {
+        let (read, mut write) = socket.split();
+        let mut reader = BufReader::new(read);
+
+        // Peers that stop sending halfway through would keep the connection open forever
+        let request = tokio::time::timeout(PEER_TIMEOUT, Self::read_request(&mut reader))
+            .await
+            .map_err(|_| PeerError::Timeout)?;
+        let (line, range) = match request {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                return respond(&mut write, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, &[]).await
+            }
+            Err(PeerError::LineTooLong) => {
+                return respond(&mut write, StatusCode::BAD_REQUEST, &[]).await
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+            _ => return respond(&mut write, StatusCode::BAD_REQUEST, &[]).await,
+        };
+
+        if method != "GET" && method != "HEAD" {
+            return respond(&mut write, StatusCode::METHOD_NOT_ALLOWED, &[]).await;
+        }
+
+        let path = target.split('?').next().unwrap_or_default();
+        let segments = match path
+            .trim_start_matches('/')
+            .split('/')
+            .map(percent_decode)
+            .collect::<Option<Vec<String>>>()
+        {
+            Some(segments) => segments,
+            None => return respond(&mut write, StatusCode::BAD_REQUEST, &[]).await,
+        };
+
+        match segments.as_slice() {
+            [builds] if builds == "builds" => {
+                let body = serde_json::to_vec(&self.builds().await?)?;
+                respond(
+                    &mut write,
+                    StatusCode::OK,
+                    &[
+                        ("Content-Type", "application/json".to_owned()),
+                        ("Content-Length", body.len().to_string()),
+                    ],
+                )
+                .await?;
+
+                if method == "GET" {
+                    write.write_all(&body).await?;
+                }
+
+                Ok(())
+            }
+            [builds, offer_id, build_id, file @ ..] if builds == "builds" && !file.is_empty() => {
+                let path = match self.file_path(offer_id, build_id, file).await? {
+                    Some(path) => path,
+                    None => return respond(&mut write, StatusCode::NOT_FOUND, &[]).await,
+                };
+
+                let mut file = File::open(&path).await?;
+                let len = file.metadata().await?.len();
+
+                let (status, start, end) = match range {
+                    None => (StatusCode::OK, 0, len),
+                    Some(range) => match parse_range(&range, len) {
+                        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+                        None => {
+                            return respond(
+                                &mut write,
+                                StatusCode::RANGE_NOT_SATISFIABLE,
+                                &[("Content-Range", format!("bytes */{}", len))],
+                            )
+                            .await
+                        }
+                    },
+                };
+
+                let mut headers = vec![
+                    ("Content-Type", "application/octet-stream".to_owned()),
+                    ("Content-Length", (end - start).to_string()),
+                    ("Accept-Ranges", "bytes".to_owned()),
+                ];
+                if status == StatusCode::PARTIAL_CONTENT {
+                    headers.push((
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end - 1, len),
+                    ));
+                }
+
+                respond(&mut write, status, &headers).await?;
+
+                if method == "GET" {
+                    file.seek(SeekFrom::Start(start)).await?;
+                    tokio::io::copy(&mut file.take(end - start), &mut write).await?;
+                }
+
+                write.flush().await?;
+                Ok(())
+            }
+            _ => respond(&mut write, StatusCode::NOT_FOUND, &[]).await,
+        }
+    }
+
+    /// Finds a file of an installed build, refusing anything that would leave the install
+    async fn file_path(
+        &self,
+        offer_id: &str,
+        build_id: &str,
+        file: &[String],
+    ) -> Result<Option<PathBuf>, PeerError> {
+        let builds = self.builds().await?;
+        let build = match builds
+            .iter()
+            .find(|build| build.offer_id == offer_id && build.build_id == build_id)
+        {
+            Some(build) => build,
+            None => return Ok(None),
+        };
+
+        let mut path = build.path.clone();
+        for segment in file {
+            let unsafe_segment = segment.is_empty()
+                || segment == "."
+                || segment == ".."
+                || segment.contains(['\\', ':']);
+            if unsafe_segment {
+                return Ok(None);
+            }
+
+            path.push(segment);
+        }
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        Ok(Some(path))
+    }
+}
+
+/// Downloads files of a build from other machines running a `PeerServer`. Nothing here can
+/// be trusted, the files have to be checked against the central directory from the CDN.
+pub struct PeerClient {
+    client: Client,
+    peers: Vec<Url>,
+    offer_id: String,
+    build_id: String,
+    /// The peers that have the build, asked for once before the first file is downloaded
+    available: OnceCell<Vec<Url>>,
+}
+
+impl PeerClient {
+    /// Peers are base URLs like `http://192.168.1.20:31040`, invalid ones are skipped
+    pub fn new(peers: &[String], offer_id: &str, build_id: &str) -> Self {
+        let peers = peers
+            .iter()
+            .filter_map(|peer| match Url::parse(peer) {
+                Ok(url) => Some(url),
+                Err(err) => {
+                    warn!("Ignoring peer {}: {}", peer, err);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            client: DOWNLOAD_CLIENT.clone(),
+            peers,
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+            available: OnceCell::new(),
+        }
+    }
+
+    pub fn peers(&self) -> &Vec<Url> {
+        &self.peers
+    }
+
+    /// The peers listing the build in their `/builds`, so the others aren't asked for
+    /// every single file
+    pub async fn available_peers(&self) -> &Vec<Url> {
+        self.available
+            .get_or_init(|| async {
+                let mut available = Vec::new();
+                for peer in &self.peers {
+                    match self.builds(peer).await {
+                        Ok(builds) => {
+                            let has_build = builds.iter().any(|x| {
+                                x.offer_id == self.offer_id && x.build_id == self.build_id
+                            });
+                            if has_build {
+                                available.push(peer.clone());
+                            } else {
+                                debug!("Peer {} doesn't have build {}", peer, self.build_id);
+                            }
+                        }
+                        Err(err) => warn!("Failed to get the builds of peer {}: {}", peer, err),
+                    }
+                }
+
+                available
+            })
+            .await
+    }
+
+    async fn builds(&self, peer: &Url) -> Result<Vec<PeerBuild>, PeerError> {
+        let mut url = peer.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push("builds");
+        }
+
+        let response = self.client.get(url).timeout(PEER_TIMEOUT).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PeerError::Http(status));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    fn file_url(&self, peer: &Url, name: &str) -> Url {
+        let mut url = peer.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments
+                .pop_if_empty()
+                .extend(["builds", &self.offer_id, &self.build_id])
+                .extend(name.split('/'));
+        }
+
+        url
+    }
+
+    /// Writes the file `name` of the build, as `peer` has it, to `path`. Every chunk goes
+    /// through `rate_limiter` like the CDN does, and its size is passed to `progress` once
+    /// it's written.
+    pub async fn download(
+        &self,
+        peer: &Url,
+        name: &str,
+        path: &Path,
+        rate_limiter: Option<&RateLimiter>,
+        mut progress: impl FnMut(usize),
+    ) -> Result<(), PeerError> {
+        let response = tokio::time::timeout(
+            PEER_TIMEOUT,
+            self.client.get(self.file_url(peer, name)).send(),
+        )
+        .await
+        .map_err(|_| PeerError::Timeout)??;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PeerError::Http(status));
+        }
+
+        // Big files take a while, only stalls are given up on
+        let mut file = File::create(path).await?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = tokio::time::timeout(PEER_TIMEOUT, stream.next())
+            .await
+            .map_err(|_| PeerError::Timeout)?
+        {
+            let chunk = chunk?;
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.acquire(chunk.len()).await;
+            }
+
+            file.write_all(&chunk).await?;
+            progress(chunk.len());
+        }
+
+        file.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::content::{progressive::ChunkProgress, source::ByteSource};
+
+/// How much a new speed sample counts towards the smoothed speed
+const SPEED_SMOOTHING: f64 = 0.2;
+/// Samples taken closer together than this are too noisy to be useful
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadPhase {
+    /// Looking up the build and reading its central directory
+    FetchingManifest,
+    Downloading,
+    /// Running the game's touchup installer after every file was downloaded
+    Touchup,
+}
+
+impl DownloadPhase {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::FetchingManifest,
+            1 => Self::Downloading,
+            _ => Self::Touchup,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::FetchingManifest => 0,
+            Self::Downloading => 1,
+            Self::Touchup => 2,
+        }
+    }
+}
+
+impl fmt::Display for DownloadPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::FetchingManifest => "Fetching manifest",
+            Self::Downloading => "Downloading",
+            Self::Touchup => "Running touchup",
+        })
+    }
+}
+
+/// Snapshot of a running download, emitted through `MaximaEvent::DownloadProgress`
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    pub offer_id: String,
+    pub phase: DownloadPhase,
+    pub bytes_done: usize,
+    pub bytes_total: usize,
+    pub files_done: usize,
+    pub files_total: usize,
+    /// Bytes per second, smoothed over the last few seconds
+    pub speed: f64,
+    /// `None` until there's enough data to make a guess
+    pub eta: Option<Duration>,
+    /// The mirror the files are coming from
+    pub mirror: Option<String>,
+}
+
+impl DownloadProgress {
+    /// Progress from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total == 0 {
+            return 0.0;
+        }
+
+        (self.bytes_done as f64 / self.bytes_total as f64).min(1.0)
+    }
+}
+
+impl fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.phase != DownloadPhase::Downloading {
+            return write!(f, "{}...", self.phase);
+        }
+
+        write!(
+            f,
+            "{} {:.1}% ({} / {}, {}/{} files, {}/s",
+            self.phase,
+            self.fraction() * 100.0,
+            format_bytes(self.bytes_done as u64),
+            format_bytes(self.bytes_total as u64),
+            self.files_done,
+            self.files_total,
+            format_bytes(self.speed as u64),
+        )?;
+
+        if let Some(eta) = self.eta {
+            write!(f, ", {} left", format_duration(eta))?;
+        }
+
+        if let Some(mirror) = &self.mirror {
+            write!(f, ", from {}", mirror)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+/// Counters shared between a download and the tasks doing the work
+#[derive(Default)]
+pub(crate) struct ProgressCounters {
+    phase: AtomicU8,
+    pub bytes_done: AtomicUsize,
+    pub bytes_total: AtomicUsize,
+    pub files_done: AtomicUsize,
+    pub files_total: AtomicUsize,
+    /// The archive being downloaded, set once it's been opened
+    pub source: Mutex<Option<Arc<dyn ByteSource>>>,
+    /// Set once the build has been read, for progressive installs
+    pub chunks: Mutex<Option<Arc<ChunkProgress>>>,
+}
+
+impl ProgressCounters {
+    pub fn phase(&self) -> DownloadPhase {
+        DownloadPhase::from_u8(self.phase.load(Ordering::SeqCst))
+    }
+
+    pub fn set_phase(&self, phase: DownloadPhase) {
+        self.phase.store(phase.as_u8(), Ordering::SeqCst);
+    }
+
+    pub fn mirror(&self) -> Option<String> {
+        self.source.lock().unwrap().as_ref()?.mirror()
+    }
+
+    pub fn chunks(&self) -> Option<Arc<ChunkProgress>> {
+        self.chunks.lock().unwrap().clone()
+    }
+}
+
+/// Smooths the download speed, so the ETA doesn't jump around with every sample
+#[derive(Default)]
+pub(crate) struct ThroughputMeter {
+    last_sample: Option<(Instant, usize)>,
+    speed: f64,
+}
+
+impl ThroughputMeter {
+    /// Records the amount of bytes done so far, returning the smoothed speed
+    pub fn sample(&mut self, bytes_done: usize) -> f64 {
+        let now = Instant::now();
+
+        let (time, bytes) = match self.last_sample {
+            Some(sample) => sample,
+            None => {
+                self.last_sample = Some((now, bytes_done));
+                return self.speed;
+            }
+        };
+
+        let elapsed = now - time;
+        if elapsed < MIN_SAMPLE_INTERVAL {
+            return self.speed;
+        }
+
+        let speed = bytes_done.saturating_sub(bytes) as f64 / elapsed.as_secs_f64();
+        self.speed = if self.speed == 0.0 {
+            speed
+        } else {
+            SPEED_SMOOTHING * speed + (1.0 - SPEED_SMOOTHING) * self.speed
+        };
+        self.last_sample = Some((now, bytes_done));
+
+        self.speed
+    }
+
+    pub fn eta(&self, bytes_left: usize) -> Option<Duration> {
+        if self.speed < 1.0 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(bytes_left as f64 / self.speed))
+    }
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use derive_getters::Getters;
+use log::info;
+
+use crate::{
+    content::{exclusion::get_pattern_list, zip::ZipFileEntry},
+    core::manifest::dip::DiPManifest,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkState {
+    /// Nothing of the chunk has been downloaded yet
+    Queued,
+    Downloading,
+    Installed,
+}
+
+/// Snapshot of a chunk of a progressive install
+#[derive(Debug, Clone, Getters)]
+pub struct ChunkStatus {
+    id: u32,
+    name: String,
+    required: bool,
+    state: ChunkState,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+impl ChunkStatus {
+    /// Progress from 0.0 to 1.0
+    pub fn fraction(&self) -> f32 {
+        match self.state {
+            ChunkState::Installed => 1.0,
+            _ if self.bytes_total == 0 => 0.0,
+            _ => (self.bytes_done as f64 / self.bytes_total as f64).min(1.0) as f32,
+        }
+    }
+}
+
+struct Chunk {
+    status: ChunkStatus,
+    entries_left: usize,
+}
+
+/// Keeps track of which chunks of a progressive install are done while its build downloads
+pub struct ChunkProgress {
+    offer_id: String,
+    chunks: Mutex<Vec<Chunk>>,
+    /// Index of the chunk each file being downloaded belongs to
+    entries: HashMap<String, usize>,
+}
+
+impl ChunkProgress {
+    /// Sorts `entries` so the chunks are downloaded in the order the manifest wants them.
+    /// Returns `None` for games without a progressive install.
+    ///
+    /// Files that aren't part of any chunk go with the first one, nothing says the game can
+    /// start without them.
+    pub(crate) fn new(
+        offer_id: &str,
+        manifest: &DiPManifest,
+        entries: &mut [ZipFileEntry],
+    ) -> Option<Self> {
+        let definitions = manifest.progressive_chunks();
+        if definitions.is_empty() {
+            return None;
+        }
+
+        let patterns: Vec<_> = definitions
+            .iter()
+            .map(|x| get_pattern_list(&x.patterns().collect::<Vec<String>>()))
+            .collect();
+
+        let chunk_of = |name: &str| patterns.iter().position(|x| x.is_match(name)).unwrap_or(0);
+        entries.sort_by_cached_key(|x| chunk_of(x.name()));
+
+        let mut chunks: Vec<Chunk> = definitions
+            .iter()
+            .map(|x| Chunk {
+                status: ChunkStatus {
+                    id: x.attr_id,
+                    name: x.attr_name.clone(),
+                    required: x.attr_required,
+                    state: ChunkState::Queued,
+                    bytes_done: 0,
+                    bytes_total: 0,
+                },
+                entries_left: 0,
+            })
+            .collect();
+
+        let mut entry_chunks = HashMap::with_capacity(entries.len());
+        for entry in entries.iter() {
+            let idx = chunk_of(entry.name());
+            chunks[idx].entries_left += 1;
+            chunks[idx].status.bytes_total += *entry.uncompressed_size() as u64;
+            entry_chunks.insert(entry.name().to_owned(), idx);
+        }
+
+        // Chunks with nothing to download, like during an update that didn't touch them
+        for chunk in chunks.iter_mut().filter(|x| x.entries_left == 0) {
+            chunk.status.state = ChunkState::Installed;
+        }
+
+        info!(
+            "Progressive install of {} with {} chunks",
+            offer_id,
+            chunks.len()
+        );
+
+        Some(Self {
+            offer_id: offer_id.to_owned(),
+            chunks: Mutex::new(chunks),
+            entries: entry_chunks,
+        })
+    }
+
+    pub fn offer_id(&self) -> &str {
+        &self.offer_id
+    }
+
+    /// Records that a file finished downloading
+    pub(crate) fn entry_done(&self, entry: &ZipFileEntry) {
+        let Some(idx) = self.entries.get(entry.name()) else {
+            return;
+        };
+
+        let mut chunks = self.chunks.lock().unwrap();
+        let chunk = &mut chunks[*idx];
+        if chunk.entries_left == 0 {
+            return;
+        }
+
+        chunk.entries_left -= 1;
+        chunk.status.bytes_done += *entry.uncompressed_size() as u64;
+        chunk.status.state = if chunk.entries_left == 0 {
+            info!(
+                "Chunk {} ({}) of {} is installed",
+                chunk.status.id, chunk.status.name, self.offer_id
+            );
+            ChunkState::Installed
+        } else {
+            ChunkState::Downloading
+        };
+    }
+
+    pub fn chunks(&self) -> Vec<ChunkStatus> {
+        let chunks = self.chunks.lock().unwrap();
+        chunks.iter().map(|x| x.status.clone()).collect()
+    }
+
+    /// Whether every one of `ids` is installed. Chunks the build doesn't have are not.
+    pub fn are_installed(&self, ids: &[u32]) -> bool {
+        let chunks = self.chunks.lock().unwrap();
+        ids.iter().all(|id| {
+            chunks
+                .iter()
+                .any(|x| x.status.id == *id && x.status.state == ChunkState::Installed)
+        })
+    }
+
+    /// The game can be started once its required chunks are installed. The first chunk is
+    /// always required, it has the files that aren't part of any chunk. Without a required
+    /// chunk, nothing says the game can start before the whole build is in.
+    pub fn playable(&self) -> bool {
+        let chunks = self.chunks.lock().unwrap();
+        let mut required = chunks
+            .iter()
+            .enumerate()
+            .filter(|(idx, x)| *idx == 0 || x.status.required)
+            .peekable();
+
+        required.peek().is_some() && required.all(|(_, x)| x.status.state == ChunkState::Installed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(required: &[bool]) -> ChunkProgress {
+        let chunks = required
+            .iter()
+            .enumerate()
+            .map(|(idx, required)| Chunk {
+                status: ChunkStatus {
+                    id: idx as u32,
+                    name: format!("Chunk {}", idx),
+                    required: *required,
+                    state: ChunkState::Queued,
+                    bytes_done: 0,
+                    bytes_total: 1024,
+                },
+                entries_left: 1,
+            })
+            .collect();
+
+        ChunkProgress {
+            offer_id: "Origin.OFR.50.0000384".to_owned(),
+            chunks: Mutex::new(chunks),
+            entries: HashMap::new(),
+        }
+    }
+
+    fn install(progress: &ChunkProgress, idx: usize) {
+        progress.chunks.lock().unwrap()[idx].status.state = ChunkState::Installed;
+    }
+
+    #[test]
+    fn playable_once_required_chunks_are_installed() {
+        // Nothing is marked as required, the first chunk still has to be there
+        let none_required = progress(&[false, false]);
+        assert!(!none_required.playable());
+        install(&none_required, 0);
+        assert!(none_required.playable());
+
+        let second_required = progress(&[false, true, false]);
+        install(&second_required, 1);
+        assert!(!second_required.playable());
+        install(&second_required, 0);
+        assert!(second_required.playable());
+
+        assert!(!progress(&[]).playable());
+    }
+}
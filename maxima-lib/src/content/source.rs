@@ -0,0 +1,420 @@
+use std::{
+    cmp,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use lazy_static::lazy_static;
+use log::warn;
+use reqwest::{header::ToStrError, Client, RequestBuilder, Response, StatusCode};
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::{Mutex, OnceCell},
+};
+use tokio_util::io::ReaderStream;
+use url::Url;
+
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, io::Error>> + Send>>;
+
+/// A server that doesn't answer or send anything for this long is treated as failed
+pub(crate) const STALL_TIMEOUT: Duration = Duration::from_secs(20);
+
+lazy_static! {
+    /// Shared by every archive read over HTTP, so connections to the CDN are kept open and
+    /// reused by all the files and ranges being downloaded
+    pub(crate) static ref DOWNLOAD_CLIENT: Client = Client::builder()
+        .pool_max_idle_per_host(64)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_nodelay(true)
+        .tcp_keepalive(Duration::from_secs(60))
+        .connect_timeout(Duration::from_secs(15))
+        // Ranges are only meaningful for the bytes as they're stored
+        .no_gzip()
+        .build()
+        .unwrap();
+}
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    ToStr(#[from] ToStrError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+
+    #[error("no content length found in response")]
+    NoContentLength,
+    #[error("invalid content length `{0}`")]
+    InvalidContentLength(String),
+    #[error("failed to read range: {0}")]
+    Http(StatusCode),
+    #[error("range {start}-{end} is out of bounds")]
+    OutOfBounds { start: u64, end: u64 },
+    #[error("the server ignored the range {start}-{end}")]
+    RangeIgnored { start: u64, end: u64 },
+    #[error("range {start}-{end} ended after {actual} bytes")]
+    ShortRead { start: u64, end: u64, actual: u64 },
+    #[error("unsupported source `{0}`")]
+    Unsupported(String),
+    #[error("failed to refresh url: {0}")]
+    Refresh(String),
+    #[error("no mirrors to read from")]
+    NoMirrors,
+    #[error("no response within {0:?}")]
+    Timeout(Duration),
+}
+
+/// Somewhere the bytes of a build archive can be read from. Everything that parses or
+/// extracts archives goes through this, so builds can come from the CDN or from disk.
+#[async_trait]
+pub trait ByteSource: Send + Sync {
+    /// Total size of the archive
+    async fn len(&self) -> Result<u64, SourceError>;
+
+    async fn is_empty(&self) -> Result<bool, SourceError> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Streams the bytes from `start` up to, but not including, `end`
+    async fn read_range(&self, start: u64, end: u64) -> Result<ByteStream, SourceError>;
+
+    /// Reads the whole range into memory, failing if the source has fewer bytes than asked for
+    async fn read_range_bytes(&self, start: u64, end: u64) -> Result<Bytes, SourceError> {
+        let chunks: Vec<Bytes> = self.read_range(start, end).await?.try_collect().await?;
+        let bytes: Bytes = chunks.concat().into();
+
+        let actual = bytes.len() as u64;
+        if actual != end.saturating_sub(start) {
+            return Err(SourceError::ShortRead { start, end, actual });
+        }
+
+        Ok(bytes)
+    }
+
+    /// Where each disk of a split archive starts. Offsets in the archive are relative to the
+    /// start of their disk, so they have to be shifted by this to be read from the source.
+    async fn disk_starts(&self) -> Result<Vec<u64>, SourceError> {
+        Ok(vec![0])
+    }
+
+    /// Name of the mirror currently being read from, if there's a choice of them
+    fn mirror(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Hands out a new URL for a source once the one it was created with stops working
+#[async_trait]
+pub trait UrlRefresher: Send + Sync {
+    async fn refresh(&self) -> Result<String, SourceError>;
+}
+
+/// Reads an archive hosted on an HTTP server that supports range requests, like the CDN
+pub struct HttpSource {
+    client: Client,
+    /// The URL and how many times it was refreshed
+    url: RwLock<(String, u64)>,
+    refresher: Option<Arc<dyn UrlRefresher>>,
+    /// Keeps concurrent requests from all refreshing the URL when it expires
+    refresh_lock: Mutex<()>,
+}
+
+impl HttpSource {
+    pub fn new(client: Client, url: &str) -> Self {
+        Self {
+            client,
+            url: RwLock::new((url.to_owned(), 0)),
+            refresher: None,
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    /// Signed CDN URLs expire, requests failing because of that are retried with a URL
+    /// from `refresher`
+    pub fn with_refresher(mut self, refresher: Arc<dyn UrlRefresher>) -> Self {
+        self.refresher = Some(refresher);
+        self
+    }
+
+    async fn send<F>(&self, request: F) -> Result<Response, SourceError>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        let (url, generation) = self.url.read().unwrap().clone();
+        let response = Self::send_once(request(&url)).await?;
+
+        let expired = matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+        );
+        if !expired || self.refresher.is_none() {
+            return Ok(response);
+        }
+
+        self.refresh(generation).await?;
+
+        let url = self.url.read().unwrap().0.clone();
+        Self::send_once(request(&url)).await
+    }
+
+    /// The connect timeout of the client doesn't cover a server that accepts the connection
+    /// and never answers, which would hold up the download forever
+    async fn send_once(request: RequestBuilder) -> Result<Response, SourceError> {
+        tokio::time::timeout(STALL_TIMEOUT, request.send())
+            .await
+            .map_err(|_| SourceError::Timeout(STALL_TIMEOUT))?
+            .map_err(SourceError::from)
+    }
+
+    async fn refresh(&self, generation: u64) -> Result<(), SourceError> {
+        let refresher = match &self.refresher {
+            Some(refresher) => refresher,
+            None => return Ok(()),
+        };
+
+        let _guard = self.refresh_lock.lock().await;
+        if self.url.read().unwrap().1 != generation {
+            // Another request got a new one while this one was waiting
+            return Ok(());
+        }
+
+        warn!("Download URL was rejected, it has probably expired");
+        let url = refresher.refresh().await?;
+        *self.url.write().unwrap() = (url, generation + 1);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ByteSource for HttpSource {
+    async fn len(&self) -> Result<u64, SourceError> {
+        let response = self.send(|url| self.client.head(url)).await?;
+        let content_length = response
+            .headers()
+            .get("content-length")
+            .ok_or(SourceError::NoContentLength)?
+            .to_str()?;
+
+        content_length
+            .parse::<u64>()
+            .map_err(|_| SourceError::InvalidContentLength(content_length.to_owned()))
+    }
+
+    async fn read_range(&self, start: u64, end: u64) -> Result<ByteStream, SourceError> {
+        if end <= start {
+            return Ok(Box::pin(
+                futures::stream::empty::<Result<Bytes, io::Error>>(),
+            ));
+        }
+
+        let range = format!("bytes={}-{}", start, end - 1);
+        let response = self
+            .send(|url| self.client.get(url).header("range", &range))
+            .await?;
+
+        // A server that doesn't support ranges sends the whole file, which is only what was
+        // asked for if the range covers all of it
+        let status = response.status();
+        match status {
+            StatusCode::PARTIAL_CONTENT => {}
+            StatusCode::OK if start == 0 && response.content_length() == Some(end) => {}
+            StatusCode::OK => return Err(SourceError::RangeIgnored { start, end }),
+            _ => return Err(SourceError::Http(status)),
+        }
+
+        Ok(Box::pin(response.bytes_stream().map_err(io::Error::other)))
+    }
+}
+
+/// Reads `start..end` as requests of at most `range_size` bytes, up to `ranges` of which run
+/// at the same time. The bytes still come out in order. Each range is held in memory until
+/// everything before it has been read.
+pub fn read_range_parallel(
+    source: Arc<dyn ByteSource>,
+    start: u64,
+    end: u64,
+    range_size: u64,
+    ranges: usize,
+) -> ByteStream {
+    let range_size = range_size.max(1);
+    let bounds = (start..end)
+        .step_by(range_size as usize)
+        .map(move |from| (from, cmp::min(from + range_size, end)));
+
+    let stream = futures::stream::iter(bounds)
+        .map(move |(from, to)| {
+            let source = source.clone();
+            async move {
+                source
+                    .read_range_bytes(from, to)
+                    .await
+                    .map_err(io::Error::other)
+            }
+        })
+        .buffered(ranges.max(1));
+
+    Box::pin(stream)
+}
+
+/// Reads an archive from the local filesystem. Network shares work too, as long as they're mounted.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl ByteSource for FileSource {
+    async fn len(&self) -> Result<u64, SourceError> {
+        Ok(tokio::fs::metadata(&self.path).await?.len())
+    }
+
+    async fn read_range(&self, start: u64, end: u64) -> Result<ByteStream, SourceError> {
+        let mut file = File::open(&self.path).await?;
+        let len = file.metadata().await?.len();
+        if start > end || end > len {
+            return Err(SourceError::OutOfBounds { start, end });
+        }
+
+        file.seek(SeekFrom::Start(start)).await?;
+        Ok(Box::pin(ReaderStream::new(file.take(end - start))))
+    }
+}
+
+/// Reads a split archive (`build.z01`, `build.z02`, ..., `build.zip`) as if it were one
+/// file, with the disks placed one after another in order
+pub struct MultiDiskSource {
+    disks: Vec<Arc<dyn ByteSource>>,
+    starts: OnceCell<Vec<u64>>,
+}
+
+impl MultiDiskSource {
+    pub fn new(disks: Vec<Arc<dyn ByteSource>>) -> Self {
+        Self {
+            disks,
+            starts: OnceCell::new(),
+        }
+    }
+
+    /// The start of every disk, followed by the end of the last one
+    async fn bounds(&self) -> Result<&Vec<u64>, SourceError> {
+        self.starts
+            .get_or_try_init(|| async {
+                let mut bounds = vec![0];
+                for disk in &self.disks {
+                    let len = disk.len().await?;
+                    bounds.push(bounds.last().unwrap() + len);
+                }
+
+                Ok::<_, SourceError>(bounds)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl ByteSource for MultiDiskSource {
+    async fn len(&self) -> Result<u64, SourceError> {
+        Ok(*self.bounds().await?.last().unwrap())
+    }
+
+    async fn read_range(&self, start: u64, end: u64) -> Result<ByteStream, SourceError> {
+        let bounds = self.bounds().await?;
+        if start > end || end > *bounds.last().unwrap() {
+            return Err(SourceError::OutOfBounds { start, end });
+        }
+
+        // Ranges crossing the end of a disk continue at the start of the next one
+        let mut streams = Vec::new();
+        for (disk, source) in self.disks.iter().enumerate() {
+            let (disk_start, disk_end) = (bounds[disk], bounds[disk + 1]);
+            if end <= disk_start || start >= disk_end {
+                continue;
+            }
+
+            let from = start.max(disk_start) - disk_start;
+            let to = end.min(disk_end) - disk_start;
+            streams.push(source.read_range(from, to).await?);
+        }
+
+        Ok(Box::pin(futures::stream::iter(streams).flatten()))
+    }
+
+    async fn disk_starts(&self) -> Result<Vec<u64>, SourceError> {
+        let bounds = self.bounds().await?;
+        Ok(bounds[..bounds.len() - 1].to_vec())
+    }
+}
+
+/// Finds the other disks of a split archive next to its last disk, which is the `.zip`.
+/// Returns `None` when there's only the one file.
+fn split_disks(path: &Path) -> Option<Vec<PathBuf>> {
+    let is_zip = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false);
+    if !is_zip {
+        return None;
+    }
+
+    let mut disks: Vec<PathBuf> = (1..)
+        .map(|disk| path.with_extension(format!("z{:02}", disk)))
+        .take_while(|disk| disk.exists())
+        .collect();
+    if disks.is_empty() {
+        return None;
+    }
+
+    disks.push(path.to_owned());
+    Some(disks)
+}
+
+fn file_source(path: PathBuf) -> Arc<dyn ByteSource> {
+    match split_disks(&path) {
+        Some(disks) => Arc::new(MultiDiskSource::new(
+            disks
+                .into_iter()
+                .map(|disk| Arc::new(FileSource::new(disk)) as Arc<dyn ByteSource>)
+                .collect(),
+        )),
+        None => Arc::new(FileSource::new(path)),
+    }
+}
+
+/// Picks a source based on the location of the archive. `http://` and `https://` URLs are
+/// fetched with range requests, `file://` URLs and absolute paths are read from disk, along
+/// with the rest of the disks if the archive is split.
+pub fn source_for(location: &str) -> Result<Arc<dyn ByteSource>, SourceError> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return Ok(Arc::new(HttpSource::new(DOWNLOAD_CLIENT.clone(), location)));
+    }
+
+    if location.starts_with("file://") {
+        let path = Url::parse(location)?
+            .to_file_path()
+            .map_err(|_| SourceError::Unsupported(location.to_owned()))?;
+        return Ok(file_source(path));
+    }
+
+    let path = PathBuf::from(location);
+    if path.is_absolute() {
+        return Ok(file_source(path));
+    }
+
+    Err(SourceError::Unsupported(location.to_owned()))
+}
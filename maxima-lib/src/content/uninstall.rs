@@ -0,0 +1,117 @@
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    core::manifest::MANIFEST_RELATIVE_PATH,
+    util::native::{maxima_dir, NativeError},
+};
+
+#[derive(Default, Clone)]
+pub struct UninstallOptions {
+    /// Delete the game's wine prefix. Does nothing on Windows
+    pub remove_wine_prefix: bool,
+    /// Delete the game's cached OOA licenses
+    pub remove_licenses: bool,
+}
+
+/// Whether a name from a central directory stays inside the directory it's extracted to.
+/// Drive prefixes like `C:` aren't components on unix, so they're checked separately.
+pub(crate) fn is_contained(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return false;
+    }
+
+    Path::new(name)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+}
+
+/// Removes the given files from `root`, along with any directories that end up empty
+pub(crate) async fn remove_files(root: &Path, names: &[String]) -> io::Result<()> {
+    for name in names {
+        if name.ends_with('/') || !is_contained(name) {
+            continue;
+        }
+
+        let path = root.join(name);
+        if !path.is_file() {
+            continue;
+        }
+
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            warn!("Failed to remove {}: {}", path.display(), err);
+        }
+    }
+
+    let root = root.to_owned();
+    tokio::task::spawn_blocking(move || remove_empty_dirs(&root))
+        .await
+        .map_err(io::Error::other)??;
+    Ok(())
+}
+
+/// Returns whether `path` was removed
+fn remove_empty_dirs(path: &Path) -> io::Result<bool> {
+    let mut empty = true;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && remove_empty_dirs(&entry.path())? {
+            continue;
+        }
+
+        empty = false;
+    }
+
+    if empty {
+        debug!("Removing empty directory {}", path.display());
+        std::fs::remove_dir(path)?;
+    }
+
+    Ok(empty)
+}
+
+/// Decides whether a whole install directory can be deleted without knowing which files belong
+/// to the game. It has to look like a game install, and can't be anything important.
+pub(crate) fn is_safe_to_remove(path: &Path) -> Result<bool, NativeError> {
+    if !is_removable_dir(path)? {
+        return Ok(false);
+    }
+
+    Ok(path.join(MANIFEST_RELATIVE_PATH).exists())
+}
+
+/// Decides whether a Wine prefix can be deleted. Besides the checks of [`is_safe_to_remove`],
+/// it has to contain a `drive_c` and a `system.reg`, directly or in Proton's `pfx` directory.
+pub(crate) fn is_safe_to_remove_prefix(path: &Path) -> Result<bool, NativeError> {
+    if !is_removable_dir(path)? {
+        return Ok(false);
+    }
+
+    Ok([path.to_path_buf(), path.join("pfx")]
+        .iter()
+        .any(|x| x.join("drive_c").is_dir() && x.join("system.reg").is_file()))
+}
+
+/// Rejects relative and shallow paths, and anything containing the maxima or home directory
+fn is_removable_dir(path: &Path) -> Result<bool, NativeError> {
+    if !path.is_absolute() || path.components().count() < 3 {
+        return Ok(false);
+    }
+
+    if maxima_dir()?.starts_with(path) {
+        return Ok(false);
+    }
+
+    if let Some(dirs) = directories::BaseDirs::new() {
+        if dirs.home_dir().starts_with(path) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,62 @@
+use crate::core::{
+    library::OwnedOffer, manifest::dip::DiPFeatureFlags, service_layer::ServiceAvailableBuild,
+};
+
+/// A newer live build of an installed game
+#[derive(Debug, Clone)]
+pub struct AvailableUpdate {
+    pub offer_id: String,
+    pub slug: String,
+    pub installed_version: String,
+    pub available_version: String,
+    pub build_id: String,
+    /// The game shouldn't be played until the update has been installed
+    pub mandatory: bool,
+    /// The game wants updates to be installed without asking the user
+    pub auto_update: bool,
+}
+
+impl AvailableUpdate {
+    /// Returns `None` when the installed build is already the live one
+    pub(crate) fn new(
+        offer: &OwnedOffer,
+        installed_version: String,
+        installed_build: Option<&str>,
+        live: &ServiceAvailableBuild,
+        flags: Option<DiPFeatureFlags>,
+    ) -> Option<Self> {
+        let available_version = live.game_version().as_ref()?.to_owned();
+
+        // Build IDs are only known for games installed by Maxima, versions are a fallback
+        let up_to_date = match installed_build {
+            Some(build_id) => build_id == live.build_id(),
+            None => installed_version == available_version,
+        };
+
+        if up_to_date {
+            return None;
+        }
+
+        // Pre-DiP manifests don't have feature flags, the offer still knows whether updates are mandatory
+        let mandatory = match &flags {
+            Some(flags) => flags.attr_treatUpdatesAsMandatory,
+            None => offer
+                .offer()
+                .downloads()
+                .iter()
+                .find(|x| x.download_type() == "LIVE")
+                .map(|x| *x.treat_updates_as_mandatory())
+                .unwrap_or(false),
+        };
+
+        Some(Self {
+            offer_id: offer.offer_id().to_owned(),
+            slug: offer.slug().to_owned(),
+            installed_version,
+            available_version,
+            build_id: live.build_id().to_owned(),
+            mandatory,
+            auto_update: flags.map(|x| x.attr_autoUpdateEnabled).unwrap_or(false),
+        })
+    }
+}
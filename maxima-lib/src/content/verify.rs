@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use log::warn;
+
+use crate::{content::zip::ZipFileEntry, util::hash::hash_file_crc32};
+
+/// Amount of files hashed at the same time
+const VERIFY_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileVerifyStatus {
+    /// The file on disk matches the central directory
+    Ok,
+    /// The file does not exist on disk
+    Missing,
+    SizeMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The file couldn't be read, so it's unknown whether it matches
+    Error(String),
+    /// The file was bad, but has been downloaded again and now matches
+    Repaired,
+    /// The file was bad, and downloading it again didn't fix it
+    RepairFailed(String),
+}
+
+impl FileVerifyStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, FileVerifyStatus::Ok | FileVerifyStatus::Repaired)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileVerifyResult {
+    pub name: String,
+    pub status: FileVerifyStatus,
+}
+
+/// Checks a single installed file against its central directory entry. Size is checked
+/// first so that truncated files don't need to be hashed.
+pub async fn verify_entry(root: &Path, entry: &ZipFileEntry) -> FileVerifyStatus {
+    let path = root.join(entry.name());
+    let expected_size = *entry.uncompressed_size() as u64;
+
+    let actual_size = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return FileVerifyStatus::Missing,
+        Err(err) => return FileVerifyStatus::Error(err.to_string()),
+    };
+
+    if actual_size != expected_size {
+        return FileVerifyStatus::SizeMismatch {
+            expected: expected_size,
+            actual: actual_size,
+        };
+    }
+
+    let expected_crc = *entry.crc32();
+    let hash_path: PathBuf = path.clone();
+    let actual_crc = match tokio::task::spawn_blocking(move || hash_file_crc32(hash_path)).await {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(err)) => {
+            warn!("Failed to hash {}: {}", path.display(), err);
+            return FileVerifyStatus::Error(err.to_string());
+        }
+        Err(err) => {
+            warn!("Hashing task for {} failed: {}", path.display(), err);
+            return FileVerifyStatus::Error(err.to_string());
+        }
+    };
+
+    if actual_crc != expected_crc {
+        return FileVerifyStatus::CrcMismatch {
+            expected: expected_crc,
+            actual: actual_crc,
+        };
+    }
+
+    FileVerifyStatus::Ok
+}
+
+/// Verifies all given entries against the files installed in `root`, hashing several files in parallel.
+/// Directory entries are skipped. Results are returned in the same order as `entries`.
+pub async fn verify_entries(root: &Path, entries: &[ZipFileEntry]) -> Vec<FileVerifyResult> {
+    futures::stream::iter(entries.iter().filter(|x| !x.name().ends_with('/')))
+        .map(|entry| async move {
+            FileVerifyResult {
+                name: entry.name().to_owned(),
+                status: verify_entry(root, entry).await,
+            }
+        })
+        .buffered(VERIFY_CONCURRENCY)
+        .collect()
+        .await
+}
@@ -1,13 +1,17 @@
 use bytebuffer::{ByteBuffer, Endian};
 use derive_getters::Getters;
 use encoding::{all::WINDOWS_1252, DecoderTrap, Encoding};
+use futures::{StreamExt, TryStreamExt};
 use log::{debug, warn};
 use reqwest::header::ToStrError;
-use reqwest::Client;
 use std::cmp;
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
+use crate::content::source::{source_for, ByteSource, SourceError};
+use crate::content::uninstall::is_contained;
+use crate::core::manifest::MANIFEST_RELATIVE_PATH;
+
 /// This module is based on https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
 
 const ZIP_EOCD_SIGNATURE: u32 = 0x06054b50;
@@ -20,6 +24,25 @@ const ZIP_EOCD_FIXED_PART_SIZE: u32 = 22;
 const ZIP64_EOCD_FIXED_PART_SIZE: u32 = 56;
 
 const ZIP_FILE_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP_LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+
+const ZIP_LOCAL_FILE_HEADER_FIXED_PART_SIZE: u64 = 30;
+
+/// Set when LZMA data ends with an end of stream marker, instead of relying on the size
+const ZIP_FLAG_LZMA_END_MARKER: u16 = 1 << 1;
+/// Set when the sizes and CRC32 follow the data instead of being in the local file header
+const ZIP_FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const ZIP_FLAG_UTF8: u16 = 1 << 11;
+
+/// Local file headers at most this far apart are read with a single range request, reading the
+/// data in between is cheaper than another request
+const LOCAL_HEADER_BATCH_GAP: i64 = 64 * 1024;
+/// Largest range read for a batch of local file headers
+const LOCAL_HEADER_BATCH_SPAN: i64 = 1024 * 1024;
+/// Amount of local file header batches read at the same time
+const LOCAL_HEADER_BATCH_CONCURRENCY: usize = 16;
+/// Amount of lone local file headers read at the same time, they're only 30 bytes each
+const LOCAL_HEADER_CONCURRENCY: usize = 64;
 
 const MAX_BACKSCAN_OFFSET: usize = 6 * 1024 * 1024;
 
@@ -59,6 +82,8 @@ pub enum ZipError {
     Utf8(#[from] FromUtf8Error),
     #[error(transparent)]
     Eocd(#[from] EOCDError),
+    #[error(transparent)]
+    Source(#[from] SourceError),
 
     #[error("content-length > 8192")]
     ContentTooLong,
@@ -78,6 +103,14 @@ pub enum ZipError {
     ReadTooBig { attempted: i64, max: i64 },
     #[error("invalid signature {0:#10x}")]
     Signature(u32),
+    #[error("invalid local file header signature {signature:#10x} for `{name}`")]
+    LocalHeaderSignature { name: String, signature: u32 },
+    #[error("local file header of `{0}` is out of bounds")]
+    LocalHeaderBounds(String),
+    #[error("archive refers to disk {disk}, but only {available} disks were found")]
+    MissingDisk { disk: u32, available: usize },
+    #[error("refusing to extract `{0}`, it points outside the install directory")]
+    UnsafeEntryName(String),
 }
 
 fn signature_scan_rev(data: &[u8], signature: u32) -> Option<usize> {
@@ -98,15 +131,26 @@ fn signature_scan_rev(data: &[u8], signature: u32) -> Option<usize> {
 #[derive(Default, Clone, Debug, PartialEq)]
 pub enum CompressionType {
     #[default]
-    None = 0,
-    Deflate = 8,
+    None,
+    Deflate,
+    Deflate64,
+    BZip2,
+    Lzma,
+    Zstd,
+    /// A method we can't decompress, downloading the entry fails
+    Unknown(u16),
 }
 
 impl CompressionType {
     pub fn from_num(num: u16) -> CompressionType {
         match num {
+            0 => CompressionType::None,
             8 => CompressionType::Deflate,
-            0 | _ => CompressionType::None,
+            9 => CompressionType::Deflate64,
+            12 => CompressionType::BZip2,
+            14 => CompressionType::Lzma,
+            93 => CompressionType::Zstd,
+            method => CompressionType::Unknown(method),
         }
     }
 }
@@ -118,9 +162,16 @@ pub struct ZipFileEntry {
     compression_type: CompressionType,
     compressed_size: i64,
     uncompressed_size: i64,
-    disk_number_start: u16,
+    disk_number_start: u32,
+    /// Offset of the local file header from the start of the first disk
     local_header_offset: i64,
+    /// Offset of the data from the start of the first disk, read from the local file header
     data_offset: i64,
+    /// The sizes and CRC32 follow the data. The central directory has them too, so they're
+    /// taken from there instead.
+    data_descriptor: bool,
+    /// Only meaningful for LZMA, see `ZIP_FLAG_LZMA_END_MARKER`
+    lzma_end_marker: bool,
 
     #[getter(skip)]
     extra_field: Vec<u8>,
@@ -138,7 +189,9 @@ impl ZipFileEntry {
         data.read_u16()?; // Version
         data.read_u16()?; // Vers. needed
         let flags = data.read_u16()?;
-        let use_utf8 = flags & (1 << 11) != 0;
+        let use_utf8 = flags & ZIP_FLAG_UTF8 != 0;
+        entry.data_descriptor = flags & ZIP_FLAG_DATA_DESCRIPTOR != 0;
+        entry.lzma_end_marker = flags & ZIP_FLAG_LZMA_END_MARKER != 0;
 
         entry.compression_type = CompressionType::from_num(data.read_u16()?);
 
@@ -153,7 +206,7 @@ impl ZipFileEntry {
         let extra_field_len = data.read_u16()?;
         let file_comment_len = data.read_u16()?;
 
-        entry.disk_number_start = data.read_u16()?;
+        entry.disk_number_start = data.read_u16()? as u32;
 
         data.read_u16()?; // Internal attr.
         data.read_u32()?; // External attr.
@@ -187,6 +240,10 @@ impl ZipFileEntry {
             if entry.local_header_offset == 0xFFFFFFFF {
                 entry.local_header_offset = data.read_i64()?;
             }
+
+            if entry.disk_number_start == 0xFFFF {
+                entry.disk_number_start = data.read_u32()?;
+            }
         }
 
         data.set_rpos(data.get_rpos() + file_comment_len as usize);
@@ -198,23 +255,19 @@ impl ZipFileEntry {
         let mut data = ByteBuffer::from_vec(self.extra_field.clone());
         data.set_endian(Endian::LittleEndian);
 
-        loop {
+        while data.len() - data.get_rpos() >= 4 {
             let id2 = data.read_u16()?;
             let size = data.read_u16()? as usize;
 
-            if id == id2 {
-                if data.len() - data.get_rpos() >= size {
-                    return Ok(data.read_bytes(size)?);
-                }
-
+            if data.len() - data.get_rpos() < size {
                 break;
             }
 
-            data.set_rpos(size);
-
-            if data.len() - data.get_rpos() > 0 {
-                break;
+            if id == id2 {
+                return Ok(data.read_bytes(size)?);
             }
+
+            data.set_rpos(data.get_rpos() + size);
         }
 
         Err(ZipError::ExtraField {
@@ -227,6 +280,10 @@ impl ZipFileEntry {
 #[derive(Default, Getters)]
 pub struct ZipFile {
     entries: Vec<ZipFileEntry>,
+
+    /// Where each disk starts in the source, see `ByteSource::disk_starts`
+    #[getter(skip)]
+    disk_starts: Vec<u64>,
 }
 
 #[derive(Default)]
@@ -297,16 +354,12 @@ impl EndOfCentralDirectory {
 
 impl ZipFile {
     pub async fn fetch(url: &str) -> Result<Self, ZipError> {
-        let client = Client::new();
+        let source = source_for(url)?;
+        Self::fetch_from(source.as_ref()).await
+    }
 
-        let response = client.head(url).send().await?;
-        let content_length = response
-            .headers()
-            .get("content-length")
-            .ok_or(ZipError::NoContentLength)?
-            .to_str()?
-            .parse::<i64>()
-            .unwrap_or(0);
+    pub async fn fetch_from(source: &dyn ByteSource) -> Result<Self, ZipError> {
+        let content_length = source.len().await? as i64;
 
         let mut data: Vec<u8> = Vec::with_capacity(MAX_BACKSCAN_OFFSET);
         let mut offset = content_length - 8 * 1024;
@@ -314,20 +367,20 @@ impl ZipFile {
             return Err(ZipError::ContentTooLong);
         }
 
-        let mut zip = Self::default();
+        let mut zip = Self {
+            disk_starts: source.disk_starts().await?,
+            ..Default::default()
+        };
 
         while offset > 0 && data.len() < MAX_BACKSCAN_OFFSET {
             let read = content_length - offset - data.len() as i64;
             let start_offset = content_length - data.len() as i64 - read;
             let end_offset = start_offset + read;
 
-            let range_header = format!("bytes={}-{}", start_offset, end_offset - 1);
-            let response = client
-                .get(url)
-                .header("range", &range_header)
-                .send()
-                .await?;
-            let this_data = response.bytes().await?.to_vec();
+            let this_data = source
+                .read_range_bytes(start_offset as u64, end_offset as u64)
+                .await?
+                .to_vec();
             data = [this_data, data].concat();
 
             offset = zip.load(&mut ByteBuffer::from_vec(data.clone()), content_length)?;
@@ -339,9 +392,76 @@ impl ZipFile {
             }
         }
 
+        zip.resolve_package_layout()?;
+        zip.load_data_offsets(source).await?;
+
         Ok(zip)
     }
 
+    /// Packages from before Download-In-Place don't always follow the layout of DiP builds.
+    /// Their names can use backslashes, and the whole game can be inside one directory with
+    /// the manifest at `<dir>/__Installer/installerdata.xml`. Entries are renamed to be
+    /// relative to the install directory, like the ones of DiP builds. Names that would end
+    /// up outside of the install directory fail the whole archive.
+    fn resolve_package_layout(&mut self) -> Result<(), ZipError> {
+        for entry in self.entries.iter_mut().filter(|x| x.name.contains('\\')) {
+            entry.name = entry.name.replace('\\', "/");
+        }
+
+        if let Some(entry) = self.entries.iter().find(|x| !is_contained(&x.name)) {
+            return Err(ZipError::UnsafeEntryName(entry.name.clone()));
+        }
+
+        let Some(root) = self.package_root() else {
+            return Ok(());
+        };
+
+        debug!(
+            "Package is inside {}, leaving it out of the file names",
+            root
+        );
+        // Every entry is inside the root, only the directory itself is left out
+        self.entries
+            .retain_mut(|entry| match entry.name.strip_prefix(&root) {
+                Some(name) if !name.is_empty() => {
+                    entry.name = name.to_owned();
+                    true
+                }
+                _ => false,
+            });
+
+        Ok(())
+    }
+
+    /// Directory everything in the package is in, with a trailing slash. `None` for packages
+    /// laid out like the install directory.
+    fn package_root(&self) -> Option<String> {
+        let is_manifest = |name: &str| name.eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH);
+        if self.entries.iter().any(|x| is_manifest(&x.name)) {
+            return None;
+        }
+
+        let root = self.entries.iter().find_map(|x| {
+            let (root, rest) = x.name.split_once('/')?;
+            is_manifest(rest).then(|| format!("{}/", root))
+        })?;
+
+        self.entries
+            .iter()
+            .all(|x| x.name.starts_with(&root))
+            .then_some(root)
+    }
+
+    fn disk_start(&self, disk: u32) -> Result<i64, ZipError> {
+        self.disk_starts
+            .get(disk as usize)
+            .map(|start| *start as i64)
+            .ok_or(ZipError::MissingDisk {
+                disk,
+                available: self.disk_starts.len(),
+            })
+    }
+
     fn load(&mut self, data: &mut ByteBuffer, total_size: i64) -> Result<i64, ZipError> {
         data.set_endian(Endian::LittleEndian);
 
@@ -369,8 +489,8 @@ impl ZipFile {
                 return Err(ZipError::Signature(signature));
             }
 
-            data.read_u32()?; // Disk that contains EOCD
-            let zip64_eocd_offset = data.read_i64()?;
+            let zip64_eocd_disk = data.read_u32()?;
+            let zip64_eocd_offset = data.read_i64()? + self.disk_start(zip64_eocd_disk)?;
             data.read_u32()?; // Disk count
 
             let pos2 = zip64_eocd_offset - (total_size - data.len() as i64);
@@ -389,6 +509,10 @@ impl ZipFile {
             return Err(ZipError::CentralDirectoryEndGeneric);
         }
 
+        // The last disk is the one with the end of central directory
+        self.disk_start(eocd.disk_number)?;
+        eocd.cd_offset += self.disk_start(eocd.disk_number_with_cd)?;
+
         if data.len() < (total_size - eocd.cd_offset) as usize {
             if eocd.cd_offset < total_size {
                 return Ok(eocd.cd_offset);
@@ -412,7 +536,7 @@ impl ZipFile {
         eocd: EndOfCentralDirectory,
     ) -> Result<(), ZipError> {
         for i in 0..eocd.total_entries {
-            let entry = match ZipFileEntry::parse(data) {
+            let mut entry = match ZipFileEntry::parse(data) {
                 Err(err) => {
                     return Err(ZipError::CentralDirectory {
                         idx: i,
@@ -423,30 +547,101 @@ impl ZipFile {
                 Ok(e) => e,
             };
 
-            self.entries.push(entry.clone());
+            entry.local_header_offset += self.disk_start(entry.disk_number_start)?;
+            self.entries.push(entry);
+        }
+
+        Ok(())
+    }
 
-            if i == 0 {
-                continue;
+    /// Reads the local file header of every entry to find where its data starts. The name
+    /// and extra field there can differ from the central directory, so there's no other way
+    /// to know for sure. Headers of small files next to each other are read together, the
+    /// others on their own.
+    async fn load_data_offsets(&mut self, source: &dyn ByteSource) -> Result<(), ZipError> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|idx| self.entries[*idx].local_header_offset);
+
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for idx in order {
+            let offset = self.entries[idx].local_header_offset;
+            match batches.last_mut() {
+                Some(batch)
+                    if offset - self.entries[*batch.last().unwrap()].local_header_offset
+                        < LOCAL_HEADER_BATCH_GAP
+                        && offset - self.entries[batch[0]].local_header_offset
+                            < LOCAL_HEADER_BATCH_SPAN =>
+                {
+                    batch.push(idx)
+                }
+                _ => batches.push(vec![idx]),
             }
+        }
 
-            let prev = if let Some(prev) = self.entries.get_mut(i as usize - 1) {
-                prev
-            } else {
-                continue;
-            };
+        let (batches, singles): (Vec<_>, Vec<_>) = batches.into_iter().partition(|x| x.len() > 1);
+
+        let entries = &self.entries;
+        let mut offsets: Vec<Vec<(usize, i64)>> = futures::stream::iter(batches)
+            .map(|batch| Self::read_local_headers(source, entries, batch))
+            .buffer_unordered(LOCAL_HEADER_BATCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        let single_offsets: Vec<Vec<(usize, i64)>> = futures::stream::iter(singles)
+            .map(|batch| Self::read_local_headers(source, entries, batch))
+            .buffer_unordered(LOCAL_HEADER_CONCURRENCY)
+            .try_collect()
+            .await?;
+        offsets.extend(single_offsets);
+
+        for (idx, data_offset) in offsets.into_iter().flatten() {
+            self.entries[idx].data_offset = data_offset;
+        }
+
+        Ok(())
+    }
+
+    async fn read_local_headers(
+        source: &dyn ByteSource,
+        entries: &[ZipFileEntry],
+        batch: Vec<usize>,
+    ) -> Result<Vec<(usize, i64)>, ZipError> {
+        let start = entries[batch[0]].local_header_offset;
+        let end = entries[*batch.last().unwrap()].local_header_offset
+            + ZIP_LOCAL_FILE_HEADER_FIXED_PART_SIZE as i64;
+
+        let bytes = source.read_range_bytes(start as u64, end as u64).await?;
+        let mut data = ByteBuffer::from_vec(bytes.to_vec());
+        data.set_endian(Endian::LittleEndian);
 
-            if prev.disk_number_start != entry.disk_number_start {
-                warn!("Data offset could not be calculated");
-                continue;
+        let mut offsets = Vec::with_capacity(batch.len());
+        for idx in batch {
+            let entry = &entries[idx];
+            let pos = (entry.local_header_offset - start) as usize;
+            if pos + ZIP_LOCAL_FILE_HEADER_FIXED_PART_SIZE as usize > data.len() {
+                return Err(ZipError::LocalHeaderBounds(entry.name.clone()));
             }
 
-            prev.data_offset = entry.local_header_offset - prev.compressed_size;
-        }
+            data.set_rpos(pos);
+            let signature = data.read_u32()?;
+            if signature != ZIP_LOCAL_FILE_HEADER_SIGNATURE {
+                return Err(ZipError::LocalHeaderSignature {
+                    name: entry.name.clone(),
+                    signature,
+                });
+            }
+
+            // Skip to the lengths, everything else is in the central directory as well
+            data.set_rpos(pos + 26);
+            let file_name_len = data.read_u16()? as i64;
+            let extra_field_len = data.read_u16()? as i64;
 
-        if let Some(last_entry) = self.entries.last_mut() {
-            last_entry.data_offset = eocd.cd_offset - last_entry.compressed_size;
+            let data_offset = entry.local_header_offset
+                + ZIP_LOCAL_FILE_HEADER_FIXED_PART_SIZE as i64
+                + file_name_len
+                + extra_field_len;
+            offsets.push((idx, data_offset));
         }
 
-        Ok(())
+        Ok(offsets)
     }
 }
@@ -2,7 +2,8 @@ use std::{any::Any, borrow::Borrow, hash::Hash, sync::Arc, time::Duration};
 
 use moka::sync::Cache;
 
-/// Note that values are cloned when retrieved
+/// Note that values are cloned when retrieved. Clones share the same underlying cache
+#[derive(Clone)]
 pub struct DynamicCache<K> {
     cache: Cache<K, Arc<dyn Any + Sync + Send>>,
 }
@@ -451,12 +451,16 @@ pub async fn mx_linux_setup(slug: Option<&str>) -> Result<(), NativeError> {
     }
 
     setup_wine_registry(slug).await?;
-    let install_path = load_game_info_from_json(slug.unwrap()).unwrap().path;
-    let manifest = manifest::read(install_path.join(MANIFEST_RELATIVE_PATH))
-        .await
-        .unwrap();
-
-    let _result = manifest.run_touchup(&install_path, slug.unwrap()).await;
+    let install_info = load_game_info_from_json(slug.unwrap()).unwrap();
+    let install_path = install_info.path();
+    // Some pre-DiP builds have no manifest, and nothing to set up
+    let manifest_path = case_insensitive_path(install_path.join(MANIFEST_RELATIVE_PATH));
+    if manifest_path.exists() {
+        let manifest = manifest::read(manifest_path).await.unwrap();
+        let _result = manifest
+            .run_touchup(&install_path, slug.unwrap(), install_info.locale())
+            .await;
+    }
 
     Ok(())
 }
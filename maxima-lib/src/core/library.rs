@@ -114,23 +114,23 @@ impl OwnedOffer {
         .to_owned())
     }
 
+    /// Pre-DiP manifests don't have the executable, and some pre-DiP builds have no manifest
+    /// at all. Those are started with the `execute_path_override` of the offer.
     pub async fn execute_path(&self, trial: bool) -> Result<PathBuf, LibraryError> {
-        let manifest = match self.local_manifest().await? {
-            Some(manifest) => manifest,
-            None => return Err(LibraryError::NoManifest(self.slug.clone())),
-        };
+        let manifest = self.local_manifest().await?;
 
-        let path = if let Some(path) = manifest.execute_path(trial) {
-            &Some(path)
-        } else {
-            self.offer.execute_path_override()
+        let path = match manifest.as_ref().and_then(|x| x.execute_path(trial)) {
+            Some(path) => path,
+            None => match self.offer.execute_path_override() {
+                Some(path) => path.clone(),
+                None if manifest.is_none() => {
+                    return Err(LibraryError::NoManifest(self.slug.clone()))
+                }
+                None => return Err(LibraryError::NoPath(self.slug.clone())),
+            },
         };
 
-        if let Some(path) = path {
-            Ok(parse_registry_path_json(path, Some(&self.slug)).await?)
-        } else {
-            Err(LibraryError::NoPath(self.slug.clone()))
-        }
+        Ok(parse_registry_path_json(&path, Some(&self.slug)).await?)
     }
 
     pub async fn installed_version(&self) -> Result<String, LibraryError> {
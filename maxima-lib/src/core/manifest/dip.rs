@@ -128,12 +128,113 @@ fn remove_trailing_backslash(path: &str) -> &str {
     path.strip_suffix('\\').unwrap_or(path)
 }
 
+/// Makes a file pattern relative to the install directory, with forward slashes. Patterns
+/// naming a directory cover everything in it.
+fn file_pattern(pattern: &str) -> String {
+    let pattern = pattern.trim().replace('\\', "/");
+    let pattern = remove_leading_slash(&pattern);
+    if pattern.ends_with('/') {
+        format!("{}**", pattern)
+    } else {
+        pattern.to_owned()
+    }
+}
+
 impl DiPTouchup {
     pub fn path(&self) -> &str {
         remove_leading_slash(&self.file_path)
     }
 }
 
+dip_type!(
+    Languages;
+    attr {},
+    data {
+        #[serde(default)]
+        language: Vec<String>,
+    }
+);
+
+dip_type!(
+    /// Files that are only installed for some languages
+    Include;
+    attr {
+        /// Comma separated locales, like `de_DE,fr_FR`
+        #[serde(default)]
+        languages: String,
+    },
+    data {
+        #[serde(default)]
+        filePattern: Vec<String>,
+    }
+);
+
+dip_type!(
+    Includes;
+    attr {},
+    data {
+        #[serde(default)]
+        include: Vec<DiPInclude>,
+    }
+);
+
+dip_type!(
+    InstallMetaData;
+    attr {},
+    data {
+        #[serde(default)]
+        includes: DiPIncludes,
+    }
+);
+
+impl DiPInclude {
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.attr_languages
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = String> + '_ {
+        self.filePattern.iter().map(|x| file_pattern(x))
+    }
+}
+
+dip_type!(
+    /// A part of a progressive install. The game can be started once every required chunk
+    /// is installed, the rest is downloaded while it runs.
+    Chunk;
+    attr {
+        id: u32,
+        #[serde(default)]
+        name: String,
+        /// Lower priorities are downloaded first
+        #[serde(default)]
+        priority: u32,
+        #[serde(default)]
+        required: bool,
+    },
+    data {
+        #[serde(default)]
+        filePattern: Vec<String>,
+    }
+);
+
+dip_type!(
+    Chunks;
+    attr {},
+    data {
+        #[serde(default)]
+        chunk: Vec<DiPChunk>,
+    }
+);
+
+impl DiPChunk {
+    pub fn patterns(&self) -> impl Iterator<Item = String> + '_ {
+        self.filePattern.iter().map(|x| file_pattern(x))
+    }
+}
+
 dip_type!(
     Manifest;
     attr {
@@ -141,6 +242,12 @@ dip_type!(
     },
     data {
         buildMetaData: DiPBuildMetaData,
+        #[serde(default)]
+        chunks: DiPChunks,
+        #[serde(default)]
+        installMetaData: DiPInstallMetaData,
+        #[serde(default)]
+        languages: DiPLanguages,
         runtime: DiPRuntime,
         touchup: DiPTouchup,
     }
@@ -175,12 +282,67 @@ fn bytes_to_string(bytes: Vec<u8>) -> Option<String> {
 
 impl DiPManifest {
     pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
-        let bytes = tokio::fs::read(path).await?;
-        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+        Self::parse(tokio::fs::read(path).await?)
+    }
 
+    /// Parses a manifest that isn't on disk yet, like the one in a build archive
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, ManifestError> {
+        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
         Ok(quick_xml::de::from_str(&string)?)
     }
 
+    /// Locales the game can be installed in. Empty if the manifest doesn't say.
+    pub fn supported_languages(&self) -> &Vec<String> {
+        &self.languages.language
+    }
+
+    /// Chunks of the progressive install, in the order they should be downloaded. Empty for
+    /// games that can't be played before they're fully installed.
+    pub fn progressive_chunks(&self) -> Vec<&DiPChunk> {
+        let mut chunks: Vec<&DiPChunk> = self.chunks.chunk.iter().collect();
+        chunks.sort_by_key(|x| (x.attr_priority, x.attr_id));
+        chunks
+    }
+
+    /// Patterns of the files that belong to none of the `selected` languages, and can be left
+    /// out of the install. Nothing is left out when no languages are selected. Patterns can
+    /// overlap, files that are also in `language_inclusions` have to be kept.
+    pub fn language_exclusions(&self, selected: &[String]) -> Vec<String> {
+        if selected.is_empty() {
+            return Vec::new();
+        }
+
+        self.installMetaData
+            .includes
+            .include
+            .iter()
+            .filter(|include| {
+                include.languages().next().is_some() && !Self::is_selected(include, selected)
+            })
+            .flat_map(|include| include.patterns())
+            .collect()
+    }
+
+    /// Patterns of the files needed by the `selected` languages, including the ones every
+    /// language needs
+    pub fn language_inclusions(&self, selected: &[String]) -> Vec<String> {
+        self.installMetaData
+            .includes
+            .include
+            .iter()
+            .filter(|include| {
+                include.languages().next().is_none() || Self::is_selected(include, selected)
+            })
+            .flat_map(|include| include.patterns())
+            .collect()
+    }
+
+    fn is_selected(include: &DiPInclude, selected: &[String]) -> bool {
+        include
+            .languages()
+            .any(|lang| selected.iter().any(|x| x.eq_ignore_ascii_case(lang)))
+    }
+
     pub fn execute_path(&self, trial: bool) -> Option<String> {
         let launcher = self.runtime.launcher.iter().find(|l| l.trial == trial);
         launcher.map(|l| l.file_path.clone())
@@ -195,6 +357,7 @@ impl DiPManifest {
         &self,
         install_path: &PathBuf,
         slug: &str,
+        locale: &str,
     ) -> Result<(), ManifestError> {
         use crate::unix::{
             fs::case_insensitive_path,
@@ -204,7 +367,7 @@ impl DiPManifest {
         let install_path = PathBuf::from(remove_trailing_slash(
             install_path.to_str().ok_or(ManifestError::Decode)?,
         ));
-        let args = self.collect_touchup_args(&install_path)?;
+        let args = self.collect_touchup_args(&install_path, locale)?;
         let path = install_path.join(&self.touchup.path());
         let path = case_insensitive_path(path);
         run_wine_command(path, Some(args), None, true, CommandType::Run, Some(slug)).await?;
@@ -218,11 +381,12 @@ impl DiPManifest {
         &self,
         install_path: &PathBuf,
         _slug: &str,
+        locale: &str,
     ) -> Result<(), ManifestError> {
         use crate::util::native::NativeError;
         use tokio::process::Command;
 
-        let args = self.collect_touchup_args(install_path)?;
+        let args = self.collect_touchup_args(install_path, locale)?;
         let path = install_path.join(&self.touchup.path());
 
         let mut binding = Command::new(path);
@@ -238,10 +402,14 @@ impl DiPManifest {
         Ok(())
     }
 
-    fn collect_touchup_args(&self, install_path: &PathBuf) -> Result<Vec<PathBuf>, ManifestError> {
+    fn collect_touchup_args(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<Vec<PathBuf>, ManifestError> {
         let mut args = Vec::new();
         for arg in self.touchup.parameters.split(" ") {
-            let arg = arg.replace("{locale}", "en_US").replace(
+            let arg = arg.replace("{locale}", locale).replace(
                 "\"{installLocation}\"",
                 platform_path(
                     remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
@@ -1,7 +1,7 @@
 pub mod dip;
 pub mod pre_dip;
 
-use dip::DiPManifest;
+use dip::{DiPFeatureFlags, DiPManifest};
 use pre_dip::PreDiPManifest;
 use quick_xml::DeError;
 use std::path::PathBuf;
@@ -30,17 +30,32 @@ pub enum ManifestError {
 }
 
 pub const MANIFEST_RELATIVE_PATH: &str = "__Installer/installerdata.xml";
+/// Locale touchup is run with when no language was picked
+pub const DEFAULT_LOCALE: &str = "en_US";
 
 #[async_trait::async_trait]
 pub trait GameManifest: Send + std::fmt::Debug {
-    async fn run_touchup(&self, install_path: &PathBuf, slug: &str) -> Result<(), ManifestError>;
+    async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        slug: &str,
+        locale: &str,
+    ) -> Result<(), ManifestError>;
     fn execute_path(&self, trial: bool) -> Option<String>;
     fn version(&self) -> Option<String>;
+    fn feature_flags(&self) -> Option<DiPFeatureFlags>;
+    /// Whether the game can be started before every file is installed
+    fn progressive_install(&self) -> bool;
 }
 #[async_trait::async_trait]
 impl GameManifest for DiPManifest {
-    async fn run_touchup(&self, install_path: &PathBuf, slug: &str) -> Result<(), ManifestError> {
-        self.run_touchup(install_path, slug).await
+    async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        slug: &str,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
+        self.run_touchup(install_path, slug, locale).await
     }
 
     fn execute_path(&self, trial: bool) -> Option<String> {
@@ -50,12 +65,25 @@ impl GameManifest for DiPManifest {
     fn version(&self) -> Option<String> {
         self.version()
     }
+
+    fn feature_flags(&self) -> Option<DiPFeatureFlags> {
+        Some(self.buildMetaData.featureFlags.clone())
+    }
+
+    fn progressive_install(&self) -> bool {
+        !self.chunks.chunk.is_empty()
+    }
 }
 
 #[async_trait::async_trait]
 impl GameManifest for PreDiPManifest {
-    async fn run_touchup(&self, install_path: &PathBuf, slug: &str) -> Result<(), ManifestError> {
-        self.run_touchup(install_path, slug).await
+    async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        slug: &str,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
+        self.run_touchup(install_path, slug, locale).await
     }
 
     fn execute_path(&self, _: bool) -> Option<String> {
@@ -65,6 +93,14 @@ impl GameManifest for PreDiPManifest {
     fn version(&self) -> Option<String> {
         self.version()
     }
+
+    fn feature_flags(&self) -> Option<DiPFeatureFlags> {
+        None
+    }
+
+    fn progressive_install(&self) -> bool {
+        false
+    }
 }
 
 pub async fn read(path: PathBuf) -> Result<Box<dyn GameManifest>, ManifestError> {
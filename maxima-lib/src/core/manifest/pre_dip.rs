@@ -70,6 +70,9 @@ predip_type!(
         manifestVersion: String,
     },
     data {
+        /// Installer that sets the game up once its files are in place. Packages that
+        /// need no setup leave it out.
+        #[serde(default)]
         executable: PreDiPExecutable,
     }
 );
@@ -95,12 +98,27 @@ fn bytes_to_string(bytes: Vec<u8>) -> Option<String> {
 
 impl PreDiPManifest {
     pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
-        let bytes = tokio::fs::read(path).await?;
-        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+        Self::parse(tokio::fs::read(path).await?)
+    }
 
+    /// Parses a manifest that isn't on disk yet, like the one in a build archive
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, ManifestError> {
+        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
         Ok(quick_xml::de::from_str(&string)?)
     }
 
+    /// Where the installer is in `install_path`. The manifest has it relative to the install
+    /// directory, with a leading slash and Windows separators.
+    fn installer_path(&self, install_path: &PathBuf) -> Option<PathBuf> {
+        let file_path = self.executable.file_path.trim().replace('\\', "/");
+        let file_path = remove_leading_slash(&file_path);
+        if file_path.is_empty() {
+            return None;
+        }
+
+        Some(install_path.join(file_path))
+    }
+
     pub fn version(&self) -> Option<String> {
         Some(self.attr_gameVersion.clone())
     }
@@ -110,6 +128,7 @@ impl PreDiPManifest {
         &self,
         install_path: &PathBuf,
         slug: &str,
+        locale: &str,
     ) -> Result<(), ManifestError> {
         use crate::unix::{
             fs::case_insensitive_path,
@@ -119,9 +138,11 @@ impl PreDiPManifest {
         let install_path = PathBuf::from(remove_trailing_slash(
             install_path.to_str().ok_or(ManifestError::Decode)?,
         ));
-        let args = self.collect_touchup_args(&install_path)?;
+        let Some(path) = self.installer_path(&install_path) else {
+            return Ok(());
+        };
 
-        let path = install_path.join(remove_leading_slash(&self.executable.file_path));
+        let args = self.collect_touchup_args(&install_path, locale)?;
         let path = case_insensitive_path(path);
         run_wine_command(path, Some(args), None, true, CommandType::Run, Some(slug)).await?;
 
@@ -134,12 +155,16 @@ impl PreDiPManifest {
         &self,
         install_path: &PathBuf,
         _slug: &str,
+        locale: &str,
     ) -> Result<(), ManifestError> {
         use crate::util::native::NativeError;
         use tokio::process::Command;
 
-        let args = self.collect_touchup_args(install_path)?;
-        let path = install_path.join(&self.executable.file_path);
+        let Some(path) = self.installer_path(install_path) else {
+            return Ok(());
+        };
+
+        let args = self.collect_touchup_args(install_path, locale)?;
 
         let mut binding = Command::new(path);
         let child = binding.args(args);
@@ -154,18 +179,24 @@ impl PreDiPManifest {
         Ok(())
     }
 
-    fn collect_touchup_args(&self, install_path: &PathBuf) -> Result<Vec<PathBuf>, ManifestError> {
+    fn collect_touchup_args(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<Vec<PathBuf>, ManifestError> {
+        let install_location = platform_path(
+            remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
+                .replace("/", "\\"),
+        );
+        let install_location = install_location.to_str().ok_or(ManifestError::Decode)?;
+
         let mut args = Vec::new();
-        for arg in self.executable.parameters.split(" ") {
-            let arg = arg.replace("{locale}", "en_US").replace(
-                "\"{installLocation}\"",
-                platform_path(
-                    remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
-                        .replace("/", "\\"),
-                )
-                .to_str()
-                .ok_or(ManifestError::Decode)?,
-            );
+        for arg in self.executable.parameters.split_whitespace() {
+            // Older installers take the location without quotes
+            let arg = arg
+                .replace("{locale}", locale)
+                .replace("\"{installLocation}\"", install_location)
+                .replace("{installLocation}", install_location);
 
             args.push(PathBuf::from(arg));
         }
@@ -65,7 +65,11 @@ use self::{
     },
 };
 use crate::{
-    content::manager::{ContentManager, ContentManagerError},
+    content::{
+        manager::{ContentManager, ContentManagerError, InstallFailure},
+        progress::DownloadProgress,
+        update::AvailableUpdate,
+    },
     lsx::{self, service::LSXServerError, types::LSXRequestType},
     rtm::client::{BasicPresence, RtmClient},
     util::native::{maxima_dir, NativeError},
@@ -77,6 +81,26 @@ pub enum MaximaEvent {
     ReceivedLSXRequest(u32, LSXRequestType),
     /// Offer ID. Use `maxima.mut_library().title_by_base_offer(id)` for details
     InstallFinished(String),
+    /// Offer ID of a progressive install that can be started while the rest of it downloads
+    InstallPlayable(String),
+    /// An install stopped before it finished, like when files couldn't be downloaded even after
+    /// retrying or the disk is full. The game stays in the download queue with the reason, and
+    /// is skipped until `ContentManager::retry` is called or it's installed again. Either resumes
+    /// the download.
+    InstallFailed {
+        offer_id: String,
+        reason: InstallFailure,
+    },
+    /// A newer build of an installed game is live. Emitted by `Maxima::check_for_updates`
+    UpdateAvailable(AvailableUpdate),
+    /// Progress of the current download. Emitted by `Maxima::update` a couple of times per second
+    DownloadProgress(DownloadProgress),
+    /// The download queue saved by a previous session was restarted, see `MaximaOptions::resume_downloads`.
+    /// Offer IDs, the rest of the queue is available through `maxima.content_manager().queue()`
+    DownloadQueueRestored {
+        current: Option<String>,
+        queued: Vec<String>,
+    },
 }
 
 pub type MaximaLSXEventCallback = extern "C" fn(*const c_char);
@@ -119,6 +143,9 @@ pub struct Maxima {
 pub struct MaximaOptions {
     load_auth_storage: bool,
     dummy_local_user: bool,
+    /// Restart the download queue left behind by the previous session
+    #[builder(default)]
+    resume_downloads: bool,
 }
 
 #[derive(Error, Debug)]
@@ -213,7 +240,8 @@ impl Maxima {
             lsx_event_callback: None,
             lsx_connections: 0,
             cloud_sync: CloudSyncClient::new(auth_storage.clone()),
-            content_manager: ContentManager::new(auth_storage.clone(), false).await?,
+            content_manager: ContentManager::new(auth_storage.clone(), options.resume_downloads)
+                .await?,
             rtm: RtmClient::new(auth_storage),
             request_cache,
             dummy_local_user,
@@ -440,6 +468,47 @@ impl Maxima {
         }
     }
 
+    /// Checks every installed game for a newer live build, emitting `MaximaEvent::UpdateAvailable`
+    /// for each one found. If auto-updating is enabled in the content manager, updates that the
+    /// game marks as automatic or mandatory are queued as well.
+    pub async fn check_for_updates(&mut self) -> Result<Vec<AvailableUpdate>, ContentManagerError> {
+        let titles = self.library.games().await?.clone();
+
+        let mut updates = Vec::new();
+        for title in titles {
+            match self
+                .content_manager
+                .check_for_update(title.base_offer())
+                .await
+            {
+                Ok(Some(update)) => updates.push(update),
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "Failed to check {} for updates: {}",
+                    title.base_offer().slug(),
+                    err
+                ),
+            }
+        }
+
+        for update in &updates {
+            info!(
+                "Update available for {}: {} -> {}",
+                update.slug, update.installed_version, update.available_version
+            );
+
+            self.call_event(MaximaEvent::UpdateAvailable(update.clone()));
+
+            if *self.content_manager.auto_update() && (update.auto_update || update.mandatory) {
+                if let Err(err) = self.content_manager.queue_update(update).await {
+                    warn!("Failed to queue the update of {}: {}", update.slug, err);
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
     /// Call this as often as possible from the loop you consume events from
     pub async fn update(&mut self) {
         self.update_playing_status().await;
@@ -447,14 +516,34 @@ impl Maxima {
         let result = self.content_manager.update().await;
         match result {
             Err(err) => warn!("Failed to update content manager: {}", err),
-            Ok(result) => {
-                if let Some(event) = result {
+            Ok(events) => {
+                for event in events {
+                    if let MaximaEvent::InstallFinished(offer_id) = &event {
+                        self.register_with_ea_desktop(offer_id).await;
+                    }
+
                     self.call_event(event);
                 }
             }
         }
     }
 
+    /// DLC isn't registered, EA Desktop finds it through the base game
+    async fn register_with_ea_desktop(&mut self, offer_id: &str) {
+        let offer = match self.library.game_by_base_offer(offer_id).await {
+            Ok(Some(offer)) => offer,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("Failed to look up {} for EA Desktop: {}", offer_id, err);
+                return;
+            }
+        };
+
+        if let Err(err) = self.content_manager.register_with_ea_desktop(offer).await {
+            warn!("Failed to register {} with EA Desktop: {}", offer_id, err);
+        }
+    }
+
     async fn update_playing_status(&mut self) {
         if self.lsx_connections > 0 || self.playing.is_none() {
             return;
@@ -468,6 +557,9 @@ impl Maxima {
 
         info!("Game stopped");
 
+        // The game may have throttled downloads through the LSX while it was running
+        self.content_manager.set_downloader_utilization(1.0);
+
         if let Some(offer) = playing.offer() {
             if *playing.cloud_saves() && offer.offer().has_cloud_save() {
                 let result = self
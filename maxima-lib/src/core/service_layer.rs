@@ -43,6 +43,8 @@ pub enum ServiceLayerError {
     ServiceGetLegacyCatalogDefsRequestBuilder(
         #[from] ServiceGetLegacyCatalogDefsRequestBuilderError,
     ),
+    #[error(transparent)]
+    ServiceAddonSearchRequestBuilder(#[from] ServiceAddonSearchRequestBuilderError),
 
     #[error("HTTP {status_code:?}: `{message}`")]
     Http {
@@ -539,6 +541,23 @@ impl ServiceAvailableBuild {
     }
 }
 
+impl From<&ServiceLegacyDownloadMetadata> for ServiceAvailableBuild {
+    /// Pre-DiP builds have no build ID, they're told apart by their release version
+    fn from(download: &ServiceLegacyDownloadMetadata) -> Self {
+        Self {
+            build_id: download.build_release_version.clone(),
+            download_type: Some(match download.download_type.as_str() {
+                "LIVE" => ServiceDownloadType::Live,
+                "STAGED" => ServiceDownloadType::Staged,
+                _ => ServiceDownloadType::None,
+            }),
+            game_version: download.game_version.clone(),
+            build_release_version: Some(download.build_release_version.clone()),
+            build_live_date: Some(download.build_live_date.clone()),
+        }
+    }
+}
+
 service_layer_type!(AvailableBuilds, {
     pub builds: Vec<ServiceAvailableBuild>,
 });
@@ -2,7 +2,7 @@ use serde::{Deserialize, Deserializer, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-use crate::util::native::maxima_dir;
+use crate::{core::manifest::DEFAULT_LOCALE, util::native::maxima_dir};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -42,6 +42,15 @@ where
     }
 }
 
+/// An add-on installed into the directory of its base game
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InstalledDlc {
+    pub offer_id: String,
+    pub build_id: String,
+    /// When the add-on finished installing, as `YYYY-MM-DDTHH:MM:SS` in UTC
+    pub installed_at: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameInstallInfo {
     pub path: PathBuf,
@@ -50,11 +59,57 @@ pub struct GameInstallInfo {
         serialize_with = "prefix_to_string"
     )]
     pub wine_prefix: Option<PathBuf>,
+    /// Offer and build that were installed by Maxima. Games installed by EA Desktop, or by older
+    /// versions of Maxima, don't have this
+    #[serde(default)]
+    pub offer_id: Option<String>,
+    #[serde(default)]
+    pub build_id: Option<String>,
+    /// Languages that were installed, the first one is what the game runs in. Empty if every
+    /// language was installed.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub dlc: Vec<InstalledDlc>,
+    /// Set while a progressive install can be played but is still downloading. Only part of
+    /// `build_id` is on disk.
+    #[serde(default)]
+    pub incomplete: bool,
 }
 
 impl GameInstallInfo {
     pub fn new(path: PathBuf, wine_prefix: Option<PathBuf>) -> Self {
-        Self { path, wine_prefix }
+        Self {
+            path,
+            wine_prefix,
+            offer_id: None,
+            build_id: None,
+            languages: Vec::new(),
+            dlc: Vec::new(),
+            incomplete: false,
+        }
+    }
+
+    pub fn installed_dlc(&self, offer_id: &str) -> Option<&InstalledDlc> {
+        self.dlc.iter().find(|x| x.offer_id == offer_id)
+    }
+
+    /// Records `dlc` as installed, replacing an earlier build of it
+    pub fn add_dlc(&mut self, dlc: InstalledDlc) {
+        self.dlc.retain(|x| x.offer_id != dlc.offer_id);
+        self.dlc.push(dlc);
+    }
+
+    pub fn build_id(&self) -> Option<&str> {
+        self.build_id.as_deref()
+    }
+
+    /// Locale the game was installed in
+    pub fn locale(&self) -> &str {
+        self.languages
+            .first()
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_LOCALE)
     }
 
     pub fn path(&self) -> PathBuf {
@@ -65,6 +120,20 @@ impl GameInstallInfo {
         self.wine_prefix.clone()
     }
 
+    /// Writes the game info to a temporary file first, so that a crash can't leave it truncated
+    pub fn try_save_to_json(&self, slug: &str) -> Result<(), GameVersionError> {
+        let json = serde_json::to_string_pretty(self)?;
+
+        let dir = maxima_dir()?.join("gameinfo");
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(format!("{}.json", slug));
+        let tmp_path = dir.join(format!("{}.json.tmp", slug));
+        fs::write(&tmp_path, json)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
     // TODO: Maybe we can just query the slug by the filename of the path? Look into this later
     pub fn save_to_json(&self, slug: &str) {
         if let Ok(json) = serde_json::to_string_pretty(self) {
@@ -4,11 +4,12 @@ use log::{debug, error, warn};
 use quick_xml::DeError;
 use regex::Regex;
 use std::{
+    collections::HashMap,
     io::{ErrorKind, Read, Write},
     net::TcpStream,
     path::PathBuf,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
 use thiserror::Error;
@@ -33,12 +34,14 @@ use super::{
             handle_query_friends_request, handle_query_image_request,
             handle_query_presence_request, handle_set_presence_request,
         },
-        progressive_install::{handle_pi_availability_request, handle_pi_installed_chunks_request},
+        progressive_install::{
+            chunk_status, handle_pi_availability_request, handle_pi_installed_chunks_request,
+        },
         voip::handle_voip_status_request,
     },
     types::{
-        create_lsx_message, LSXChallenge, LSXEvent, LSXEventType, LSXMessageType, LSXRequest,
-        LSXResponse, LSX,
+        create_lsx_message, LSXChallenge, LSXChunkState, LSXChunkStatus, LSXEvent, LSXEventType,
+        LSXMessageType, LSXRequest, LSXResponse, LSX,
     },
 };
 use crate::{
@@ -77,6 +80,9 @@ const CHALLENGE_BUILD: &str = "release";
 const CHALLENGE_KEY: &str = "cacf897a20b6d612ad0c05e011df52bb"; // Need to figure out how to generate this
 const CHALLENGE_VERSION: &str = "10,5,30,15625";
 
+/// How often the game is told about changes to its progressive install
+const PI_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
 lazy_static! {
     static ref LSX_PATTERN: Regex = Regex::new(r"<LSX>.*?</LSX>").unwrap();
 }
@@ -206,6 +212,9 @@ pub struct Connection {
     maxima: LockedMaxima,
     stream: TcpStream,
     state: LockedConnectionState,
+    /// The last status sent for each chunk of the game's progressive install
+    reported_chunks: HashMap<u32, LSXChunkStatus>,
+    last_pi_update: Instant,
 }
 
 impl Connection {
@@ -273,6 +282,8 @@ impl Connection {
             maxima: maxima_arc.clone(),
             stream,
             state,
+            reported_chunks: HashMap::new(),
+            last_pi_update: Instant::now(),
         })
     }
 
@@ -298,6 +309,63 @@ impl Connection {
         Ok(())
     }
 
+    /// Sends a `ChunkStatus` event for every chunk of the game's progressive install that
+    /// changed since the last call. Chunks a paused download didn't finish are reported as paused.
+    pub async fn send_pi_events(&mut self) -> Result<(), LSXConnectionError> {
+        if self.last_pi_update.elapsed() < PI_EVENT_INTERVAL {
+            return Ok(());
+        }
+        self.last_pi_update = Instant::now();
+
+        // Events can only be sent once the game has answered the challenge
+        if !matches!(
+            self.state.read().await.encryption,
+            EncryptionState::Enabled(_)
+        ) {
+            return Ok(());
+        }
+
+        let statuses: Vec<LSXChunkStatus> = {
+            let mut maxima = self.maxima.lock().await;
+            let offer_id = match maxima.playing().as_ref().and_then(|x| x.offer().as_ref()) {
+                Some(offer) => offer.offer_id().to_owned(),
+                None => return Ok(()),
+            };
+
+            match maxima.content_manager().chunk_progress(&offer_id) {
+                Some(progress) => progress
+                    .chunks()
+                    .iter()
+                    .map(|x| chunk_status(&offer_id, x))
+                    .collect(),
+                None => self
+                    .reported_chunks
+                    .values()
+                    .filter(|x| x.attr_State != LSXChunkState::Installed)
+                    .map(|x| LSXChunkStatus {
+                        attr_State: LSXChunkState::Paused,
+                        ..x.clone()
+                    })
+                    .collect(),
+            }
+        };
+
+        let mut state = self.state.write().await;
+        for status in statuses {
+            if self.reported_chunks.get(&status.attr_ChunkId) == Some(&status) {
+                continue;
+            }
+
+            state.queue_message(create_lsx_message(LSXMessageType::Event(LSXEvent {
+                sender: CORE_SENDER.to_string(),
+                value: LSXEventType::ChunkStatus(status.clone()),
+            })))?;
+            self.reported_chunks.insert(status.attr_ChunkId, status);
+        }
+
+        Ok(())
+    }
+
     pub async fn listen(&mut self) -> Result<(), LSXConnectionError> {
         let mut buffer = [0; 1024 * 8];
 
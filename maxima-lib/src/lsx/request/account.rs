@@ -5,7 +5,7 @@ use crate::{
     },
     lsx::{
         connection::LockedConnectionState,
-        request::LSXRequestError,
+        request::{offer::installed_dlc, LSXRequestError},
         types::{
             LSXEntitlement, LSXQueryEntitlements, LSXQueryEntitlementsResponse, LSXResponseType,
         },
@@ -41,6 +41,33 @@ pub async fn handle_query_entitlements_request(
     entitlements.append(&mut response.sdk_entitlements().entitlements().clone());
     // there's some hints of pagination here but i'm not sure how to handle that :)
 
+    // Installed add-ons aren't always part of the group the game asks for, without their
+    // entitlement the game doesn't unlock them
+    let missing: Vec<String> = installed_dlc(&maxima)
+        .into_iter()
+        .map(|x| x.offer_id)
+        .filter(|id| !entitlements.iter().any(|x| x.product_id() == id))
+        .collect();
+
+    if !missing.is_empty() {
+        let response: ServiceSdkEntitlementsResult = service_layer
+            .request(
+                SERVICE_REQUEST_SDKENTITLEMENTS,
+                ServiceSdkEntitlementsRequestBuilder::default()
+                    .page_number(1)
+                    .page_size(100)
+                    .product_ids(missing)
+                    .include_child_groups(false)
+                    .entitlement_tag("".to_string())
+                    .group_names(Vec::new())
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        entitlements.append(&mut response.sdk_entitlements().entitlements().clone());
+    }
+
     let mut lsx_entitlements = Vec::new();
     for entitlement in entitlements {
         lsx_entitlements.push(LSXEntitlement {
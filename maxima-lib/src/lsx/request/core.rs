@@ -33,9 +33,15 @@ pub async fn handle_connectivity_request(
 }
 
 pub async fn handle_set_downloader_util_request(
-    _: LockedConnectionState,
-    _: LSXSetDownloaderUtilization,
+    state: LockedConnectionState,
+    request: LSXSetDownloaderUtilization,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
-    // TODO Actually set this
+    let maxima = state.write().await.maxima_arc();
+    let mut maxima = maxima.lock().await;
+
+    maxima
+        .content_manager()
+        .set_downloader_utilization(request.attr_Utilization);
+
     Ok(None)
 }
@@ -4,6 +4,8 @@ const LANGUAGES: &str =
 //const LANGUAGES: &str = "en_US,es_ES,fr_FR,pt_BR";
 
 use crate::{
+    core::manifest::DEFAULT_LOCALE,
+    gameinfo::load_game_info_from_json,
     lsx::{
         connection::LockedConnectionState,
         request::LSXRequestError,
@@ -15,14 +17,35 @@ use crate::{
     make_lsx_handler_response,
 };
 
+/// Languages installed for the running game and the one it runs in. Games installed with
+/// every language, or not by Maxima, get the full list.
+async fn installed_languages(state: &LockedConnectionState) -> (String, String) {
+    let arc = state.write().await.maxima_arc();
+    let maxima = arc.lock().await;
+
+    let install_info = maxima
+        .playing()
+        .as_ref()
+        .and_then(|x| x.slug().clone())
+        .and_then(|slug| load_game_info_from_json(&slug).ok());
+
+    match install_info {
+        Some(info) if !info.languages.is_empty() => {
+            (info.languages.join(","), info.locale().to_owned())
+        }
+        _ => (LANGUAGES.to_string(), DEFAULT_LOCALE.to_string()),
+    }
+}
+
 pub async fn handle_game_info_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     request: LSXGetGameInfo,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let (languages, installed_language) = installed_languages(&state).await;
     let game_info = match request.attr_GameInfoId {
         LSXGameInfoId::FreeTrial => "false".to_string(),
-        LSXGameInfoId::Languages => LANGUAGES.to_string(),
-        LSXGameInfoId::InstalledLanguage => "en_US".to_string(),
+        LSXGameInfoId::Languages => languages,
+        LSXGameInfoId::InstalledLanguage => installed_language,
     };
 
     make_lsx_handler_response!(Response, GetGameInfoResponse, { attr_GameInfo: game_info })
@@ -33,15 +56,16 @@ pub async fn handle_game_info_request(
 /// Just realized we're still telling every game that it's titanfall.
 /// Should fix that at some point!
 pub async fn handle_all_game_info_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     _: LSXGetAllGameInfo,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let (languages, installed_language) = installed_languages(&state).await;
     make_lsx_handler_response!(Response, GetAllGameInfoResponse, {
         attr_FullGamePurchased: true,
         attr_FullGameReleased: true,
         attr_InstalledVersion: "0".to_string(),
         attr_MaxGroupSize: 16,
-        attr_Languages: LANGUAGES.to_string(),
+        attr_Languages: languages,
         attr_Expiration: "0000-00-00T00:00:00".to_string(),
         attr_UpToDate: true,
         attr_HasExpiration: false,
@@ -49,7 +73,7 @@ pub async fn handle_all_game_info_request(
         attr_AvailableVersion: "1.0.1.3".to_string(),
         attr_DisplayName: "Titanfall® 2 Deluxe Edition".to_string(),
         attr_FreeTrial: false,
-        attr_InstalledLanguage: "en_US".to_string(),
+        attr_InstalledLanguage: installed_language,
         attr_FullGameReleaseDate: "2016-10-28T04:00:00".to_string(),
         attr_SystemTime: "2023-06-22T04:00:00".to_string()
     })
@@ -1,7 +1,12 @@
 use crate::{
-    core::service_layer::{
-        ServiceAddonSearchRequestBuilder, ServiceAddonSearchResultRoot, SERVICE_REQUEST_ADDONSEARCH,
+    core::{
+        service_layer::{
+            ServiceAddonSearchRequestBuilder, ServiceAddonSearchResultRoot,
+            SERVICE_REQUEST_ADDONSEARCH,
+        },
+        Maxima,
     },
+    gameinfo::{load_game_info_from_json, InstalledDlc},
     lsx::{
         connection::LockedConnectionState,
         request::LSXRequestError,
@@ -10,6 +15,17 @@ use crate::{
     make_lsx_handler_response,
 };
 
+/// Add-ons installed into the running game. Games that weren't installed by Maxima have none.
+pub(crate) fn installed_dlc(maxima: &Maxima) -> Vec<InstalledDlc> {
+    maxima
+        .playing()
+        .as_ref()
+        .and_then(|x| x.slug().clone())
+        .and_then(|slug| load_game_info_from_json(&slug).ok())
+        .map(|x| x.dlc)
+        .unwrap_or_default()
+}
+
 pub async fn handle_query_offers_request(
     conn: LockedConnectionState,
     request: LSXQueryOffers,
@@ -38,12 +54,20 @@ pub async fn handle_query_offers_request(
         )
         .await?;
 
+    // Games only unlock add-ons that have been downloaded
+    let installed = installed_dlc(&maxima);
     for offer in offers.addonSearch().addonOffers() {
+        let download_date = installed
+            .iter()
+            .find(|x| &x.offer_id == offer.offer_id())
+            .map(|x| x.installed_at.clone())
+            .unwrap_or("0000-00-00T00:00:00".to_string());
+
         rtn.push(LSXOffer {
             attr_InventorySold: 0,
             attr_LocalizedPrice: offer.display_list_price().to_string(),
             attr_OriginalPrice: offer.price().to_string(),
-            attr_DownloadDate: "0000-00-00T00:00:00".to_string(),
+            attr_DownloadDate: download_date,
             attr_Currency: offer.currency().to_string(),
             attr_InventoryAvailable: 0,
             attr_PurchaseDate: "0000-00-00T00:00:00".to_string(),
@@ -1,33 +1,127 @@
+use std::sync::Arc;
+
 use crate::{
+    content::progressive::{ChunkProgress, ChunkState, ChunkStatus},
     lsx::{
         connection::LockedConnectionState,
         request::LSXRequestError,
         types::{
-            LSXAreChunksInstalled, LSXAreChunksInstalledResponse,
-            LSXIsProgressiveInstallationAvailable, LSXIsProgressiveInstallationAvailableResponse,
-            LSXResponseType,
+            LSXAreChunksInstalled, LSXAreChunksInstalledResponse, LSXChunkState, LSXChunkStatus,
+            LSXChunkType, LSXIsProgressiveInstallationAvailable,
+            LSXIsProgressiveInstallationAvailableResponse, LSXResponseType,
         },
     },
     make_lsx_handler_response,
 };
 
+enum InstalledChunks {
+    All,
+    Downloading(Arc<ChunkProgress>),
+    /// A paused or interrupted download, there's no telling which chunks made it
+    Unknown,
+}
+
+impl InstalledChunks {
+    fn are_installed(&self, ids: &[u32]) -> bool {
+        match self {
+            Self::All => true,
+            Self::Downloading(progress) => progress.are_installed(ids),
+            Self::Unknown => false,
+        }
+    }
+}
+
+/// What's known about the progressive install of the running game
+struct RunningInstall {
+    item_id: String,
+    /// The build has chunks, so the game can run before it's fully installed
+    available: bool,
+    chunks: InstalledChunks,
+}
+
+async fn running_install(state: &LockedConnectionState, item_id: String) -> RunningInstall {
+    let arc = state.write().await.maxima_arc();
+    let mut maxima = arc.lock().await;
+
+    let Some(offer) = maxima.playing().as_ref().and_then(|x| x.offer().clone()) else {
+        return RunningInstall {
+            item_id,
+            available: false,
+            chunks: InstalledChunks::All,
+        };
+    };
+
+    let item_id = offer.offer_id().to_owned();
+    if let Some(progress) = maxima.content_manager().chunk_progress(&item_id) {
+        return RunningInstall {
+            item_id,
+            available: true,
+            chunks: InstalledChunks::Downloading(progress),
+        };
+    }
+
+    if maxima.content_manager().queue().contains(&item_id) {
+        return RunningInstall {
+            item_id,
+            available: true,
+            chunks: InstalledChunks::Unknown,
+        };
+    }
+
+    drop(maxima);
+    let available = match offer.local_manifest().await {
+        Ok(Some(manifest)) => manifest.progressive_install(),
+        _ => false,
+    };
+
+    RunningInstall {
+        item_id,
+        available,
+        chunks: InstalledChunks::All,
+    }
+}
+
+pub(crate) fn chunk_status(item_id: &str, status: &ChunkStatus) -> LSXChunkStatus {
+    LSXChunkStatus {
+        attr_ItemId: item_id.to_owned(),
+        attr_ChunkId: *status.id(),
+        attr_Name: status.name().to_owned(),
+        attr_Type: if *status.required() {
+            LSXChunkType::Required
+        } else {
+            LSXChunkType::Normal
+        },
+        attr_State: match status.state() {
+            ChunkState::Queued => LSXChunkState::Queued,
+            ChunkState::Downloading => LSXChunkState::Downloading,
+            ChunkState::Installed => LSXChunkState::Installed,
+        },
+        // Rounded so the game isn't told about every file
+        attr_Progress: (status.fraction() * 100.0).round() / 100.0,
+        attr_Size: *status.bytes_total(),
+    }
+}
+
 pub async fn handle_pi_availability_request(
-    _: LockedConnectionState,
-    _: LSXIsProgressiveInstallationAvailable,
+    state: LockedConnectionState,
+    request: LSXIsProgressiveInstallationAvailable,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let install = running_install(&state, request.attr_ItemId).await;
+
     make_lsx_handler_response!(Response, IsProgressiveInstallationAvailableResponse, {
-        attr_Available: false,
-        attr_ItemId: "Origin.OFR.50.0001456".to_string(),
+        attr_Available: install.available,
+        attr_ItemId: install.item_id,
     })
 }
 
 pub async fn handle_pi_installed_chunks_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     request: LSXAreChunksInstalled,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let install = running_install(&state, request.attr_ItemId).await;
     make_lsx_handler_response!(Response, AreChunksInstalledResponse, {
-        attr_ItemId: "Origin.OFR.50.0001456".to_string(),
-        attr_Installed: true,
+        attr_Installed: install.chunks.are_installed(&request.chunk_ids),
+        attr_ItemId: install.item_id,
         chunk_ids: request.chunk_ids,
     })
 }
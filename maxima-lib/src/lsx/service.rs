@@ -29,6 +29,10 @@ pub async fn start_server(port: u16, maxima: LockedMaxima) -> Result<(), LSXServ
         while idx < connections.len() {
             let connection = &mut connections[idx];
 
+            if let Err(err) = connection.send_pi_events().await {
+                warn!("Failed to send progressive install events: {}", err);
+            }
+
             if let Err(_) = connection.process_queue().await {
                 warn!("Failed to process LSX message queue");
             }
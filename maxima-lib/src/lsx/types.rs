@@ -88,6 +88,7 @@ pub struct LSXResponse {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum LSXEventType {
     Challenge(LSXChallenge),
+    ChunkStatus(LSXChunkStatus),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, IntoStaticStr)]
@@ -167,6 +168,46 @@ lsx_message! {
     data {}
 }
 
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LSXChunkType {
+    #[default]
+    Unknown,
+    Required,
+    Recommended,
+    Normal,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LSXChunkState {
+    #[default]
+    Unknown,
+    Paused,
+    Queued,
+    Downloading,
+    Installing,
+    Installed,
+    Busy,
+    Error,
+}
+
+lsx_message! {
+    /// Sent to the game through `PROGRESSIVE_INSTALLATION_EVENT` whenever a chunk changes
+    ChunkStatus;
+    attr {
+        ItemId: String,
+        ChunkId: u32,
+        Name: String,
+        Type: LSXChunkType,
+        State: LSXChunkState,
+        /// From 0.0 to 1.0
+        Progress: f32,
+        Size: u64,
+    },
+    data {}
+}
+
 // Request Messages
 
 lsx_message! {
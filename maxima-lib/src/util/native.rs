@@ -4,6 +4,7 @@ use std::{
     num::ParseIntError,
     path::{Path, PathBuf},
 };
+use sysinfo::{DiskExt, System, SystemExt};
 use thiserror::Error;
 
 #[cfg(windows)]
@@ -267,3 +268,27 @@ pub fn platform_path<P: AsRef<Path>>(path: P) -> PathBuf {
 pub fn platform_path<P: AsRef<Path>>(path: P) -> PathBuf {
     PathBuf::from(path.as_ref())
 }
+
+/// Free space on the filesystem `path` is on. The path doesn't have to exist yet, the
+/// closest parent that does is used instead. `None` if the filesystem isn't one of the disks
+/// sysinfo lists, like network shares and overlays.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let mut existing = path;
+    while !existing.exists() {
+        existing = existing.parent()?;
+    }
+
+    // Resolves symlinks to other drives, which is common for game libraries
+    #[cfg(unix)]
+    let existing = &existing.canonicalize().ok()?;
+
+    let mut system = System::new();
+    system.refresh_disks_list();
+
+    system
+        .disks()
+        .iter()
+        .filter(|disk| existing.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
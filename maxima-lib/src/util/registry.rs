@@ -208,11 +208,17 @@ pub async fn parse_registry_path_json(
 ) -> Result<PathBuf, RegistryError> {
     let game_install_info =
         load_game_info_from_json(slug.unwrap()).map_err(|_| RegistryError::InvalidInstallKey)?;
-    let idx = key.rfind(']');
     // Path looks like [HKEY_LOCAL_MACHINE\SOFTWARE\BioWare\Mass Effect Legendary Edition\Install Dir]Game\Launcher\MassEffectLauncher.exe (note this could be something other than the exe like the manifest)
-    // Extract everything after the last ] and append it to the install path
-    let after_bracket = &key[(idx.unwrap() + 1)..];
-    let path = game_install_info.path().join(after_bracket);
+    // Extract everything after the last ] and append it to the install path. Some pre-DiP offers
+    // have just the relative path, or one starting with a backslash
+    let after_bracket = match key.rfind(']') {
+        Some(idx) => &key[(idx + 1)..],
+        None => key,
+    };
+    let after_bracket = after_bracket.replace('\\', "/");
+    let path = game_install_info
+        .path()
+        .join(after_bracket.trim_start_matches('/'));
     #[cfg(unix)]
     let path = case_insensitive_path(path);
     Ok(path)
@@ -5,7 +5,7 @@ use inquire::Select;
 use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
 use regex::Regex;
-use service::{BridgeThread, MaximaLibRequest, MaximaLibResponse};
+use service::{BridgeThread, InteractThreadQueueResponse, MaximaLibRequest, MaximaLibResponse};
 
 use std::{
     io::stdout,
@@ -40,7 +40,7 @@ use maxima::{
     rtm::client::BasicPresence,
 };
 use maxima::{
-    content::ContentService,
+    content::{progress::DownloadProgress, ContentService},
     core::{
         auth::{
             context::AuthContext,
@@ -80,6 +80,11 @@ struct App {
     popup: Option<String>,
     bridge: BridgeThread,
     username: String,
+    /// Progress of the current download, if there is one
+    download: Option<DownloadProgress>,
+    queue: Option<InteractThreadQueueResponse>,
+    /// Index into the queued games
+    selected_download: usize,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
@@ -108,6 +113,9 @@ impl App {
             popup: Some("Logging in...".to_owned()),
             bridge: BridgeThread::new(),
             username: String::new(),
+            download: None,
+            queue: None,
+            selected_download: 0,
         }
     }
 
@@ -140,7 +148,11 @@ impl App {
                     Char('l') | Right => self.next_tab(),
                     Char('h') | Left => self.previous_tab(),
                     Char('q') | Esc => self.quit(),
-                    _ => {}
+                    _ => {
+                        if let SelectedTab::Downloads = self.selected_tab {
+                            self.handle_download_key(key.code);
+                        }
+                    }
                 }
             }
         }
@@ -162,6 +174,18 @@ impl App {
             MaximaLibResponse::LoginCacheEmpty => {
                 self.popup = Some("No login cache found".to_owned());
             }
+            MaximaLibResponse::DownloadProgress(progress) => {
+                self.download = Some(progress);
+            }
+            MaximaLibResponse::DownloadFinished(_) => {
+                self.download = None;
+            }
+            MaximaLibResponse::DownloadQueueUpdate(queue) => {
+                self.selected_download = self
+                    .selected_download
+                    .min(queue.queued.len().saturating_sub(1));
+                self.queue = Some(queue);
+            }
             MaximaLibResponse::InteractionThreadDiedResponse => {
                 self.popup = Some("Interaction thread died".to_owned());
             }
@@ -171,6 +195,41 @@ impl App {
         Ok(())
     }
 
+    fn handle_download_key(&mut self, code: KeyCode) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+
+        let selected = queue.queued.get(self.selected_download);
+        let request = match code {
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.selected_download = self.selected_download.saturating_sub(1);
+                None
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                if self.selected_download + 1 < queue.queued.len() {
+                    self.selected_download += 1;
+                }
+                None
+            }
+            KeyCode::Char('p') if queue.paused => Some(MaximaLibRequest::ResumeDownloadsRequest),
+            KeyCode::Char('p') => Some(MaximaLibRequest::PauseDownloadsRequest),
+            KeyCode::Char('d') => selected
+                .map(|game| MaximaLibRequest::RemoveDownloadRequest(game.offer_id().to_owned())),
+            KeyCode::Char('u') if self.selected_download > 0 => selected.map(|game| {
+                let index = self.selected_download - 1;
+                self.selected_download = index;
+                MaximaLibRequest::MoveDownloadRequest(game.offer_id().to_owned(), index)
+            }),
+            KeyCode::Char('c') => Some(MaximaLibRequest::ClearCompletedDownloadsRequest),
+            _ => None,
+        };
+
+        if let Some(request) = request {
+            self.bridge.tx.send(request).unwrap();
+        }
+    }
+
     pub fn next_tab(&mut self) {
         self.selected_tab = self.selected_tab.next();
     }
@@ -235,8 +294,8 @@ impl Widget for &App {
         render_title(title_area, buf, &title_text);
         if !self.username.is_empty() {
             self.render_tabs(tabs_area, buf);
-            self.selected_tab.render(inner_area, buf);
-            render_footer(footer_area, buf);
+            self.selected_tab.render(inner_area, buf, self);
+            render_footer(footer_area, buf, self.selected_tab);
         }
 
         if let Some(popup) = &self.popup {
@@ -271,24 +330,27 @@ fn render_title(area: Rect, buf: &mut Buffer, text: &str) {
     text.bold().render(area, buf);
 }
 
-fn render_footer(area: Rect, buf: &mut Buffer) {
-    Line::raw("◄ ► to change tab | Press q to quit")
-        .centered()
-        .render(area, buf);
+fn render_footer(area: Rect, buf: &mut Buffer, tab: SelectedTab) {
+    let text = match tab {
+        SelectedTab::Downloads => {
+            "◄ ► to change tab | ▲ ▼ to select | p pause/resume | d remove | u move up | c clear completed | q quit"
+        }
+        _ => "◄ ► to change tab | Press q to quit",
+    };
+
+    Line::raw(text).centered().render(area, buf);
 }
 
-impl Widget for SelectedTab {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl SelectedTab {
+    fn render(self, area: Rect, buf: &mut Buffer, app: &App) {
         // in a real app these might be separate widgets
         match self {
             Self::Games => self.render_games(area, buf),
-            Self::Downloads => self.render_tab1(area, buf),
+            Self::Downloads => self.render_downloads(area, buf, app),
             Self::Settings => self.render_tab2(area, buf),
         }
     }
-}
 
-impl SelectedTab {
     /// Return tab's name as a styled `Line`
     fn title(self) -> Line<'static> {
         format!("  {self}  ")
@@ -303,10 +365,66 @@ impl SelectedTab {
             .render(area, buf);
     }
 
-    fn render_tab1(self, area: Rect, buf: &mut Buffer) {
-        Paragraph::new("Welcome to the Ratatui tabs example!")
-            .block(self.block())
-            .render(area, buf);
+    fn render_downloads(self, area: Rect, buf: &mut Buffer, app: &App) {
+        let block = self.block();
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let Some(queue) = &app.queue else {
+            Line::raw("Loading download queue...").render(inner, buf);
+            return;
+        };
+
+        let [title_area, gauge_area, list_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(2),
+            Constraint::Min(0),
+        ])
+        .areas(inner);
+
+        let title = match &queue.current {
+            Some(current) if queue.paused => format!("Paused: {}", current.slug()),
+            Some(current) => format!("Downloading: {}", current.slug()),
+            None if queue.paused => "Download queue is paused".to_owned(),
+            None => "Nothing is downloading".to_owned(),
+        };
+        Line::raw(title).bold().render(title_area, buf);
+
+        let download = app.download.as_ref().filter(|download| {
+            queue
+                .current
+                .as_ref()
+                .is_some_and(|current| current.offer_id() == &download.offer_id)
+        });
+        if let (Some(download), false) = (download, queue.paused) {
+            Gauge::default()
+                .gauge_style(self.palette().c500)
+                .ratio(download.fraction())
+                .label(download.to_string())
+                .render(gauge_area, buf);
+        }
+
+        let mut lines: Vec<Line> = queue
+            .queued
+            .iter()
+            .enumerate()
+            .map(|(i, game)| {
+                let line = Line::raw(format!("{}. {}", i + 1, game.slug()));
+                if i == app.selected_download {
+                    line.fg(self.palette().c400).bold()
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        if queue.completed > 0 {
+            lines.push(Line::raw(format!("{} installs completed", queue.completed)).italic());
+        }
+
+        Paragraph::new(lines)
+            .block(Block::new().title("Queue"))
+            .render(list_area, buf);
     }
 
     fn render_tab2(self, area: Rect, buf: &mut Buffer) {
@@ -1,19 +1,39 @@
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Result};
-use log::info;
-use maxima::core::{
-    auth::{
-        context::AuthContext, login::begin_oauth_login_flow, nucleus_token_exchange, TokenResponse,
+use log::{info, warn};
+use maxima::{
+    content::{
+        manager::{ContentManager, QueuedGame},
+        progress::DownloadProgress,
+    },
+    core::{
+        auth::{
+            context::AuthContext, login::begin_oauth_login_flow, nucleus_token_exchange,
+            TokenResponse,
+        },
+        LockedMaxima, Maxima, MaximaEvent, MaximaOptionsBuilder,
     },
-    LockedMaxima, Maxima, MaximaOptionsBuilder,
 };
 
+/// How often the library is polled for events
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
 pub struct InteractThreadLoginResponse {
     pub success: bool,
     pub name: String,
 }
 
+pub struct InteractThreadQueueResponse {
+    pub current: Option<QueuedGame>,
+    pub paused: bool,
+    pub queued: Vec<QueuedGame>,
+    pub completed: usize,
+}
+
 pub enum MaximaLibRequest {
     LoginRequest,
     GetGamesRequest,
@@ -22,6 +42,11 @@ pub enum MaximaLibRequest {
     GetGameImagesRequest(String),
     GetGameDetailsRequest(String),
     StartGameRequest(String, bool),
+    PauseDownloadsRequest,
+    ResumeDownloadsRequest,
+    RemoveDownloadRequest(String),      // offer
+    MoveDownloadRequest(String, usize), // offer, index
+    ClearCompletedDownloadsRequest,
     ShutdownRequest,
 }
 
@@ -33,6 +58,9 @@ pub enum MaximaLibResponse {
     UserAvatarResponse(),
     GameDetailsResponse(),
     GameUIImagesResponse(),
+    DownloadProgress(DownloadProgress),
+    DownloadFinished(String),
+    DownloadQueueUpdate(InteractThreadQueueResponse),
     InteractionThreadDiedResponse,
 }
 
@@ -63,11 +91,22 @@ impl BridgeThread {
         Self { rx: rx0, tx: tx0 }
     }
 
+    fn queue_update(content_manager: &ContentManager) -> MaximaLibResponse {
+        let queue = content_manager.queue();
+        MaximaLibResponse::DownloadQueueUpdate(InteractThreadQueueResponse {
+            current: queue.current().clone(),
+            paused: *queue.paused(),
+            queued: queue.queued().clone(),
+            completed: queue.completed().len(),
+        })
+    }
+
     async fn run(rx1: Receiver<MaximaLibRequest>, tx1: Sender<MaximaLibResponse>) -> Result<()> {
         let maxima_arc: LockedMaxima = Maxima::new_with_options(
             MaximaOptionsBuilder::default()
                 .dummy_local_user(false)
                 .load_auth_storage(true)
+                .resume_downloads(true)
                 .build()?,
         )
         .await?;
@@ -97,7 +136,37 @@ impl BridgeThread {
             }
         }
 
+        {
+            let mut maxima = maxima_arc.lock().await;
+            tx1.send(Self::queue_update(maxima.content_manager()))?;
+        }
+
+        let mut last_update = Instant::now();
         'outer: loop {
+            if last_update.elapsed() >= UPDATE_INTERVAL {
+                last_update = Instant::now();
+
+                let mut maxima = maxima_arc.lock().await;
+                maxima.update().await;
+
+                for event in maxima.consume_pending_events() {
+                    match event {
+                        MaximaEvent::DownloadProgress(progress) => {
+                            tx1.send(MaximaLibResponse::DownloadProgress(progress))?
+                        }
+                        MaximaEvent::InstallFinished(offer_id) => {
+                            tx1.send(MaximaLibResponse::DownloadFinished(offer_id))?;
+                            tx1.send(Self::queue_update(maxima.content_manager()))?;
+                        }
+                        MaximaEvent::InstallFailed { .. }
+                        | MaximaEvent::DownloadQueueRestored { .. } => {
+                            tx1.send(Self::queue_update(maxima.content_manager()))?
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
             let request = rx1.try_recv();
             if request.is_err() {
                 continue;
@@ -159,6 +228,41 @@ impl BridgeThread {
                 MaximaLibRequest::StartGameRequest(offer_id, hardcode) => {
                     //start_game_request(maxima_arc.clone(), offer_id.clone(), hardcode).await;
                 }
+                MaximaLibRequest::PauseDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    if let Err(err) = maxima.content_manager().pause().await {
+                        warn!("Failed to pause downloads: {}", err);
+                    }
+                    tx1.send(Self::queue_update(maxima.content_manager()))?;
+                }
+                MaximaLibRequest::ResumeDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    if let Err(err) = maxima.content_manager().resume().await {
+                        warn!("Failed to resume downloads: {}", err);
+                    }
+                    tx1.send(Self::queue_update(maxima.content_manager()))?;
+                }
+                MaximaLibRequest::RemoveDownloadRequest(offer_id) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    if let Err(err) = maxima.content_manager().remove(&offer_id).await {
+                        warn!("Failed to remove download: {}", err);
+                    }
+                    tx1.send(Self::queue_update(maxima.content_manager()))?;
+                }
+                MaximaLibRequest::MoveDownloadRequest(offer_id, index) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    if let Err(err) = maxima.content_manager().move_to(&offer_id, index).await {
+                        warn!("Failed to move download: {}", err);
+                    }
+                    tx1.send(Self::queue_update(maxima.content_manager()))?;
+                }
+                MaximaLibRequest::ClearCompletedDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    if let Err(err) = maxima.content_manager().clear_completed().await {
+                        warn!("Failed to clear completed downloads: {}", err);
+                    }
+                    tx1.send(Self::queue_update(maxima.content_manager()))?;
+                }
                 MaximaLibRequest::ShutdownRequest => break 'outer Ok(()),
             }
         }
@@ -37,6 +37,12 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                             .backend_commander
                             .send(bridge_thread::MaximaLibRequest::GetFriendsRequest)
                             .unwrap();
+                        app.backend
+                            .backend_commander
+                            .send(bridge_thread::MaximaLibRequest::CheckForUpdatesRequest(
+                                app.settings.auto_update,
+                            ))
+                            .unwrap();
                     }
                     LoginCacheEmpty => app.backend_state = BackendStallState::UserNeedsToLogIn,
                     ServiceNeedsStarting => {
@@ -68,6 +74,11 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                     CriticalError(err) => app.critical_error = Some(*err),
                     NonFatalError(err) => app.nonfatal_errors.push(*err),
                     ActiveGameChanged(slug) => app.playing_game = slug,
+                    InstallSizeResponse(path, size) => {
+                        if app.installer_state.size_requested_for.as_ref() == Some(&path) {
+                            app.installer_state.install_size = Some(size);
+                        }
+                    }
                     LocateGameResponse(res) => {
                         app.installer_state.locate_response = Some(res);
                         app.installer_state.locating = false;
@@ -75,13 +86,25 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                     DownloadProgressChanged(offer_id, progress) => {
                         if let Some(dl_ing) = app.installing_now.as_mut() {
                             if dl_ing.offer == offer_id {
-                                dl_ing.downloaded_bytes = progress.bytes;
-                                dl_ing.total_bytes = progress.bytes_total;
+                                dl_ing.progress = Some(progress);
                             }
                         }
                     }
                     DownloadFinished(_) => {}
-                    DownloadQueueUpdate(current, queue) => {
+                    UpdateAvailable(offer_id, version, mandatory) => {
+                        if let Some(game) = app.games.values_mut().find(|x| x.offer == offer_id) {
+                            game.version.latest = version;
+                            game.version.mandatory = mandatory;
+                        }
+                    }
+                    UninstallFinished(slug) => {
+                        if let Some(game) = app.games.get_mut(&slug) {
+                            game.installed = false;
+                            game.version.installed = String::new();
+                        }
+                    }
+                    DownloadQueueUpdate(current, queue, paused) => {
+                        app.downloads_paused = paused;
                         if let Some(current) = current {
                             if !app.installing_now.as_ref().is_some_and(|n| n.offer == current) {
                                 app.installing_now = Some(QueuedDownload {
@@ -100,8 +123,7 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                                         rtn
                                     },
                                     offer: current,
-                                    downloaded_bytes: 0,
-                                    total_bytes: 0,
+                                    progress: None,
                                 })
                             }
                         } else {
@@ -121,11 +143,10 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                                     }
                                     rtn
                                 },
-                                offer: offer.clone(),
-                                downloaded_bytes: 0,
-                                total_bytes: 0,
+                                offer,
+                                progress: None,
                             };
-                            app.install_queue.insert(offer, i_fucking_hate_this);
+                            app.install_queue.push(i_fucking_hate_this);
                         }
                     }
                 }
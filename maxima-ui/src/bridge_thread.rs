@@ -12,8 +12,13 @@ use crate::{
     GameDetails, GameInfo, GameSettings,
 };
 use maxima::{
-    content::manager::{
-        ContentManager, ContentManagerError, QueuedGameBuilder, QueuedGameBuilderError,
+    content::{
+        manager::{
+            ContentManager, ContentManagerError, InstallSize, QueuedGameBuilder,
+            QueuedGameBuilderError,
+        },
+        progress::DownloadProgress,
+        uninstall::UninstallOptions,
     },
     core::{
         auth::storage::{AuthError, TokenError},
@@ -70,11 +75,6 @@ pub enum InteractThreadLocateGameResponse {
     Error(InteractThreadLocateGameFailure),
 }
 
-pub struct InteractThreadDownloadProgressResponse {
-    pub bytes: usize,
-    pub bytes_total: usize,
-}
-
 pub enum MaximaLibRequest {
     StartService,
     LoginRequestOauth,
@@ -83,7 +83,14 @@ pub enum MaximaLibRequest {
     GetGameDetailsRequest(String),
     StartGameRequest(GameInfo, Option<GameSettings>),
     InstallGameRequest(String, String, PathBuf, Option<PathBuf>), // offer, slug, path, wine prefix (unix only)
+    InstallSizeRequest(String, String, PathBuf),                  // offer, slug, path
     LocateGameRequest(String, String, Option<PathBuf>), // slug, path, wine prefix (unix only)
+    CheckForUpdatesRequest(bool),                       // auto update
+    UninstallGameRequest(String),                       // slug
+    PauseDownloadsRequest,
+    ResumeDownloadsRequest,
+    RemoveDownloadRequest(String),      // offer
+    MoveDownloadRequest(String, usize), // offer, index
     ShutdownRequest,
 }
 
@@ -96,13 +103,16 @@ pub enum MaximaLibResponse {
     FriendInfoResponse(InteractThreadFriendListResponse),
     GameDetailsResponse(InteractThreadGameDetailsResponse),
     LocateGameResponse(InteractThreadLocateGameResponse),
+    InstallSizeResponse(PathBuf, InstallSize), // path
     // Alerts, rather than responses:
     CriticalError(Box<BackendError>),
     NonFatalError(Box<BackendError>),
     ActiveGameChanged(Option<String>),
-    DownloadProgressChanged(String, InteractThreadDownloadProgressResponse),
+    DownloadProgressChanged(String, DownloadProgress),
     DownloadFinished(String),
-    DownloadQueueUpdate(Option<String>, Vec<String>),
+    DownloadQueueUpdate(Option<String>, Vec<String>, bool), // current, queued, paused
+    UpdateAvailable(String, String, bool),                  // offer, available version, mandatory
+    UninstallFinished(String),                              // slug
 }
 pub struct BridgeThread {
     pub backend_listener: Receiver<MaximaLibResponse>,
@@ -159,6 +169,8 @@ pub enum BackendError {
     ChannelDisconnected,
     #[error("tried to perform an action that requires being logged in, but was logged out")]
     LoggedOut,
+    #[error("failed to install `{0}`, {1}")]
+    InstallFailed(String, String),
 }
 
 impl BridgeThread {
@@ -179,7 +191,11 @@ impl BridgeThread {
         }
 
         backend_responder
-            .send(MaximaLibResponse::DownloadQueueUpdate(current, queue))
+            .send(MaximaLibResponse::DownloadQueueUpdate(
+                current,
+                queue,
+                *content_manager.queue().paused(),
+            ))
             .unwrap();
     }
 
@@ -287,12 +303,13 @@ impl BridgeThread {
             MaximaOptionsBuilder::default()
                 .dummy_local_user(false)
                 .load_auth_storage(true)
+                .resume_downloads(true)
                 .build()?,
         )
         .await?;
 
         let logged_in = {
-            let maxima = maxima_arc.lock().await;
+            let mut maxima = maxima_arc.lock().await;
             maxima.start_lsx(maxima_arc.clone()).await?;
             info!("LSX started");
 
@@ -352,6 +369,12 @@ impl BridgeThread {
             ctx.request_repaint();
         }
 
+        {
+            // The queue saved by the last session is restarted by the first update after this
+            let mut maxima = maxima_arc.lock().await;
+            Self::update_queue(maxima.content_manager(), backend_responder.clone());
+        }
+
         let _ = EventThread::new(
             &ctx.clone(),
             maxima_arc.clone(),
@@ -388,16 +411,6 @@ impl BridgeThread {
                     };
                 }
 
-                if let Some(dl) = maxima.content_manager().current() {
-                    backend_responder.send(MaximaLibResponse::DownloadProgressChanged(
-                        dl.offer_id().to_string(),
-                        InteractThreadDownloadProgressResponse {
-                            bytes: dl.bytes_downloaded(),
-                            bytes_total: dl.bytes_total(),
-                        },
-                    ))?;
-                }
-
                 for ev in maxima.consume_pending_events() {
                     match ev {
                         maxima::core::MaximaEvent::ReceivedLSXRequest(_, _) => {}
@@ -406,6 +419,32 @@ impl BridgeThread {
                                 .send(MaximaLibResponse::DownloadFinished(offer_id))?;
                             Self::update_queue(maxima.content_manager(), backend_responder.clone());
                         }
+                        maxima::core::MaximaEvent::InstallPlayable(offer_id) => {
+                            info!("{} can be played while it finishes downloading", offer_id);
+                        }
+                        maxima::core::MaximaEvent::InstallFailed { offer_id, reason } => {
+                            backend_responder.send(MaximaLibResponse::NonFatalError(Box::new(
+                                BackendError::InstallFailed(offer_id, reason.to_string()),
+                            )))?;
+                            Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                        }
+                        maxima::core::MaximaEvent::DownloadQueueRestored { .. } => {
+                            Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                        }
+                        maxima::core::MaximaEvent::DownloadProgress(progress) => {
+                            backend_responder.send(MaximaLibResponse::DownloadProgressChanged(
+                                progress.offer_id.clone(),
+                                progress,
+                            ))?;
+                        }
+                        maxima::core::MaximaEvent::UpdateAvailable(update) => {
+                            backend_responder.send(MaximaLibResponse::UpdateAvailable(
+                                update.offer_id,
+                                update.available_version,
+                                update.mandatory,
+                            ))?;
+                            Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                        }
                     }
                 }
             }
@@ -452,7 +491,8 @@ impl BridgeThread {
                     let path = PathBuf::from(path);
                     let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await;
                     if let Ok(manifest) = manifest {
-                        let guh = manifest.run_touchup(&path, &slug).await;
+                        let guh =
+                            manifest.run_touchup(&path, &slug, game_install_info.locale()).await;
                         if let Err(err) = guh {
                             let _ = backend_responder.send(MaximaLibResponse::LocateGameResponse(
                                 InteractThreadLocateGameResponse::Error(
@@ -518,6 +558,70 @@ impl BridgeThread {
                         .build()?;
                     Ok(maxima.content_manager().add_install(game).await?)
                 }
+                MaximaLibRequest::InstallSizeRequest(offer, slug, path) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let builds =
+                        maxima.content_manager().service().available_builds(&offer).await?;
+                    let build = if let Some(build) = builds.live_build() {
+                        build
+                    } else {
+                        continue;
+                    };
+
+                    let game = QueuedGameBuilder::default()
+                        .offer_id(offer)
+                        .build_id(build.build_id().to_owned())
+                        .path(path.clone())
+                        .slug(slug)
+                        .wine_prefix(None)
+                        .build()?;
+                    let size = maxima.content_manager().install_size(&game).await?;
+                    backend_responder.send(MaximaLibResponse::InstallSizeResponse(path, size))?;
+                    Ok(())
+                }
+                MaximaLibRequest::CheckForUpdatesRequest(auto_update) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    // Games that ask for automatic or mandatory updates get them queued when checking
+                    maxima.content_manager().set_auto_update(auto_update);
+                    maxima.check_for_updates().await?;
+                    Ok(())
+                }
+                MaximaLibRequest::UninstallGameRequest(slug) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let offer = match maxima.mut_library().game_by_base_slug(&slug).await? {
+                        Some(offer) => offer.clone(),
+                        None => continue,
+                    };
+
+                    maxima.content_manager().uninstall(&offer, UninstallOptions::default()).await?;
+                    Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                    backend_responder.send(MaximaLibResponse::UninstallFinished(slug))?;
+                    Ok(())
+                }
+                MaximaLibRequest::PauseDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    maxima.content_manager().pause().await?;
+                    Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                    Ok(())
+                }
+                MaximaLibRequest::ResumeDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    maxima.content_manager().resume().await?;
+                    Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                    Ok(())
+                }
+                MaximaLibRequest::RemoveDownloadRequest(offer) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    maxima.content_manager().remove(&offer).await?;
+                    Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                    Ok(())
+                }
+                MaximaLibRequest::MoveDownloadRequest(offer, index) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    maxima.content_manager().move_to(&offer, index).await?;
+                    Self::update_queue(maxima.content_manager(), backend_responder.clone());
+                    Ok(())
+                }
                 MaximaLibRequest::StartGameRequest(info, settings) => {
                     Ok(start_game_request(maxima_arc.clone(), info, settings).await?)
                 }
@@ -9,7 +9,7 @@ use egui::{
     Response, Rounding, Stroke, Style, TextureId, Ui, Vec2, ViewportBuilder, Visuals, Widget,
 };
 use log::error;
-use maxima::{core::library::OwnedOffer, util::log::init_logger};
+use maxima::{content::manager::InstallSize, core::library::OwnedOffer, util::log::init_logger};
 use std::{collections::HashMap, default::Default, ops::RangeInclusive, path::PathBuf};
 use strum_macros::EnumIter;
 use ui_image::{UIImageCache, UIImageType};
@@ -150,6 +150,7 @@ enum PopupModal {
     GameSettings(String),
     GameInstall(String),
     GameLaunchOOD(String),
+    GameUninstall(String),
 }
 
 /// Which tab is selected in the game list info panel
@@ -243,6 +244,9 @@ pub struct InstallModalState {
     locating: bool,
     locate_response: Option<InteractThreadLocateGameResponse>,
     should_close: bool,
+    /// Install path the size was last requested for
+    size_requested_for: Option<PathBuf>,
+    install_size: Option<InstallSize>,
 }
 
 impl InstallModalState {
@@ -254,6 +258,8 @@ impl InstallModalState {
             locating: false,
             locate_response: None,
             should_close: false,
+            size_requested_for: None,
+            install_size: None,
         }
     }
 }
@@ -306,8 +312,10 @@ pub struct MaximaEguiApp {
     playing_game: Option<String>,
     /// Currently downloading game
     installing_now: Option<QueuedDownload>,
-    /// Queue of game installs, indexed by offer ID
-    install_queue: HashMap<String, QueuedDownload>,
+    /// Queue of game installs, in the order they'll be installed
+    install_queue: Vec<QueuedDownload>,
+    /// Whether the download queue is paused
+    downloads_paused: bool,
     /// State for installer modal
     installer_state: InstallModalState,
     /// User Settings for the frontend
@@ -339,6 +347,8 @@ pub struct FrontendSettings {
     default_wine_prefix_path: String,
     language: FrontendLanguage,
     ignore_ood_games: bool,
+    #[serde(default)]
+    auto_update: bool,
     game_settings: HashMap<String, GameSettings>,
     performance_settings: FrontendPerformanceSettings,
 }
@@ -350,6 +360,7 @@ impl FrontendSettings {
             default_wine_prefix_path: String::new(),
             language: FrontendLanguage::SystemDefault,
             ignore_ood_games: false,
+            auto_update: false,
             game_settings: HashMap::new(),
             performance_settings: FrontendPerformanceSettings::new(),
         }
@@ -487,7 +498,8 @@ impl MaximaEguiApp {
             backend_state: BackendStallState::Starting,
             playing_game: None,
             installing_now: None,
-            install_queue: HashMap::new(),
+            install_queue: Vec::new(),
+            downloads_paused: false,
             installer_state: InstallModalState::new(&settings),
             settings,
         }
@@ -620,6 +632,7 @@ macro_rules! set_app_modal {
                     $arg1.installer_state = InstallModalState::new(&$arg1.settings);
                 }
                 PopupModal::GameLaunchOOD(_) => {}
+                PopupModal::GameUninstall(_) => {}
             }
             $arg1.modal = $arg2;
         } else {
@@ -776,6 +789,7 @@ impl MaximaEguiApp {
             });
         });
         let mut clear = false;
+        let mut confirm_uninstall = None;
         if let Some(modal) = &self.modal {
             ui.allocate_ui_at_rect(app_rect, |contents| {
                     egui::Frame::default()
@@ -826,7 +840,9 @@ impl MaximaEguiApp {
                                     ui.horizontal(|ui| {
                                         ui.label(positional_replace!(self.locale.localization.modals.game_settings.version, "version", &game.version.installed));
                                         ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
-                                            ui.add_enabled(false, egui::Button::new(format!("  {}  ", &self.locale.localization.modals.game_settings.uninstall.to_ascii_uppercase())));
+                                            if ui.add(egui::Button::new(format!("  {}  ", &self.locale.localization.modals.game_settings.uninstall.to_ascii_uppercase()))).clicked() {
+                                                confirm_uninstall = Some(game.slug.clone());
+                                            }
                                         });
                                     });
                                 } else {
@@ -924,6 +940,14 @@ impl MaximaEguiApp {
                                     let path = PathBuf::from(self.installer_state.install_folder.clone());
                                     let valid = path.exists();
 
+                                    let target = path.join(slug);
+                                    if valid && self.installer_state.size_requested_for.as_ref() != Some(&target) {
+                                        self.installer_state.size_requested_for = Some(target.clone());
+                                        self.installer_state.install_size = None;
+                                        self.backend.backend_commander.send(bridge_thread::MaximaLibRequest::InstallSizeRequest(game.offer.clone(), slug.clone(), target)).unwrap();
+                                    }
+                                    let fits = self.installer_state.install_size.as_ref().map(|size| size.fits()).unwrap_or(true);
+
                                     #[cfg(unix)]
                                     {
                                         ui.label("Wine prefix folder");
@@ -933,7 +957,7 @@ impl MaximaEguiApp {
                                         });
                                     }
 
-                                    ui.add_enabled_ui(valid, |ui| {
+                                    ui.add_enabled_ui(valid && fits, |ui| {
                                         if ui.add_sized(button_size, egui::Button::new(&self.locale.localization.modals.game_install.fresh_action)).clicked() {
                                             #[cfg(unix)]
                                             let wine_prefix = if self.installer_state.wine_prefix.is_empty() {
@@ -946,9 +970,9 @@ impl MaximaEguiApp {
                                             let wine_prefix: Option<PathBuf> = None;
 
                                             if self.installing_now.is_none() {
-                                                self.installing_now = Some(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), downloaded_bytes: 0, total_bytes: 0 });
+                                                self.installing_now = Some(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), progress: None });
                                             } else {
-                                                self.install_queue.insert(game.offer.clone(),QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), downloaded_bytes: 0, total_bytes: 0 });
+                                                self.install_queue.push(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), progress: None });
                                             }
                                             self.backend.backend_commander.send(bridge_thread::MaximaLibRequest::InstallGameRequest(game.offer.clone(), slug.clone(), path.join(slug), wine_prefix)).unwrap();
 
@@ -979,6 +1003,17 @@ impl MaximaEguiApp {
                                         }
                                         if !valid {
                                             egui::Label::new(egui::RichText::new(&self.locale.localization.modals.game_install.fresh_path_invalid).color(Color32::RED)).ui(ui);
+                                        } else if let Some(size) = &self.installer_state.install_size {
+                                            let localization = &self.locale.localization.modals.game_install;
+                                            egui::Label::new(localization.fresh_size
+                                                .replace("{download}", &humansize::SizeFormatter::new(*size.download_size(), humansize::DECIMAL).to_string())
+                                                .replace("{install}", &humansize::SizeFormatter::new(*size.install_size(), humansize::DECIMAL).to_string())
+                                                .replace("{available}", &size.available_space().map_or_else(|| "?".to_owned(), |x| humansize::SizeFormatter::new(x, humansize::DECIMAL).to_string()))).selectable(false).ui(ui);
+                                            if !fits {
+                                                egui::Label::new(egui::RichText::new(&localization.fresh_not_enough_space).color(Color32::RED)).ui(ui);
+                                            }
+                                        } else {
+                                            egui::Label::new(&self.locale.localization.modals.game_install.fresh_size_loading).selectable(false).ui(ui);
                                         }
                                     }
                                 });
@@ -1028,6 +1063,28 @@ impl MaximaEguiApp {
                                     ui.checkbox(&mut self.settings.ignore_ood_games, &self.locale.localization.modals.game_launch_out_of_date.ok_i_get_it);
                                 });
                             }
+                            PopupModal::GameUninstall(slug) => 'outer: {
+                                let game = if let Some(game) = self.games.get(slug) { game } else { break 'outer; };
+                                ui.horizontal(|header| {
+                                    header.heading(positional_replace!(&self.locale.localization.modals.game_uninstall.header, "gamename", &game.name));
+                                    header.with_layout(Layout::right_to_left(egui::Align::Center), |close_button| {
+                                        if close_button.add_sized(vec2(80.0, 30.0), egui::Button::new(&self.locale.localization.modals.close.to_ascii_uppercase())).clicked() {
+                                            clear = true
+                                        }
+                                    });
+                                });
+
+                                ui.separator();
+
+                                ui.label(positional_replace!(&self.locale.localization.modals.game_uninstall.warning, "gamename", &game.name));
+
+                                ui.with_layout(Layout::bottom_up(egui::Align::Min), |ui| {
+                                    if ui.add_sized([ui.available_size_before_wrap().x, ui.spacing().interact_size.y], egui::Button::new(&self.locale.localization.modals.game_uninstall.confirm)).clicked() {
+                                        self.backend.backend_commander.send(bridge_thread::MaximaLibRequest::UninstallGameRequest(game.slug.clone())).unwrap();
+                                        clear = true
+                                    }
+                                });
+                            }
                         }
                         ui.allocate_space(ui.available_size_before_wrap());
                     });
@@ -1035,6 +1092,8 @@ impl MaximaEguiApp {
         }
         if clear {
             self.modal = None;
+        } else if let Some(slug) = confirm_uninstall {
+            set_app_modal!(self, Some(PopupModal::GameUninstall(slug.clone())));
         }
     }
 
@@ -36,6 +36,8 @@ pub struct LocalizedModals {
     pub game_settings: LocalizedGameSettingsModal,
     /// The modal shown when launching an out-of-date game (one that has an update available but is not installed, or is just an old build)
     pub game_launch_out_of_date: LocalizedGameLaunchOODModal,
+    /// The modal asking to confirm uninstalling a game
+    pub game_uninstall: LocalizedGameUninstallModal,
 }
 
 #[derive(Deserialize)]
@@ -57,6 +59,12 @@ pub struct LocalizedGameInstallModal {
     pub fresh_path_invalid: String,
     /// Button that initiates the download
     pub fresh_action: String,
+    /// Download and install size of the game, and the free space on the disk
+    pub fresh_size: String,
+    /// Shown while the size of the game is being worked out
+    pub fresh_size_loading: String,
+    /// Informs the user the game doesn't fit on the disk
+    pub fresh_not_enough_space: String,
 }
 
 #[derive(Deserialize)]
@@ -76,6 +84,15 @@ pub struct LocalizedGameSettingsModal {
     pub version: String,
 }
 
+#[derive(Deserialize)]
+pub struct LocalizedGameUninstallModal {
+    pub header: String,
+    /// States that the game's files will be deleted
+    pub warning: String,
+    /// Button that initiates uninstallation
+    pub confirm: String,
+}
+
 #[derive(Deserialize)]
 pub struct LocalizedGameLaunchOODModal {
     pub header: String,
@@ -176,6 +193,8 @@ pub struct LocalizedGameInstallationSettings {
     pub default_wine_prefix: String,
     /// Checkbox for ignoring the out-of-date launch warning
     pub ignore_ood_warning: String,
+    /// Checkbox for queueing updates that games ask for on their own
+    pub auto_update: String,
 }
 
 #[derive(Deserialize)]
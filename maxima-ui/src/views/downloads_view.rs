@@ -1,14 +1,15 @@
-use egui::{pos2, vec2, Align2, Color32, FontId, Mesh, Rect, Rounding, Shape, Stroke, Ui, Widget};
+use egui::{pos2, vec2, Align2, Color32, FontId, Mesh, Rect, Rounding, Shape, Stroke, Ui};
 use humansize::DECIMAL;
+use maxima::content::progress::{format_duration, DownloadPhase, DownloadProgress};
 
-use crate::{MaximaEguiApp, APP_MARGIN};
+use crate::{bridge_thread::MaximaLibRequest, MaximaEguiApp, APP_MARGIN};
 
 #[derive(Clone)]
 pub struct QueuedDownload {
     pub slug: String,
     pub offer: String,
-    pub downloaded_bytes: usize,
-    pub total_bytes: usize,
+    /// `None` until the first progress event of the download arrives
+    pub progress: Option<DownloadProgress>,
     // maybe add a thing here for updates? idk there's no real api to hook this up to yet
 }
 
@@ -20,7 +21,10 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
     let container_size = vec2(ui.available_width(), 160.0);
     ui.allocate_ui(container_size, |ui| {
         let game_dl = game;
-        let game = app.games.get_mut(&game.slug).unwrap();
+        // The queue can be restored before the library finished loading
+        let Some(game) = app.games.values_mut().find(|x| x.offer == game_dl.offer) else {
+            return;
+        };
         let (hero, logo) = {
             (
                 app.img_cache.get(crate::ui_image::UIImageType::Hero(game.slug.clone())),
@@ -114,6 +118,15 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
         let left_button_rect = right_button_rect.translate(vec2(0.0, button_size + corner_radius));
 
         if is_current {
+            let (fraction, downloaded_bytes, total_bytes) = match &game_dl.progress {
+                Some(progress) => (
+                    progress.fraction() as f32,
+                    progress.bytes_done,
+                    progress.bytes_total,
+                ),
+                None => (0.0, 0, 0),
+            };
+
             let progress_bar_rect = Rect {
                 min: img_response.rect.max + vec2(0.0, -18.0),
                 max: rect.max - vec2(corner_radius, corner_radius),
@@ -122,9 +135,7 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
             let progress_bar_progress = Rect {
                 min: progress_bar_rect.min,
                 max: pos2(
-                    progress_bar_rect.min.x
-                        + (progress_bar_rect.width()
-                            * (game_dl.downloaded_bytes as f32 / game_dl.total_bytes as f32)),
+                    progress_bar_rect.min.x + (progress_bar_rect.width() * fraction),
                     progress_bar_rect.max.y,
                 ),
             };
@@ -142,7 +153,7 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
                 Align2::LEFT_BOTTOM,
                 format!(
                     "{}",
-                    humansize::SizeFormatter::new(game_dl.downloaded_bytes, DECIMAL)
+                    humansize::SizeFormatter::new(downloaded_bytes, DECIMAL)
                 ),
                 FontId::proportional(12.0),
                 Color32::WHITE,
@@ -150,19 +161,53 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
             ui.painter().text(
                 progress_bar_rect.max - vec2(0.0, progress_bar_rect.height() + 8.0),
                 Align2::RIGHT_BOTTOM,
-                format!(
-                    "{}",
-                    humansize::SizeFormatter::new(game_dl.total_bytes, DECIMAL)
-                ),
+                format!("{}", humansize::SizeFormatter::new(total_bytes, DECIMAL)),
+                FontId::proportional(12.0),
+                Color32::WHITE,
+            );
+
+            let status = match &game_dl.progress {
+                _ if app.downloads_paused => "Paused".to_owned(),
+                Some(progress) if progress.phase == DownloadPhase::Downloading => {
+                    let mut status = format!(
+                        "{}/s, {}/{} files",
+                        humansize::SizeFormatter::new(progress.speed as u64, DECIMAL),
+                        progress.files_done,
+                        progress.files_total
+                    );
+                    if let Some(eta) = progress.eta {
+                        status += &format!(", {} left", format_duration(eta));
+                    }
+                    status
+                }
+                Some(progress) => format!("{}...", progress.phase),
+                None => "Starting...".to_owned(),
+            };
+
+            ui.painter().text(
+                pos2(progress_bar_rect.center().x, progress_bar_rect.min.y - 8.0),
+                Align2::CENTER_BOTTOM,
+                status,
                 FontId::proportional(12.0),
                 Color32::WHITE,
             );
 
             if ui.put(left_button_rect, egui::Button::new("🗙")).clicked() {
-                //TODO: Remove
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::RemoveDownloadRequest(
+                        game_dl.offer.clone(),
+                    ))
+                    .unwrap();
             }
-            if ui.put(right_button_rect, egui::Button::new("⏸")).clicked() {
-                //TODO: Pause
+
+            let (pause_icon, pause_request) = if app.downloads_paused {
+                ("▶", MaximaLibRequest::ResumeDownloadsRequest)
+            } else {
+                ("⏸", MaximaLibRequest::PauseDownloadsRequest)
+            };
+            if ui.put(right_button_rect, egui::Button::new(pause_icon)).clicked() {
+                app.backend.backend_commander.send(pause_request).unwrap();
             }
         } else {
             ui.painter().text(
@@ -174,10 +219,21 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
             );
 
             if ui.put(left_button_rect, egui::Button::new("🗙")).clicked() {
-                //TODO: Remove
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::RemoveDownloadRequest(
+                        game_dl.offer.clone(),
+                    ))
+                    .unwrap();
             }
             if ui.put(right_button_rect, egui::Button::new("⮉")).clicked() {
-                //TODO: Move to top
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::MoveDownloadRequest(
+                        game_dl.offer.clone(),
+                        0,
+                    ))
+                    .unwrap();
             }
         }
     });
@@ -188,16 +244,7 @@ pub fn downloads_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
         render_queued(app, ui, &now, true);
         ui.separator();
     }
-    for (_offer, game) in app.install_queue.clone() {
-        if !_offer.eq(&game.offer) {
-            egui::Label::new(egui::RichText::new(format!(
-                "offer mismatch! {} vs {}",
-                _offer, game.offer
-            )))
-            .selectable(false)
-            .ui(ui);
-        } else {
-            render_queued(app, ui, &game, false);
-        }
+    for game in app.install_queue.clone() {
+        render_queued(app, ui, &game, false);
     }
 }
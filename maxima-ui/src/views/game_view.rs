@@ -104,7 +104,7 @@ fn game_view_action_buttons(app: &mut MaximaEguiApp, game: &GameInfo, ui: &mut U
                             );
                         }
                     }
-                } else if app.install_queue.contains_key(&game.offer)
+                } else if app.install_queue.iter().any(|q| q.offer.eq(&game.offer))
                     || app.installing_now.as_ref().is_some_and(|q| q.offer.eq(&game.offer))
                 {
                     let install_str = format!("  {}  ", &localization.resume.to_uppercase());
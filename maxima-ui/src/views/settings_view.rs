@@ -55,6 +55,10 @@ pub fn settings_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
         &mut app.settings.ignore_ood_games,
         &app.locale.localization.settings_view.game_installation.ignore_ood_warning,
     );
+    ui.checkbox(
+        &mut app.settings.auto_update,
+        &localization.game_installation.auto_update,
+    );
 
     ui.heading("");
     ui.heading(&localization.performance.header);